@@ -0,0 +1,336 @@
+//! Contains the conversion between structure references and arrays.
+//!
+//! Generated layouts often place the same structure many times at a regular
+//! pitch. Structure references with the same structure and transformation
+//! whose origins lie on a lattice can be replaced by a single array
+//! reference. Rows of the lattice are horizontal and columns vertical, a
+//! lattice with a single row or column is detected as well.
+
+use std::collections::{BTreeSet, HashMap};
+
+use {Element, ElementParameter, ElementType, Library, Structure};
+
+/// Largest number of columns or rows of an array reference.
+const MAX_COUNT: i64 = 32767;
+
+/// A lattice of references found in a structure.
+struct Lattice {
+    /// Origin of the first instance.
+    origin: (i32,i32),
+    /// Distance between neighbouring columns.
+    col_step: i32,
+    /// Distance between neighbouring rows.
+    row_step: i32,
+    cols: i64,
+    rows: i64
+}
+
+impl Lattice {
+    /// Returns the origin of the instance in column `c` and row `r`.
+    fn point(&self, c: i64, r: i64) -> (i32,i32) {
+        ((self.origin.0 as i64 + c*self.col_step as i64) as i32,
+            (self.origin.1 as i64 + r*self.row_step as i64) as i32)
+    }
+}
+
+impl Element {
+    /// Replaces an array reference by one structure reference per instance.
+    ///
+    /// The origins of the instances are rounded to database units. Other
+    /// elements are returned unchanged.
+    pub fn expand_array(&self) -> Vec<Element> {
+        if !matches!(self.element_type, ElementType::ArrayRef) {
+            return vec![self.clone()];
+        }
+        let mut sref = self.clone();
+        sref.element_type = ElementType::StructureRef;
+        sref.parameters.retain(
+            |p| !matches!(*p, ElementParameter::ColRow(_)));
+        let (cols, rows) = self.array_size();
+        let mut res = Vec::new();
+        for r in 0..rows {
+            for c in 0..cols {
+                if let Some(t) = self.instance_transform(c, r) {
+                    let (x, y) = t.apply((0., 0.));
+                    res.push(sref.with_xy(
+                        vec![(x.round() as i32, y.round() as i32)]));
+                }
+            }
+        }
+        res
+    }
+}
+
+impl Structure {
+    /// Replaces structure references lying on a lattice by array
+    /// references.
+    ///
+    /// Only references with the same structure and the same parameters
+    /// apart from the origin are combined. Arrays are built greedily from
+    /// the lower left reference, preferring rows over single columns, and
+    /// are only created for at least `min_instances` references. An array
+    /// takes the position of its first reference in the element list.
+    /// Returns the number of replaced structure references.
+    pub fn compress_arrays(&mut self, min_instances: usize) -> usize {
+        let min_instances = min_instances.max(2) as i64;
+        // references grouped by all parameters except the origin
+        let mut groups: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, elem) in self.elements.iter().enumerate() {
+            if !matches!(elem.element_type, ElementType::StructureRef) {
+                continue;
+            }
+            match elem.xy() {
+                Some(xy) if xy.len() == 1 => {},
+                _ => continue
+            }
+            let key: Vec<String> = elem.parameters.iter()
+                .filter(|p| !matches!(**p, ElementParameter::XY(_)))
+                .map(|p| format!("{:?}", p)).collect();
+            groups.entry(key.join(" ")).or_default().push(i);
+        }
+
+        // element index of the first reference of every array and the indices
+        // of all replaced references
+        let mut arrays: HashMap<usize, Element> = HashMap::new();
+        let mut replaced: BTreeSet<usize> = BTreeSet::new();
+        for indices in groups.values() {
+            if (indices.len() as i64) < min_instances {
+                continue;
+            }
+            let mut at: HashMap<(i32,i32), Vec<usize>> = HashMap::new();
+            for &i in indices {
+                let p = self.elements[i].xy().unwrap()[0];
+                at.entry(p).or_default().push(i);
+            }
+            // remaining origins by row and by column
+            let mut by_row: BTreeSet<(i32,i32)> = BTreeSet::new();
+            let mut by_col: BTreeSet<(i32,i32)> = BTreeSet::new();
+            for &(x, y) in at.keys() {
+                by_row.insert((y, x));
+                by_col.insert((x, y));
+            }
+            while let Some(&(y, x)) = by_row.iter().next() {
+                let lattice = find_lattice((x, y), &by_row, &by_col);
+                let count = lattice.as_ref().map_or(1, |l| l.cols*l.rows);
+                let lattice = match lattice {
+                    Some(l) if count >= min_instances => l,
+                    _ => {
+                        by_row.remove(&(y, x));
+                        by_col.remove(&(x, y));
+                        continue;
+                    }
+                };
+                let mut members = Vec::new();
+                for r in 0..lattice.rows {
+                    for c in 0..lattice.cols {
+                        let p = lattice.point(c, r);
+                        by_row.remove(&(p.1, p.0));
+                        by_col.remove(&p);
+                        // one reference per origin, duplicates are kept
+                        let list = at.get_mut(&p).unwrap();
+                        members.push(list.remove(0));
+                    }
+                }
+                let first = *members.iter().min().unwrap();
+                arrays.insert(first, array(&self.elements[first], &lattice));
+                replaced.extend(members);
+            }
+        }
+
+        let count = replaced.len();
+        let elements: Vec<Element> = self.elements.drain(..).enumerate()
+            .filter_map(|(i, e)| match arrays.remove(&i) {
+                Some(a) => Some(a),
+                None if replaced.contains(&i) => None,
+                None => Some(e)
+            }).collect();
+        self.elements = elements;
+        count
+    }
+
+    /// Replaces all array references by structure references.
+    ///
+    /// Returns the number of created structure references.
+    pub fn expand_arrays(&mut self) -> usize {
+        let mut count = 0;
+        let mut elements = Vec::with_capacity(self.elements.len());
+        for elem in self.elements.drain(..) {
+            if let ElementType::ArrayRef = elem.element_type {
+                let srefs = elem.expand_array();
+                count += srefs.len();
+                elements.extend(srefs);
+            } else {
+                elements.push(elem);
+            }
+        }
+        self.elements = elements;
+        count
+    }
+}
+
+impl Library {
+    /// Replaces structure references lying on a lattice by array references
+    /// in all structures.
+    ///
+    /// See [Structure::compress_arrays](struct.Structure.html) for details.
+    /// Returns the number of replaced structure references.
+    pub fn compress_arrays(&mut self, min_instances: usize) -> usize {
+        self.structures.iter_mut().map(|s| s.compress_arrays(min_instances))
+            .sum()
+    }
+
+    /// Replaces all array references by structure references.
+    ///
+    /// Returns the number of created structure references.
+    pub fn expand_arrays(&mut self) -> usize {
+        self.structures.iter_mut().map(|s| s.expand_arrays()).sum()
+    }
+}
+
+/// Returns the largest lattice starting at `origin`.
+///
+/// The column step is the distance to the next origin on the right and the
+/// row step the distance to the next origin above. A lattice is either a
+/// rectangle of complete rows or a single column.
+fn find_lattice(origin: (i32,i32), by_row: &BTreeSet<(i32,i32)>,
+        by_col: &BTreeSet<(i32,i32)>) -> Option<Lattice> {
+    let (x, y) = origin;
+    let right = by_row.range((y, x.saturating_add(1))..=(y, i32::MAX)).next()
+        .filter(|_| x < i32::MAX).map(|&(_, x1)| x1 - x);
+    let up = by_col.range((x, y.saturating_add(1))..=(x, i32::MAX)).next()
+        .filter(|_| y < i32::MAX).map(|&(_, y1)| y1 - y);
+    let has = |l: &Lattice, c: i64, r: i64| {
+        let p = l.point(c, r);
+        by_row.contains(&(p.1, p.0))
+    };
+    // the displacement vectors span all columns and rows, so they have to
+    // fit into the range of the coordinates
+    let fits = |v: i32, n: i64, step: i32|
+        v as i64 + n*step as i64 <= i32::MAX as i64;
+    let mut best: Option<Lattice> = None;
+    if let Some(dx) = right {
+        let mut l = Lattice{origin, col_step: dx, row_step: up.unwrap_or(dx),
+            cols: 1, rows: 1};
+        while l.cols < MAX_COUNT && fits(x, l.cols + 1, dx) &&
+                has(&l, l.cols, 0) {
+            l.cols += 1;
+        }
+        if up.is_some() {
+            while l.rows < MAX_COUNT && fits(y, l.rows + 1, l.row_step) &&
+                    (0..l.cols).all(|c| has(&l, c, l.rows)) {
+                l.rows += 1;
+            }
+        }
+        best = Some(l);
+    }
+    if let Some(dy) = up {
+        let mut l = Lattice{origin, col_step: right.unwrap_or(dy),
+            row_step: dy, cols: 1, rows: 1};
+        while l.rows < MAX_COUNT && fits(y, l.rows + 1, dy) &&
+                has(&l, 0, l.rows) {
+            l.rows += 1;
+        }
+        match best {
+            Some(ref b) if l.rows <= b.cols*b.rows => {},
+            _ => best = Some(l)
+        }
+    }
+    best
+}
+
+/// Creates an array reference with the parameters of `sref` placed on the
+/// lattice.
+fn array(sref: &Element, lattice: &Lattice) -> Element {
+    let mut res = sref.clone();
+    res.element_type = ElementType::ArrayRef;
+    // the step of a single row or column does not matter and may exceed the
+    // range of the coordinates
+    let clamp = |v: i64| v.max(i32::MIN as i64).min(i32::MAX as i64) as i32;
+    let (x, y) = (lattice.origin.0 as i64, lattice.origin.1 as i64);
+    let xy = vec![lattice.origin,
+        (clamp(x + lattice.cols*lattice.col_step as i64), y as i32),
+        (x as i32, clamp(y + lattice.rows*lattice.row_step as i64))];
+    let pos = res.parameters.iter()
+        .position(|p| matches!(*p, ElementParameter::XY(_)))
+        .unwrap_or(res.parameters.len());
+    res.parameters.insert(pos, ElementParameter::ColRow(
+        vec![lattice.cols as i16, lattice.rows as i16]));
+    res.with_xy(xy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use builder::{Strans, StructureBuilder};
+    use testing::sref;
+
+    /// Returns the sorted descriptions of the references of the structure.
+    fn references(stru: &Structure) -> Vec<String> {
+        let mut res: Vec<String> = stru.elements.iter()
+            .flat_map(|e| e.expand_array())
+            .map(|e| format!("{:?}", e.parameters)).collect();
+        res.sort();
+        res
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut stru = Structure::new();
+        for r in 0..3 {
+            for c in 0..5 {
+                stru.elements.push(sref("A", (10 + c*20, -5 + r*30)));
+            }
+        }
+        stru.elements.swap(3, 11);
+        let original = references(&stru);
+        assert_eq!(stru.compress_arrays(2), 15);
+        assert_eq!(stru.elements.len(), 1);
+        let aref = &stru.elements[0];
+        assert!(matches!(aref.element_type, ElementType::ArrayRef));
+        assert_eq!(aref.array_size(), (5, 3));
+        assert_eq!(aref.xy().unwrap(), &[(10, -5), (110, -5), (10, 85)]);
+        assert_eq!(references(&stru), original);
+        assert_eq!(stru.expand_arrays(), 15);
+        assert_eq!(references(&stru), original);
+    }
+
+    #[test]
+    fn groups() {
+        // a column, a rotated row, an isolated reference and a duplicate
+        let mut builder = StructureBuilder::new("TOP");
+        for r in 0..4 {
+            builder = builder.sref("A", (0, r*7), Strans::identity());
+        }
+        for c in 0..3 {
+            builder = builder.sref("A", (100 + c*5, 0), Strans::rotation(90.));
+        }
+        let mut stru = builder.sref("B", (0, 0), Strans::identity())
+            .sref("A", (0, 7), Strans::identity()).build();
+        let original = references(&stru);
+        let mut compressed = stru.clone();
+        assert_eq!(compressed.compress_arrays(4), 4);
+        assert_eq!(compressed.elements.len(), 6);
+        assert_eq!(compressed.elements[0].array_size(), (1, 4));
+        assert_eq!(compressed.elements[0].xy().unwrap(),
+            &[(0, 0), (7, 0), (0, 28)]);
+        assert_eq!(references(&compressed), original);
+
+        assert_eq!(stru.compress_arrays(2), 7);
+        assert_eq!(stru.elements.len(), 4);
+        let rotated = &stru.elements[1];
+        assert_eq!(rotated.array_size(), (3, 1));
+        assert!(rotated.parameters.iter()
+            .any(|p| matches!(*p, ElementParameter::Angle(_))));
+        assert_eq!(references(&stru), original);
+    }
+
+    #[test]
+    fn library() {
+        let refs = || (0..3).map(|i| sref("C", (i*10, 0))).collect();
+        let mut lib = ::testing::library(vec![("A", refs()), ("B", refs())]);
+        assert_eq!(lib.compress_arrays(3), 6);
+        assert_eq!(lib.compress_arrays(3), 0);
+        assert_eq!(lib.expand_arrays(), 6);
+        assert!(lib.structures.iter().all(|s| s.elements.len() == 3));
+    }
+}
@@ -0,0 +1,415 @@
+//! Command line interface for inspecting and converting GDS files.
+
+extern crate gds;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process;
+
+use gds::{ElementType, Error, Library};
+use gds::geometry::BoundingBox;
+use gds::json::{number, quote};
+use gds::layers::LayerMap;
+use gds::normalize::NormalizeOptions;
+
+const USAGE: &str = "Usage: gds <command> [options]
+
+Commands:
+    info [--json] <file>
+        Print a summary of a GDS file.
+    convert [options] <input> <output>
+        Convert between file formats. The formats are selected by the file
+        extensions (gds, oas, txt, json, svg) unless given explicitly. An
+        output of - writes to the standard output.
+
+        --from <format>     format of the input file
+        --to <format>       format of the output file
+        --top <cell>        only keep the cell and its dependencies
+        --flatten           flatten the top cells
+        --layers <list>     only keep the given layers, e.g. 1,2/0,5/1
+        --layer-map <file>  renumber or remove layers using a map file
+        --units <metres>    convert to a new database unit
+        --normalize         normalise the points of boundaries and boxes
+        --compress-arrays   replace regularly placed references by arrays
+        --expand-arrays     replace arrays by single references
+        --deduplicate       replace identical structures by a single one
+        --touch             set the modification date to the current time
+    diff [--xor <cell>] <old> <new>
+        Compare two GDS files structure by structure. With --xor the
+        flattened geometry of the cell is compared in addition. Exits with
+        1 if the files differ and 2 on errors.";
+
+/// Supported file formats.
+#[derive(Clone,Copy,PartialEq)]
+enum Format {
+    Gds,
+    Oasis,
+    Text,
+    Json,
+    Svg
+}
+
+impl Format {
+    fn from_name(name: &str) -> Option<Format> {
+        match name.to_lowercase().as_str() {
+            "gds" | "gds2" | "gdsii" => Some(Format::Gds),
+            "oas" | "oasis" => Some(Format::Oasis),
+            "txt" | "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            "svg" => Some(Format::Svg),
+            _ => None
+        }
+    }
+
+    fn from_path(path: &str) -> Option<Format> {
+        Path::new(path).extension().and_then(|x| x.to_str())
+            .and_then(Format::from_name)
+    }
+}
+
+/// Summary of a library as printed by the info command.
+struct Info {
+    structures: usize,
+    top_cells: Vec<String>,
+    shapes: BTreeMap<(i16,i16), usize>,
+    vertices: usize,
+    bbox: Option<BoundingBox>
+}
+
+impl Info {
+    fn new(lib: &Library) -> Info {
+        let top_cells: Vec<String> = lib.top_structures().iter()
+            .map(|s| s.name.clone()).collect();
+        let mut shapes = BTreeMap::new();
+        let mut vertices = 0;
+        for stru in &lib.structures {
+            for elem in &stru.elements {
+                match elem.element_type {
+                    ElementType::Boundary | ElementType::Path |
+                        ElementType::Box | ElementType::Text |
+                        ElementType::Node => {},
+                    _ => continue
+                }
+                let key = (elem.layer().unwrap_or(0),
+                    elem.datatype().unwrap_or(0));
+                *shapes.entry(key).or_insert(0) += 1;
+                vertices += elem.xy().map_or(0, |x| x.len());
+            }
+        }
+        let mut bbox: Option<BoundingBox> = None;
+        for name in &top_cells {
+            if let Some(b) = lib.bounding_box(name) {
+                bbox = Some(bbox.map_or(b, |x| x.union(&b)));
+            }
+        }
+        Info{structures: lib.structures.len(), top_cells,
+            shapes, vertices, bbox}
+    }
+
+    fn print_text(&self, lib: &Library) {
+        println!("Library:      {}", lib.name);
+        println!("Version:      {}", lib.version);
+        println!("Units:        {} user, {} m", lib.units_user, lib.units_m);
+        println!("Modified:     {}", lib.date_mod);
+        println!("Accessed:     {}", lib.date_acc);
+        println!("Structures:   {}", self.structures);
+        println!("Top cells:    {}", self.top_cells.join(", "));
+        println!("Vertices:     {}", self.vertices);
+        match self.bbox {
+            Some(b) => println!("Bounding box: ({}, {}) - ({}, {})",
+                b.x_min, b.y_min, b.x_max, b.y_max),
+            None => println!("Bounding box: -")
+        }
+        println!("Shapes:");
+        for (&(layer, datatype), count) in &self.shapes {
+            println!("    {:>5}/{:<5} {}", layer, datatype, count);
+        }
+    }
+
+    fn print_json(&self, lib: &Library) {
+        let top_cells: Vec<String> = self.top_cells.iter()
+            .map(|x| quote(x)).collect();
+        let shapes: Vec<String> = self.shapes.iter()
+            .map(|(&(l, d), c)| format!(
+                "{{\"layer\": {}, \"datatype\": {}, \"count\": {}}}", l, d, c))
+            .collect();
+        let bbox = match self.bbox {
+            Some(b) => format!("[{}, {}, {}, {}]",
+                b.x_min, b.y_min, b.x_max, b.y_max),
+            None => String::from("null")
+        };
+        println!("{{");
+        println!("  \"name\": {},", quote(&lib.name));
+        println!("  \"version\": {},", lib.version);
+        println!("  \"units_user\": {},", number(lib.units_user));
+        println!("  \"units_m\": {},", number(lib.units_m));
+        println!("  \"date_mod\": {},", quote(&lib.date_mod.to_string()));
+        println!("  \"date_acc\": {},", quote(&lib.date_acc.to_string()));
+        println!("  \"structures\": {},", self.structures);
+        println!("  \"top_cells\": [{}],", top_cells.join(", "));
+        println!("  \"shapes\": [{}],", shapes.join(", "));
+        println!("  \"vertices\": {},", self.vertices);
+        println!("  \"bounding_box\": {}", bbox);
+        println!("}}");
+    }
+}
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("error: {}\n\n{}", msg, USAGE);
+    process::exit(2);
+}
+
+fn cmd_info(args: &[String]) -> Result<(), Error> {
+    let mut json = false;
+    let mut file: Option<&str> = None;
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            x if x.starts_with('-') =>
+                usage_error(&format!("unknown option {}", x)),
+            x if file.is_none() => file = Some(x),
+            _ => usage_error("too many arguments")
+        }
+    }
+    let file = match file {
+        Some(x) => x,
+        None => usage_error("missing input file")
+    };
+    let lib = Library::read(file)?;
+    let info = Info::new(&lib);
+    if json {
+        info.print_json(&lib);
+    } else {
+        info.print_text(&lib);
+    }
+    Ok(())
+}
+
+/// Options of the convert command.
+struct ConvertOptions {
+    from: Option<Format>,
+    to: Option<Format>,
+    top: Option<String>,
+    flatten: bool,
+    layers: Option<Vec<(i16, Option<i16>)>>,
+    layer_map: Option<String>,
+    units: Option<f64>,
+    touch: bool,
+    normalize: bool,
+    compress_arrays: bool,
+    expand_arrays: bool,
+    deduplicate: bool
+}
+
+fn parse_layers(s: &str) -> Option<Vec<(i16, Option<i16>)>> {
+    let mut res = Vec::new();
+    for item in s.split(',') {
+        let mut parts = item.trim().splitn(2, '/');
+        let layer = parts.next()?.parse().ok()?;
+        let datatype = match parts.next() {
+            Some(x) => Some(x.parse().ok()?),
+            None => None
+        };
+        res.push((layer, datatype));
+    }
+    Some(res)
+}
+
+fn cmd_convert(args: &[String]) -> Result<(), Error> {
+    let mut opts = ConvertOptions{from: None, to: None, top: None,
+        flatten: false, layers: None, layer_map: None, units: None,
+        touch: false, normalize: false, compress_arrays: false,
+        expand_arrays: false, deduplicate: false};
+    let mut files: Vec<&str> = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| match iter.next() {
+            Some(x) => x.clone(),
+            None => usage_error(&format!("missing value for {}", name))
+        };
+        match arg.as_str() {
+            "--from" | "--to" => {
+                let v = value(arg);
+                let f = Format::from_name(&v).unwrap_or_else(
+                    || usage_error(&format!("unknown format {}", v)));
+                if arg == "--from" {
+                    opts.from = Some(f);
+                } else {
+                    opts.to = Some(f);
+                }
+            },
+            "--top" => opts.top = Some(value(arg)),
+            "--flatten" => opts.flatten = true,
+            "--touch" => opts.touch = true,
+            "--normalize" => opts.normalize = true,
+            "--compress-arrays" => opts.compress_arrays = true,
+            "--expand-arrays" => opts.expand_arrays = true,
+            "--deduplicate" => opts.deduplicate = true,
+            "--layers" => {
+                let v = value(arg);
+                opts.layers = Some(parse_layers(&v).unwrap_or_else(
+                    || usage_error(&format!("invalid layer list {}", v))));
+            },
+            "--layer-map" => opts.layer_map = Some(value(arg)),
+            "--units" => {
+                let v = value(arg);
+                opts.units = Some(match v.parse::<f64>() {
+                    Ok(x) if x > 0. => x,
+                    _ => usage_error(&format!("invalid unit {}", v))
+                });
+            },
+            "-" => files.push(arg),
+            x if x.starts_with('-') =>
+                usage_error(&format!("unknown option {}", x)),
+            x => files.push(x)
+        }
+    }
+    if files.len() != 2 {
+        usage_error("expected an input and an output file");
+    }
+    let (input, output) = (files[0], files[1]);
+    let from = opts.from.or_else(|| Format::from_path(input)).unwrap_or_else(
+        || usage_error(&format!("unknown format of {}", input)));
+    let to = opts.to.or_else(|| Format::from_path(output)).unwrap_or_else(
+        || usage_error(&format!("unknown format of {}", output)));
+
+    if to == Format::Oasis {
+        return Err(Error::UnsupportedFormat(
+            String::from("writing OASIS files")));
+    }
+
+    let mut lib = match from {
+        Format::Gds => Library::read(input)?,
+        Format::Text => Library::read_text(
+            &mut BufReader::new(File::open(input)?))?,
+        Format::Oasis => return Err(Error::UnsupportedFormat(
+            String::from("reading OASIS files"))),
+        Format::Json => return Err(Error::UnsupportedFormat(
+            String::from("reading JSON files"))),
+        Format::Svg => return Err(Error::UnsupportedFormat(
+            String::from("reading SVG files")))
+    };
+
+    if let Some(ref top) = opts.top {
+        lib = lib.extract(top)?;
+    }
+    if opts.flatten {
+        let names: Vec<String> = lib.top_structures().iter()
+            .map(|s| s.name.clone()).collect();
+        lib.structures = names.iter().filter_map(|n| lib.flatten(n)).collect();
+    }
+    if let Some(ref layers) = opts.layers {
+        lib.map_layers(&LayerMap::keep_only(layers));
+    }
+    if let Some(ref path) = opts.layer_map {
+        let map = LayerMap::read(&mut BufReader::new(File::open(path)?))?;
+        lib.map_layers(&map);
+    }
+    if let Some(units) = opts.units {
+        lib.rescale(units)?;
+    }
+    if opts.normalize {
+        lib.normalize(&NormalizeOptions::default());
+    }
+    if opts.deduplicate {
+        lib.deduplicate();
+    }
+    if opts.expand_arrays {
+        lib.expand_arrays();
+    }
+    if opts.compress_arrays {
+        lib.compress_arrays(2);
+    }
+    if opts.touch {
+        lib.touch();
+    }
+
+    let stdout = io::stdout();
+    let mut w: Box<dyn Write> = if output == "-" {
+        Box::new(stdout.lock())
+    } else {
+        Box::new(BufWriter::new(File::create(output)?))
+    };
+    match to {
+        Format::Gds => lib.to_writer(&mut w)?,
+        Format::Text => lib.write_text(&mut w)?,
+        Format::Json => lib.write_json(&mut w)?,
+        Format::Svg => {
+            let top = match opts.top {
+                Some(x) => x,
+                None => {
+                    let tops = lib.top_structures();
+                    if tops.len() != 1 {
+                        return Err(Error::UnsupportedFormat(String::from(
+                            "SVG output of several top cells, use --top")));
+                    }
+                    tops[0].name.clone()
+                }
+            };
+            lib.write_svg(&top, &mut w)?;
+        },
+        Format::Oasis => unreachable!()
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Runs the diff command and returns true if the files are equal.
+fn cmd_diff(args: &[String]) -> Result<bool, Error> {
+    let mut xor: Option<&str> = None;
+    let mut files: Vec<&str> = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--xor" => match iter.next() {
+                Some(x) => xor = Some(x),
+                None => usage_error("missing value for --xor")
+            },
+            x if x.starts_with('-') =>
+                usage_error(&format!("unknown option {}", x)),
+            x => files.push(x)
+        }
+    }
+    if files.len() != 2 {
+        usage_error("expected two files");
+    }
+    let old = Library::read(files[0])?;
+    let new = Library::read(files[1])?;
+    let diff = old.diff(&new);
+    print!("{}", diff);
+    let mut equal = diff.is_empty();
+    if let Some(cell) = xor {
+        for l in old.xor(&new, cell)? {
+            println!("xor {} layer {}/{}: area {} in {} polygons", cell,
+                l.layer, l.datatype, l.area, l.polygons.len());
+            equal = false;
+        }
+    }
+    Ok(equal)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (res, error_code) = match args.first().map(|x| x.as_str()) {
+        Some("info") => (cmd_info(&args[1..]).map(|_| 0), 1),
+        Some("convert") => (cmd_convert(&args[1..]).map(|_| 0), 1),
+        Some("diff") => (cmd_diff(&args[1..])
+            .map(|equal| if equal { 0 } else { 1 }), 2),
+        Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            (Ok(0), 1)
+        },
+        Some(x) => usage_error(&format!("unknown command {}", x)),
+        None => usage_error("missing command")
+    };
+    match res {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(error_code);
+        }
+    }
+}
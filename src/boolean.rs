@@ -0,0 +1,358 @@
+//! Contains boolean operations on polygons.
+//!
+//! The polygons are decomposed into trapezoids with horizontal top and bottom
+//! edges using a scanline algorithm. Every polygon is treated as oriented
+//! counterclockwise and a point is inside a set of polygons if it is inside
+//! any of them.
+//!
+//! Intersections are found while sweeping by testing the edges which are
+//! neighbours at a scanline instead of all pairs of edges.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Boolean operations on two sets of polygons.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Operation {
+    /// Area covered by any of the polygons.
+    Union,
+    /// Area covered by both sets.
+    Intersection,
+    /// Area covered by the first but not by the second set.
+    Difference,
+    /// Area covered by exactly one of the sets.
+    Xor
+}
+
+/// A trapezoid with horizontal top and bottom edges.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Trapezoid {
+    /// y-coordinate of the bottom edge.
+    pub y_bottom: f64,
+    /// y-coordinate of the top edge.
+    pub y_top: f64,
+    /// Left end of the bottom edge.
+    pub x_bottom_left: f64,
+    /// Right end of the bottom edge.
+    pub x_bottom_right: f64,
+    /// Left end of the top edge.
+    pub x_top_left: f64,
+    /// Right end of the top edge.
+    pub x_top_right: f64
+}
+
+impl Trapezoid {
+    /// Returns the area of the trapezoid.
+    pub fn area(&self) -> f64 {
+        (self.x_bottom_right - self.x_bottom_left + self.x_top_right -
+            self.x_top_left)*(self.y_top - self.y_bottom)/2.
+    }
+
+    /// Returns true if the trapezoid is a rectangle.
+    pub fn is_rectangle(&self) -> bool {
+        self.x_bottom_left == self.x_top_left &&
+            self.x_bottom_right == self.x_top_right
+    }
+
+    /// Returns the closed polygon of the trapezoid in counterclockwise order.
+    ///
+    /// The coordinates are rounded to the nearest integer and repeated points
+    /// are removed.
+    pub fn to_polygon(&self) -> Vec<(i32,i32)> {
+        let r = |x: f64| x.round() as i32;
+        let pts = [(r(self.x_bottom_left), r(self.y_bottom)),
+            (r(self.x_bottom_right), r(self.y_bottom)),
+            (r(self.x_top_right), r(self.y_top)),
+            (r(self.x_top_left), r(self.y_top))];
+        let mut res: Vec<(i32,i32)> = Vec::new();
+        for &p in &pts {
+            if res.last() != Some(&p) {
+                res.push(p);
+            }
+        }
+        if res.len() > 1 && res[0] == res[res.len() - 1] {
+            res.pop();
+        }
+        if let Some(&p) = res.first() {
+            res.push(p);
+        }
+        res
+    }
+}
+
+/// An edge of a polygon oriented upwards.
+#[derive(Debug,Clone,Copy)]
+struct Edge {
+    x0: f64,
+    y0: f64,
+    x1: f64,
+    y1: f64,
+    /// +1 for edges pointing upwards in the oriented polygon, -1 otherwise.
+    wind: i32,
+    /// 0 for the first set, 1 for the second set.
+    set: usize
+}
+
+impl Edge {
+    fn slope(&self) -> f64 {
+        (self.x1 - self.x0)/(self.y1 - self.y0)
+    }
+
+    fn x_at(&self, y: f64) -> f64 {
+        if y <= self.y0 {
+            self.x0
+        } else if y >= self.y1 {
+            self.x1
+        } else {
+            self.x0 + (y - self.y0)*(self.x1 - self.x0)/(self.y1 - self.y0)
+        }
+    }
+}
+
+/// Computes a boolean operation of two sets of polygons.
+///
+/// The polygons are given by their points, the closing point may be omitted.
+/// The resulting area is returned as non-overlapping trapezoids. Vertically
+/// adjacent trapezoids sharing the same edges are merged.
+pub fn boolean(a: &[Vec<(i32,i32)>], b: &[Vec<(i32,i32)>], op: Operation)
+        -> Vec<Trapezoid> {
+    let mut edges: Vec<Edge> = Vec::new();
+    for (set, polys) in [a, b].iter().enumerate() {
+        for poly in polys.iter() {
+            add_edges(&mut edges, poly, set);
+        }
+    }
+    edges.sort_by(|e, f| cmp(e.y0, f.y0));
+    // scanline positions at every vertex, intersections are added while
+    // sweeping
+    let mut ys: BinaryHeap<Scanline> = edges.iter()
+        .flat_map(|e| vec![Scanline(e.y0), Scanline(e.y1)]).collect();
+
+    let inside = |w: [i32;2]| {
+        let (a, b) = (w[0] != 0, w[1] != 0);
+        match op {
+            Operation::Union => a || b,
+            Operation::Intersection => a && b,
+            Operation::Difference => a && !b,
+            Operation::Xor => a != b
+        }
+    };
+
+    let mut res: Vec<Trapezoid> = Vec::new();
+    // trapezoids of the previous slab keyed by the indices of their edges
+    let mut open: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut next = 0;
+    let mut active: Vec<usize> = Vec::new();
+    while let Some(Scanline(ya)) = ys.pop() {
+        while let Some(&Scanline(y)) = ys.peek() {
+            if y > ya {
+                break;
+            }
+            ys.pop();
+        }
+        while next < edges.len() && edges[next].y0 <= ya {
+            active.push(next);
+            next += 1;
+        }
+        active.retain(|&i| edges[i].y1 > ya);
+        active.sort_by(|&i, &j| cmp(edges[i].x_at(ya), edges[j].x_at(ya))
+            .then(cmp(edges[i].slope(), edges[j].slope())));
+        // the next crossing is between edges which are neighbours now
+        for pair in active.windows(2) {
+            if let Some(y) = intersection(&edges[pair[0]], &edges[pair[1]]) {
+                if y > ya {
+                    ys.push(Scanline(y));
+                }
+            }
+        }
+        let yb = match ys.peek() {
+            Some(&Scanline(y)) => y,
+            None => break
+        };
+
+        let mut current: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut wind = [0, 0];
+        let mut left: Option<(f64, f64, usize)> = None;
+        for &i in &active {
+            let (xa, xb) = (edges[i].x_at(ya), edges[i].x_at(yb));
+            let was_inside = inside(wind);
+            wind[edges[i].set] += edges[i].wind;
+            let is_inside = inside(wind);
+            if !was_inside && is_inside {
+                left = Some((xa, xb, i));
+            } else if was_inside && !is_inside {
+                let l = match left.take() {
+                    Some(x) => x,
+                    None => continue
+                };
+                if xa - l.0 <= 0. && xb - l.1 <= 0. {
+                    continue;
+                }
+                let prev = open.get(&(l.2, i)).cloned()
+                    .filter(|&k| res[k].y_top == ya);
+                match prev {
+                    Some(k) => {
+                        res[k].y_top = yb;
+                        res[k].x_top_left = l.1;
+                        res[k].x_top_right = xb;
+                        current.insert((l.2, i), k);
+                    },
+                    None => {
+                        res.push(Trapezoid{y_bottom: ya, y_top: yb,
+                            x_bottom_left: l.0, x_bottom_right: xa,
+                            x_top_left: l.1, x_top_right: xb});
+                        current.insert((l.2, i), res.len() - 1);
+                    }
+                }
+            }
+        }
+        open = current;
+    }
+    res
+}
+
+/// Returns the trapezoids covering the union of all polygons.
+pub fn union(polys: &[Vec<(i32,i32)>]) -> Vec<Trapezoid> {
+    boolean(polys, &[], Operation::Union)
+}
+
+/// Returns the total area of the trapezoids.
+pub fn area(traps: &[Trapezoid]) -> f64 {
+    traps.iter().map(|t| t.area()).sum()
+}
+
+fn cmp(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+}
+
+/// Position of a scanline ordered to pop the lowest position first.
+#[derive(Debug,Clone,Copy,PartialEq)]
+struct Scanline(f64);
+
+impl Eq for Scanline {}
+
+impl PartialOrd for Scanline {
+    fn partial_cmp(&self, other: &Scanline) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scanline {
+    fn cmp(&self, other: &Scanline) -> Ordering {
+        cmp(other.0, self.0)
+    }
+}
+
+/// Adds the edges of a polygon oriented counterclockwise.
+fn add_edges(edges: &mut Vec<Edge>, poly: &[(i32,i32)], set: usize) {
+    let n = poly.len();
+    let mut area2: i64 = 0;
+    for i in 0..n {
+        let (p, q) = (poly[i], poly[(i + 1) % n]);
+        area2 += p.0 as i64*q.1 as i64 - q.0 as i64*p.1 as i64;
+    }
+    let orientation = if area2 < 0 { -1 } else { 1 };
+    for i in 0..n {
+        let (p, q) = (poly[i], poly[(i + 1) % n]);
+        if p.1 == q.1 {
+            continue;
+        }
+        let (p, q, wind) = if p.1 < q.1 {
+            (p, q, orientation)
+        } else {
+            (q, p, -orientation)
+        };
+        edges.push(Edge{x0: p.0 as f64, y0: p.1 as f64, x1: q.0 as f64,
+            y1: q.1 as f64, wind, set});
+    }
+}
+
+/// Returns the y-coordinate of a proper intersection of two edges.
+fn intersection(e: &Edge, f: &Edge) -> Option<f64> {
+    let (ya, yb) = (e.y0.max(f.y0), e.y1.min(f.y1));
+    if ya >= yb {
+        return None;
+    }
+    let da = e.x_at(ya) - f.x_at(ya);
+    let db = e.x_at(yb) - f.x_at(yb);
+    if da*db >= 0. {
+        return None;
+    }
+    Some(ya + (yb - ya)*da/(da - db))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: i32, y: i32, size: i32) -> Vec<(i32,i32)> {
+        vec![(x, y), (x + size, y), (x + size, y + size), (x, y + size)]
+    }
+
+    #[test]
+    fn overlapping_squares() {
+        let a = vec![square(0, 0, 10)];
+        let b = vec![square(5, 5, 10)];
+        assert_eq!(area(&boolean(&a, &b, Operation::Union)), 175.);
+        assert_eq!(area(&boolean(&a, &b, Operation::Intersection)), 25.);
+        assert_eq!(area(&boolean(&a, &b, Operation::Difference)), 75.);
+        assert_eq!(area(&boolean(&b, &a, Operation::Difference)), 75.);
+        assert_eq!(area(&boolean(&a, &b, Operation::Xor)), 150.);
+        assert!(boolean(&a, &b, Operation::Union).iter()
+            .all(|t| t.is_rectangle()));
+    }
+
+    #[test]
+    fn orientation_and_self_overlap() {
+        let mut cw = square(0, 0, 10);
+        cw.reverse();
+        assert_eq!(area(&union(&[cw, square(0, 0, 10)])), 100.);
+        assert_eq!(area(&boolean(&[square(0, 0, 10)], &[square(20, 0, 5)],
+            Operation::Intersection)), 0.);
+    }
+
+    #[test]
+    fn slanted_edges() {
+        let triangle = vec![(0, 0), (10, 0), (0, 10)];
+        let traps = union(std::slice::from_ref(&triangle));
+        assert_eq!(area(&traps), 50.);
+        let cut = boolean(&[triangle], &[square(0, 0, 5)],
+            Operation::Difference);
+        assert_eq!(area(&cut), 25.);
+        // crossing edges create a scanline at the intersection
+        let a = vec![(0, 0), (10, 10), (0, 10)];
+        let b = vec![(10, 0), (0, 10), (10, 10)];
+        assert_eq!(area(&boolean(&[a], &[b], Operation::Intersection)), 25.);
+    }
+
+    #[test]
+    fn crossing_strips() {
+        // every strip of one set crosses every strip of the other set, the
+        // crossings are not between neighbouring edges at the bottom
+        let strip = |x: i32, dx: i32| vec![(x, 0), (x + 10, 0),
+            (x + 10 + dx, 100), (x + dx, 100)];
+        let a: Vec<_> = (0..3).map(|i| strip(20*i, 100)).collect();
+        let b: Vec<_> = (0..3).map(|i| strip(100 + 20*i, -100)).collect();
+        let both = boolean(&a, &b, Operation::Intersection);
+        assert!((area(&both) - 450.).abs() < 1e-6);
+        assert!((area(&boolean(&a, &b, Operation::Union)) - 5550.).abs()
+            < 1e-6);
+    }
+
+    #[test]
+    fn cut_line_hole() {
+        let keyhole = vec![(0, 0), (10, 0), (10, 10), (0, 10), (0, 0),
+            (4, 4), (4, 6), (6, 6), (6, 4), (4, 4), (0, 0)];
+        assert_eq!(area(&union(&[keyhole])), 96.);
+    }
+
+    #[test]
+    fn trapezoid_polygon() {
+        let t = Trapezoid{y_bottom: 0., y_top: 2., x_bottom_left: 0.,
+            x_bottom_right: 4., x_top_left: 1.75, x_top_right: 2.25};
+        assert_eq!(t.area(), 4.5);
+        assert!(!t.is_rectangle());
+        // the top edge collapses to a point after rounding
+        assert_eq!(t.to_polygon(), vec![(0, 0), (4, 0), (2, 2), (0, 0)]);
+    }
+}
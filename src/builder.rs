@@ -0,0 +1,281 @@
+//! Contains builders for creating libraries and structures in code.
+//!
+//! ```no_run
+//! use gds::builder::{LibraryBuilder, Strans, StructureBuilder};
+//!
+//! let via = StructureBuilder::new("VIA")
+//!     .rect(1, 0, 0, 0, 100, 100)
+//!     .build();
+//! let top = StructureBuilder::new("TOP")
+//!     .path(2, 0, 20, &[(0, 0), (1000, 0), (1000, 500)])
+//!     .text(10, 0, (0, 0), "IN")
+//!     .sref("VIA", (1000, 500), Strans::rotation(90.))
+//!     .aref("VIA", 4, 2, (0, 1000), (200, 0), (0, 200), Strans::identity())
+//!     .build();
+//! let lib = LibraryBuilder::new("LIB")
+//!     .units(1e-3, 1e-9)
+//!     .structure(via)
+//!     .structure(top)
+//!     .build();
+//! lib.write("out.gds").unwrap();
+//! ```
+
+use {Date, Element, ElementParameter, ElementType, Library, Structure};
+
+/// Builder for libraries.
+#[derive(Clone)]
+pub struct LibraryBuilder {
+    lib: Library
+}
+
+/// Builder for structures.
+#[derive(Debug,Clone)]
+pub struct StructureBuilder {
+    stru: Structure
+}
+
+/// Orientation of a structure or array reference.
+///
+/// The referenced structure is reflected about the x-axis first, then
+/// magnified and finally rotated counterclockwise by `angle` degrees.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Strans {
+    /// Reflection about the x-axis.
+    pub reflect: bool,
+    /// Magnification factor.
+    pub magnification: f64,
+    /// Rotation angle in degrees.
+    pub angle: f64
+}
+
+impl LibraryBuilder {
+    /// Creates a builder for a library named `name`.
+    ///
+    /// The library uses version 600, a database unit of 1 nm with a user
+    /// unit of 1 µm and the current date.
+    pub fn new(name: &str) -> LibraryBuilder {
+        let mut lib = Library::new(600, name.to_string());
+        lib.units_user = 1e-3;
+        lib.units_m = 1e-9;
+        LibraryBuilder{lib}
+    }
+
+    /// Sets the size of the database unit in user units and in metres.
+    pub fn units(mut self, user: f64, metres: f64) -> LibraryBuilder {
+        self.lib.units_user = user;
+        self.lib.units_m = metres;
+        self
+    }
+
+    /// Sets the GDS version.
+    pub fn version(mut self, version: i16) -> LibraryBuilder {
+        self.lib.version = version;
+        self
+    }
+
+    /// Adds a structure.
+    pub fn structure(mut self, stru: Structure) -> LibraryBuilder {
+        self.lib.structures.push(stru);
+        self
+    }
+
+    /// Returns the library.
+    pub fn build(self) -> Library {
+        self.lib
+    }
+}
+
+impl StructureBuilder {
+    /// Creates a builder for an empty structure named `name` with the
+    /// current date.
+    pub fn new(name: &str) -> StructureBuilder {
+        let mut stru = Structure::new();
+        stru.name = name.to_string();
+        stru.date_mod = Date::now();
+        stru.date_acc = stru.date_mod.clone();
+        StructureBuilder{stru}
+    }
+
+    /// Adds an element.
+    pub fn element(mut self, elem: Element) -> StructureBuilder {
+        self.stru.elements.push(elem);
+        self
+    }
+
+    /// Adds a rectangle with the corners `(x0, y0)` and `(x1, y1)` as
+    /// boundary.
+    pub fn rect(self, layer: i16, datatype: i16, x0: i32, y0: i32, x1: i32,
+            y1: i32) -> StructureBuilder {
+        let (xa, xb) = (x0.min(x1), x0.max(x1));
+        let (ya, yb) = (y0.min(y1), y0.max(y1));
+        self.polygon(layer, datatype, &[(xa, ya), (xb, ya), (xb, yb),
+            (xa, yb)])
+    }
+
+    /// Adds a boundary with the given points.
+    ///
+    /// The polygon is closed if the last point differs from the first one.
+    pub fn polygon(self, layer: i16, datatype: i16, points: &[(i32,i32)])
+            -> StructureBuilder {
+        let mut xy = points.to_vec();
+        if xy.first() != xy.last() {
+            xy.push(xy[0]);
+        }
+        self.element(Element{element_type: ElementType::Boundary,
+            parameters: vec![ElementParameter::Layer(layer),
+                ElementParameter::Datatype(datatype),
+                ElementParameter::XY(xy)]})
+    }
+
+    /// Adds a path with square ends flush with the first and last point.
+    pub fn path(self, layer: i16, datatype: i16, width: i32,
+            points: &[(i32,i32)]) -> StructureBuilder {
+        self.element(Element{element_type: ElementType::Path,
+            parameters: vec![ElementParameter::Layer(layer),
+                ElementParameter::Datatype(datatype),
+                ElementParameter::Pathtype(0), ElementParameter::Width(width),
+                ElementParameter::XY(points.to_vec())]})
+    }
+
+    /// Adds a text at `origin`.
+    pub fn text(self, layer: i16, texttype: i16, origin: (i32,i32),
+            string: &str) -> StructureBuilder {
+        self.element(Element{element_type: ElementType::Text,
+            parameters: vec![ElementParameter::Layer(layer),
+                ElementParameter::TextType(texttype),
+                ElementParameter::XY(vec![origin]),
+                ElementParameter::String(string.to_string())]})
+    }
+
+    /// Adds a reference to the structure `name` placed at `origin`.
+    pub fn sref(self, name: &str, origin: (i32,i32), strans: Strans)
+            -> StructureBuilder {
+        let mut parameters = vec![
+            ElementParameter::StructureName(name.to_string())];
+        parameters.extend(strans.parameters());
+        parameters.push(ElementParameter::XY(vec![origin]));
+        self.element(Element{element_type: ElementType::StructureRef,
+            parameters})
+    }
+
+    /// Adds an array of `cols` times `rows` references to the structure
+    /// `name`.
+    ///
+    /// The first instance is placed at `origin`, the following ones are
+    /// displaced by `col_pitch` and `row_pitch`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn aref(self, name: &str, cols: i16, rows: i16, origin: (i32,i32),
+            col_pitch: (i32,i32), row_pitch: (i32,i32), strans: Strans)
+            -> StructureBuilder {
+        let corner = |n: i16, p: (i32,i32)|
+            (origin.0 + n as i32*p.0, origin.1 + n as i32*p.1);
+        let mut parameters = vec![
+            ElementParameter::StructureName(name.to_string())];
+        parameters.extend(strans.parameters());
+        parameters.push(ElementParameter::ColRow(vec![cols, rows]));
+        parameters.push(ElementParameter::XY(vec![origin,
+            corner(cols, col_pitch), corner(rows, row_pitch)]));
+        self.element(Element{element_type: ElementType::ArrayRef,
+            parameters})
+    }
+
+    /// Returns the structure.
+    pub fn build(self) -> Structure {
+        self.stru
+    }
+}
+
+impl Strans {
+    /// Returns the orientation without reflection, magnification and
+    /// rotation.
+    pub fn identity() -> Strans {
+        Strans{reflect: false, magnification: 1., angle: 0.}
+    }
+
+    /// Returns a rotation by `angle` degrees counterclockwise.
+    pub fn rotation(angle: f64) -> Strans {
+        Strans{angle, ..Strans::identity()}
+    }
+
+    /// Returns a reflection about the x-axis.
+    pub fn reflection() -> Strans {
+        Strans{reflect: true, ..Strans::identity()}
+    }
+
+    /// Returns the STRANS, MAG and ANGLE parameters of the orientation.
+    ///
+    /// Default values are omitted.
+    pub fn parameters(&self) -> Vec<ElementParameter> {
+        let mut res = Vec::new();
+        if *self == Strans::identity() {
+            return res;
+        }
+        res.push(ElementParameter::StrTransf(
+            if self.reflect { 0x8000 } else { 0 }));
+        if self.magnification != 1. {
+            res.push(ElementParameter::Magnification(self.magnification));
+        }
+        if self.angle != 0. {
+            res.push(ElementParameter::Angle(self.angle));
+        }
+        res
+    }
+}
+
+impl Default for Strans {
+    fn default() -> Strans {
+        Strans::identity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orientation() {
+        assert!(Strans::default().parameters().is_empty());
+        assert!(matches!(Strans::reflection().parameters()[..],
+            [ElementParameter::StrTransf(0x8000)]));
+        assert!(matches!(Strans::rotation(90.).parameters()[..],
+            [ElementParameter::StrTransf(0), ElementParameter::Angle(a)]
+            if a == 90.));
+        let s = Strans{reflect: false, magnification: 2., angle: 0.};
+        assert!(matches!(s.parameters()[..],
+            [ElementParameter::StrTransf(0),
+            ElementParameter::Magnification(m)] if m == 2.));
+    }
+
+    #[test]
+    fn round_trip() {
+        let lib = LibraryBuilder::new("LIB").units(1e-2, 1e-8).version(5)
+            .structure(StructureBuilder::new("VIA")
+                .rect(1, 0, 100, 100, 0, 0).build())
+            .structure(StructureBuilder::new("TOP")
+                .polygon(3, 1, &[(0, 0), (10, 0), (0, 10)])
+                .path(2, 0, 20, &[(0, 0), (1000, 0)])
+                .text(10, 4, (5, 5), "IN")
+                .sref("VIA", (1000, 500), Strans::rotation(90.))
+                .aref("VIA", 4, 2, (0, 1000), (200, 0), (0, 300),
+                    Strans::reflection()).build())
+            .build();
+        let mut buf = Vec::new();
+        lib.to_writer(&mut buf).unwrap();
+        let read = Library::from_reader(&mut &buf[..]).unwrap();
+        assert_eq!((read.name.as_str(), read.version), ("LIB", 5));
+        assert_eq!((read.units_user, read.units_m), (1e-2, 1e-8));
+        let via = read.structure("VIA").unwrap();
+        assert_eq!(via.elements[0].xy().unwrap(),
+            &[(0, 0), (100, 0), (100, 100), (0, 100), (0, 0)]);
+        let top = read.structure("TOP").unwrap();
+        assert_eq!(top.elements.len(), 5);
+        assert_eq!(top.elements[0].xy().unwrap().len(), 4);
+        assert_eq!((top.elements[2].layer(), top.elements[2].datatype()),
+            (Some(10), Some(4)));
+        assert_eq!(top.elements[4].array_size(), (4, 2));
+        assert_eq!(top.elements[4].xy().unwrap(),
+            &[(0, 1000), (800, 1000), (0, 1600)]);
+        assert_eq!(format!("{:?}", read.structures),
+            format!("{:?}", lib.structures));
+    }
+}
@@ -0,0 +1,444 @@
+//! Contains the extraction of electrical connectivity from layers.
+//!
+//! A connectivity specification names the conducting layers and the layers
+//! connecting them. Shapes on the same layer are connected if they overlap or
+//! touch, shapes on different layers if the layers are connected and the
+//! shapes overlap or touch. The text format contains layer definitions and
+//! chains of connected layers, one per line:
+//!
+//! ```text
+//! metal1 = 8/0
+//! via1 = 9/0
+//! metal2 = 10/0
+//! metal1 -- via1 -- metal2
+//! # a conducting layer without connections to other layers
+//! 20/0
+//! # texts on 8/1 label shapes on metal1
+//! 8/1 -> metal1
+//! ```
+//!
+//! Layers can be given by a name defined before or directly as layer and
+//! datatype, a missing datatype is zero. Texts without a label mapping label
+//! the shapes whose layer and datatype equal the layer and texttype of the
+//! text.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::BufRead;
+
+use {Element, ElementParameter, ElementType, Error, Library};
+use geometry::BoundingBox;
+use index::RTree;
+use polygon::{contains, segments_intersect};
+
+/// Conducting layers and the connections between them.
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct Connectivity {
+    /// Layers and datatypes of all conducting layers.
+    pub layers: Vec<(i16,i16)>,
+    /// Pairs of connected layers.
+    pub connections: Vec<((i16,i16), (i16,i16))>,
+    /// Layers and texttypes of texts with the conducting layers they label.
+    pub labels: Vec<((i16,i16), (i16,i16))>
+}
+
+/// A shape of a net.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Shape {
+    /// Layer of the shape.
+    pub layer: i16,
+    /// Datatype of the shape.
+    pub datatype: i16,
+    /// Closed polygon of the shape.
+    pub polygon: Vec<(i32,i32)>
+}
+
+/// A group of connected shapes.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Net {
+    /// Sorted names of the labels placed on the net.
+    pub names: Vec<String>,
+    /// The shapes of the net.
+    pub shapes: Vec<Shape>
+}
+
+/// A label attached to several nets which are not connected.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Open {
+    /// The name of the label.
+    pub name: String,
+    /// Indices of the nets carrying the label.
+    pub nets: Vec<usize>
+}
+
+/// A net carrying several different labels.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Short {
+    /// Index of the net.
+    pub net: usize,
+    /// The names of the labels.
+    pub names: Vec<String>
+}
+
+/// The nets of a structure.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Netlist {
+    /// All nets, including those without labels.
+    pub nets: Vec<Net>
+}
+
+impl Connectivity {
+    /// Creates an empty specification.
+    pub fn new() -> Connectivity {
+        Connectivity::default()
+    }
+
+    /// Adds a conducting layer.
+    pub fn add_layer(&mut self, layer: (i16,i16)) {
+        if !self.layers.contains(&layer) {
+            self.layers.push(layer);
+        }
+    }
+
+    /// Connects the layers `a` and `b`, adding them as conducting layers.
+    pub fn connect(&mut self, a: (i16,i16), b: (i16,i16)) {
+        self.add_layer(a);
+        self.add_layer(b);
+        if a != b && !self.connects(a, b) {
+            self.connections.push((a, b));
+        }
+    }
+
+    /// Connects every layer of `layers` with the next one.
+    pub fn chain(&mut self, layers: &[(i16,i16)]) {
+        for &l in layers {
+            self.add_layer(l);
+        }
+        for w in layers.windows(2) {
+            self.connect(w[0], w[1]);
+        }
+    }
+
+    /// Lets texts on `text` label shapes on `layer`, adding it as conducting
+    /// layer.
+    ///
+    /// Texts can label several layers and several text layers can label the
+    /// same layer.
+    pub fn add_label(&mut self, text: (i16,i16), layer: (i16,i16)) {
+        self.add_layer(layer);
+        if !self.labels.contains(&(text, layer)) {
+            self.labels.push((text, layer));
+        }
+    }
+
+    /// Returns the conducting layers labelled by texts on `text`.
+    pub fn labelled_layers(&self, text: (i16,i16)) -> Vec<(i16,i16)> {
+        let res: Vec<(i16,i16)> = self.labels.iter()
+            .filter(|l| l.0 == text).map(|l| l.1).collect();
+        if res.is_empty() && self.layers.contains(&text) {
+            vec![text]
+        } else {
+            res
+        }
+    }
+
+    /// Returns true if the layers `a` and `b` are connected directly.
+    pub fn connects(&self, a: (i16,i16), b: (i16,i16)) -> bool {
+        self.connections.iter()
+            .any(|&(x, y)| (x == a && y == b) || (x == b && y == a))
+    }
+
+    /// Parses a specification in the text format described in the
+    /// [module documentation](index.html).
+    pub fn parse(s: &str) -> Result<Connectivity, Error> {
+        let mut res = Connectivity::new();
+        let mut names: HashMap<String, (i16,i16)> = HashMap::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = match line.find('#') {
+                Some(p) => &line[..p],
+                None => line
+            }.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |e: String| Error::Parse(
+                format!("line {}: {}", i + 1, e));
+            let layer = |item: &str| match names.get(item) {
+                Some(&x) => Ok(x),
+                None => parse_layer(item).map_err(error)
+            };
+            if let Some(p) = line.find("->") {
+                let text = layer(line[..p].trim())?;
+                res.add_label(text, layer(line[p + 2..].trim())?);
+                continue;
+            }
+            if let Some(p) = line.find('=') {
+                let name = line[..p].trim();
+                if name.is_empty() || name.contains(char::is_whitespace) {
+                    return Err(error(format!("invalid name {:?}", name)));
+                }
+                let layer = parse_layer(line[p + 1..].trim()).map_err(error)?;
+                names.insert(name.to_string(), layer);
+                continue;
+            }
+            let mut chain = Vec::new();
+            for item in line.split("--").map(|x| x.trim()) {
+                chain.push(layer(item)?);
+            }
+            res.chain(&chain);
+        }
+        Ok(res)
+    }
+
+    /// Reads a specification in text format from `r`.
+    pub fn read<R: BufRead>(r: &mut R) -> Result<Connectivity, Error> {
+        let mut s = String::new();
+        r.read_to_string(&mut s)?;
+        Connectivity::parse(&s)
+    }
+}
+
+fn parse_layer(s: &str) -> Result<(i16,i16), String> {
+    let mut items = s.splitn(2, '/');
+    let number = |x: &str| x.trim().parse()
+        .map_err(|_| format!("invalid layer {:?}", s));
+    let layer = number(items.next().unwrap_or(""))?;
+    let datatype = match items.next() {
+        Some(x) => number(x)?,
+        None => 0
+    };
+    Ok((layer, datatype))
+}
+
+impl Netlist {
+    /// Returns the indices of the nets carrying the label `name`.
+    pub fn find(&self, name: &str) -> Vec<usize> {
+        self.nets.iter().enumerate()
+            .filter(|&(_, n)| n.names.iter().any(|x| x == name))
+            .map(|(i, _)| i).collect()
+    }
+
+    /// Returns the labels placed on several unconnected nets, sorted by
+    /// name.
+    pub fn opens(&self) -> Vec<Open> {
+        let mut nets: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (i, net) in self.nets.iter().enumerate() {
+            for name in &net.names {
+                nets.entry(name.as_str()).or_default().push(i);
+            }
+        }
+        nets.into_iter().filter(|x| x.1.len() > 1)
+            .map(|(name, nets)| Open{name: name.to_string(), nets}).collect()
+    }
+
+    /// Returns the nets carrying several different labels.
+    pub fn shorts(&self) -> Vec<Short> {
+        self.nets.iter().enumerate().filter(|&(_, n)| n.names.len() > 1)
+            .map(|(net, n)| Short{net, names: n.names.clone()}).collect()
+    }
+}
+
+impl Library {
+    /// Groups the shapes of the structure `name` into nets.
+    ///
+    /// The structure is flattened and all boundaries, paths and boxes on
+    /// the conducting layers of `spec` are grouped according to their
+    /// connections. Texts are attached as labels to the nets of all shapes
+    /// containing their origin on the layers labelled by the layer and
+    /// texttype of the text, see
+    /// [Connectivity::labelled_layers](struct.Connectivity.html). Nets are
+    /// ordered by their first shape in the flattened structure.
+    pub fn extract_nets(&self, name: &str, spec: &Connectivity)
+            -> Result<Netlist, Error> {
+        let flat = self.flatten(name)
+            .ok_or_else(|| Error::MissingStructure(name.to_string()))?;
+        // shapes with their layer index and the index of their element
+        let mut shapes: Vec<(usize, usize, Shape, BoundingBox)> = Vec::new();
+        for (i, elem) in flat.elements.iter().enumerate() {
+            let key = (elem.layer().unwrap_or(0), elem.datatype().unwrap_or(0));
+            let layer = match spec.layers.iter().position(|&l| l == key) {
+                Some(l) => l,
+                None => continue
+            };
+            for polygon in elem.polygons() {
+                let bbox = match BoundingBox::from_points(&polygon) {
+                    Some(x) => x,
+                    None => continue
+                };
+                shapes.push((layer, i, Shape{layer: key.0, datatype: key.1,
+                    polygon}, bbox));
+            }
+        }
+
+        let mut sets = DisjointSets::new(shapes.len());
+        let tree = RTree::new(shapes.iter().enumerate()
+            .map(|(i, s)| (s.3, i)).collect());
+        for (i, a) in shapes.iter().enumerate() {
+            for &j in tree.window(&a.3) {
+                let b = &shapes[j];
+                if j <= i {
+                    continue;
+                }
+                let connected = a.1 == b.1 || ((a.0 == b.0 ||
+                    spec.connects(spec.layers[a.0], spec.layers[b.0])) &&
+                    polygons_touch(&a.2.polygon, &b.2.polygon));
+                if connected {
+                    sets.join(i, j);
+                }
+            }
+        }
+
+        let mut roots: HashMap<usize, usize> = HashMap::new();
+        let mut nets: Vec<Net> = Vec::new();
+        let mut net_of = Vec::with_capacity(shapes.len());
+        for i in 0..shapes.len() {
+            let root = sets.find(i);
+            let n = *roots.entry(root).or_insert_with(|| {
+                nets.push(Net{names: Vec::new(), shapes: Vec::new()});
+                nets.len() - 1
+            });
+            net_of.push(n);
+        }
+        for elem in &flat.elements {
+            if !matches!(elem.element_type, ElementType::Text) {
+                continue;
+            }
+            let (layer, label) = match (elem.layer(), text_string(elem)) {
+                (Some(l), Some(s)) => ((l, elem.datatype().unwrap_or(0)), s),
+                _ => continue
+            };
+            let layers = spec.labelled_layers(layer);
+            let p = match elem.xy() {
+                Some(xy) if !xy.is_empty() => xy[0],
+                _ => continue
+            };
+            for &j in tree.point(p) {
+                let s = &shapes[j].2;
+                if layers.contains(&(s.layer, s.datatype)) &&
+                        contains(&s.polygon, p) {
+                    nets[net_of[j]].names.push(label.to_string());
+                }
+            }
+        }
+        for (s, n) in shapes.into_iter().zip(net_of) {
+            nets[n].shapes.push(s.2);
+        }
+        for net in &mut nets {
+            net.names.sort();
+            net.names.dedup();
+        }
+        Ok(Netlist{nets})
+    }
+}
+
+fn text_string(elem: &Element) -> Option<&str> {
+    elem.parameters.iter().filter_map(|p| match *p {
+        ElementParameter::String(ref x) => Some(x.as_str()),
+        _ => None
+    }).next()
+}
+
+/// Union-find structure with path halving.
+struct DisjointSets {
+    parent: Vec<usize>
+}
+
+impl DisjointSets {
+    fn new(n: usize) -> DisjointSets {
+        DisjointSets{parent: (0..n).collect()}
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn join(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parent[a.max(b)] = a.min(b);
+        }
+    }
+}
+
+/// Returns true if the polygons overlap or touch.
+fn polygons_touch(a: &[(i32,i32)], b: &[(i32,i32)]) -> bool {
+    if a.is_empty() || b.is_empty() {
+        return false;
+    }
+    for i in 0..a.len() {
+        let (p, q) = (a[i], a[(i + 1) % a.len()]);
+        for j in 0..b.len() {
+            if segments_intersect(p, q, b[j], b[(j + 1) % b.len()]) {
+                return true;
+            }
+        }
+    }
+    contains(a, b[0]) || contains(b, a[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use builder::{LibraryBuilder, StructureBuilder};
+
+    fn library(top: StructureBuilder) -> Library {
+        LibraryBuilder::new("LIB").structure(top.build()).build()
+    }
+
+    #[test]
+    fn parse() {
+        let spec = Connectivity::parse("m1 = 8/0\nvia=9\n\n# comment\n\
+            m1 -- via -- 10/0 # metal2\n20\n8/1 -> m1\n8/1->10/0").unwrap();
+        assert_eq!(spec.layers, vec![(8, 0), (9, 0), (10, 0), (20, 0)]);
+        assert_eq!(spec.connections, vec![((8, 0), (9, 0)), ((9, 0), (10, 0))]);
+        assert_eq!(spec.labels, vec![((8, 1), (8, 0)), ((8, 1), (10, 0))]);
+        assert_eq!(spec.labelled_layers((8, 1)), vec![(8, 0), (10, 0)]);
+        assert_eq!(spec.labelled_layers((9, 0)), vec![(9, 0)]);
+        assert_eq!(spec.labelled_layers((9, 1)), vec![]);
+        assert!(Connectivity::parse("a b = 1").is_err());
+        assert!(Connectivity::parse("1 -- x").is_err());
+        assert!(Connectivity::parse("1/0 -> ").is_err());
+    }
+
+    #[test]
+    fn nets_and_labels() {
+        let spec = Connectivity::parse("8 -- 9 -- 10\n8/1 -> 10").unwrap();
+        let lib = library(StructureBuilder::new("TOP")
+            // metal1 connected to metal2 through a via
+            .rect(8, 0, 0, 0, 100, 10).rect(9, 0, 90, 0, 100, 10)
+            .rect(10, 0, 90, 0, 200, 10)
+            // touching metal1, but metal1 on another datatype is unused
+            .rect(8, 0, 100, 20, 150, 30).rect(8, 0, 150, 20, 200, 30)
+            .rect(8, 5, 0, 20, 100, 30)
+            // metal2 crossing metal1 without a via, texts on 8/1 only label
+            // metal2
+            .rect(10, 0, 0, 40, 10, 60).rect(8, 0, 0, 50, 100, 60)
+            .text(8, 0, (5, 5), "A").text(10, 0, (150, 5), "A")
+            .text(8, 0, (120, 25), "B").text(8, 0, (180, 25), "C")
+            .text(8, 5, (50, 25), "X").text(10, 1, (150, 5), "Y")
+            .text(8, 1, (5, 45), "D").text(8, 1, (50, 55), "E"));
+        let netlist = lib.extract_nets("TOP", &spec).unwrap();
+        let names: Vec<Vec<&str>> = netlist.nets.iter()
+            .map(|n| n.names.iter().map(|x| x.as_str()).collect()).collect();
+        assert_eq!(names, vec![vec!["A"], vec!["B", "C"], vec!["D"],
+            vec![]]);
+        let shapes: Vec<usize> = netlist.nets.iter().map(|n| n.shapes.len())
+            .collect();
+        assert_eq!(shapes, vec![3, 2, 1, 1]);
+        assert_eq!(netlist.find("D"), vec![2]);
+        assert_eq!(netlist.shorts(), vec![Short{net: 1,
+            names: vec![String::from("B"), String::from("C")]}]);
+        assert!(netlist.opens().is_empty());
+
+        let lib = library(StructureBuilder::new("TOP")
+            .rect(8, 0, 0, 0, 10, 10).rect(8, 0, 20, 0, 30, 10)
+            .text(8, 0, (5, 5), "A").text(8, 0, (25, 5), "A"));
+        let netlist = lib.extract_nets("TOP", &spec).unwrap();
+        assert_eq!(netlist.opens(), vec![Open{name: String::from("A"),
+            nets: vec![0, 1]}]);
+        assert!(lib.extract_nets("MISSING", &spec).is_err());
+    }
+}
@@ -1,6 +1,9 @@
 //! Contains the necessary constants for the gds crate.
 #![allow(dead_code)]
 
+/// Maximum number of points fitting into a single XY record.
+pub const MAX_POINTS: usize = 8191;
+
 // record types
 pub const REC_TYPE_HEADER: u8 = 0x00;
 pub const REC_TYPE_BGNLIB: u8 = 0x01;
@@ -33,6 +36,9 @@ pub const REC_TYPE_PATHTYPE: u8 = 0x21;
 pub const REC_TYPE_BOX: u8 = 0x2D;
 pub const REC_TYPE_EFLAGS: u8 = 0x26;
 pub const REC_TYPE_NODETYPE: u8 = 0x2A;
+pub const REC_TYPE_PROPATTR: u8 = 0x2B;
+pub const REC_TYPE_PROPVALUE: u8 = 0x2C;
+pub const REC_TYPE_BOXTYPE: u8 = 0x2E;
 pub const REC_TYPE_BGNEXTN: u8 = 0x30;
 
 // data types
@@ -58,3 +64,57 @@ pub fn data_size(t: u8) -> usize {
         _ => 0
     }
 }
+
+/// Names and data types of all supported record types.
+const RECORDS: &[(u8, &str, u8)] = &[
+    (REC_TYPE_HEADER, "HEADER", DATA_TYPE_INT16),
+    (REC_TYPE_BGNLIB, "BGNLIB", DATA_TYPE_INT16),
+    (REC_TYPE_LIBNAME, "LIBNAME", DATA_TYPE_STR),
+    (REC_TYPE_UNITS, "UNITS", DATA_TYPE_REAL64),
+    (REC_TYPE_ENDLIB, "ENDLIB", DATA_TYPE_NONE),
+    (REC_TYPE_BGNSTR, "BGNSTR", DATA_TYPE_INT16),
+    (REC_TYPE_STRNAME, "STRNAME", DATA_TYPE_STR),
+    (REC_TYPE_ENDSTR, "ENDSTR", DATA_TYPE_NONE),
+    (REC_TYPE_BOUNDARY, "BOUNDARY", DATA_TYPE_NONE),
+    (REC_TYPE_PATH, "PATH", DATA_TYPE_NONE),
+    (REC_TYPE_SREF, "SREF", DATA_TYPE_NONE),
+    (REC_TYPE_AREF, "AREF", DATA_TYPE_NONE),
+    (REC_TYPE_TEXT, "TEXT", DATA_TYPE_NONE),
+    (REC_TYPE_LAYER, "LAYER", DATA_TYPE_INT16),
+    (REC_TYPE_DATATYPE, "DATATYPE", DATA_TYPE_INT16),
+    (REC_TYPE_WIDTH, "WIDTH", DATA_TYPE_INT32),
+    (REC_TYPE_XY, "XY", DATA_TYPE_INT32),
+    (REC_TYPE_ENDEL, "ENDEL", DATA_TYPE_NONE),
+    (REC_TYPE_SNAME, "SNAME", DATA_TYPE_STR),
+    (REC_TYPE_COLROW, "COLROW", DATA_TYPE_INT16),
+    (REC_TYPE_NODE, "NODE", DATA_TYPE_NONE),
+    (REC_TYPE_TEXTTYPE, "TEXTTYPE", DATA_TYPE_INT16),
+    (REC_TYPE_PRESENTATION, "PRESENTATION", DATA_TYPE_BIT),
+    (REC_TYPE_STRING, "STRING", DATA_TYPE_STR),
+    (REC_TYPE_STRANS, "STRANS", DATA_TYPE_BIT),
+    (REC_TYPE_MAG, "MAG", DATA_TYPE_REAL64),
+    (REC_TYPE_ANGLE, "ANGLE", DATA_TYPE_REAL64),
+    (REC_TYPE_PATHTYPE, "PATHTYPE", DATA_TYPE_INT16),
+    (REC_TYPE_BOX, "BOX", DATA_TYPE_NONE),
+    (REC_TYPE_EFLAGS, "EFLAGS", DATA_TYPE_BIT),
+    (REC_TYPE_NODETYPE, "NODETYPE", DATA_TYPE_INT16),
+    (REC_TYPE_PROPATTR, "PROPATTR", DATA_TYPE_INT16),
+    (REC_TYPE_PROPVALUE, "PROPVALUE", DATA_TYPE_STR),
+    (REC_TYPE_BOXTYPE, "BOXTYPE", DATA_TYPE_INT16),
+    (REC_TYPE_BGNEXTN, "BGNEXTN", DATA_TYPE_INT32),
+];
+
+/// Returns the name of the record type `t`.
+pub fn record_name(t: u8) -> Option<&'static str> {
+    RECORDS.iter().find(|r| r.0 == t).map(|r| r.1)
+}
+
+/// Returns the record type with the name `name`.
+pub fn record_type(name: &str) -> Option<u8> {
+    RECORDS.iter().find(|r| r.1 == name).map(|r| r.0)
+}
+
+/// Returns the data type used by the record type `t`.
+pub fn record_data_type(t: u8) -> Option<u8> {
+    RECORDS.iter().find(|r| r.0 == t).map(|r| r.2)
+}
@@ -0,0 +1,255 @@
+//! Contains conversions between dates of GDS files and system times.
+//!
+//! Dates are interpreted as UTC. With the `chrono` feature dates can also be
+//! converted from and to the types of the chrono crate.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+use {Date, Error, Library};
+
+impl Date {
+    /// Returns the current date.
+    pub fn now() -> Date {
+        Date::from_system_time(SystemTime::now())
+    }
+
+    /// Creates a date from the first six values of a BGNLIB or BGNSTR
+    /// record.
+    ///
+    /// Years written with two digits (e.g. 17 for 2017) or as years since
+    /// 1900 (e.g. 117 for 2017) are converted to full years. Two digit years
+    /// below 70 are placed in the 21st century. Dates consisting of zeros are
+    /// kept unchanged. Missing values are treated as zero.
+    pub fn from_fields(fields: &[i16]) -> Date {
+        let f = |i: usize| fields.get(i).cloned().unwrap_or(0);
+        let mut year = f(0);
+        if f(1) != 0 {
+            if (0..70).contains(&year) {
+                year += 2000;
+            } else if (70..1000).contains(&year) {
+                year += 1900;
+            }
+        }
+        Date{year, month: f(1), day: f(2), hour: f(3), minute: f(4),
+            second: f(5)}
+    }
+
+    /// Converts a system time into a date.
+    ///
+    /// Fractions of seconds are dropped.
+    pub fn from_system_time(t: SystemTime) -> Date {
+        let secs = match t.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64) -
+                if e.duration().subsec_nanos() > 0 { 1 } else { 0 }
+        };
+        let days = secs.div_euclid(86400);
+        let rem = secs.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+        Date{year: year as i16, month: month as i16, day: day as i16,
+            hour: (rem/3600) as i16, minute: (rem/60%60) as i16,
+            second: (rem%60) as i16}
+    }
+
+    /// Converts the date into a system time.
+    ///
+    /// An error is returned if the date is invalid.
+    pub fn to_system_time(&self) -> Result<SystemTime, Error> {
+        self.validate()?;
+        let secs = days_from_civil(self.year as i64, self.month as i64,
+            self.day as i64)*86400 + self.hour as i64*3600 +
+            self.minute as i64*60 + self.second as i64;
+        Ok(if secs >= 0 {
+            UNIX_EPOCH + Duration::from_secs(secs as u64)
+        } else {
+            UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+        })
+    }
+
+    /// Returns true if all fields are zero.
+    ///
+    /// Some tools write such dates if the date is unknown.
+    pub fn is_zero(&self) -> bool {
+        self.year == 0 && self.month == 0 && self.day == 0 && self.hour == 0 &&
+            self.minute == 0 && self.second == 0
+    }
+
+    /// Checks that all fields are in their valid range.
+    ///
+    /// The day has to exist in the given month, leap seconds are not
+    /// allowed.
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |field: &str, value: i16| Err(Error::InvalidValue(
+            format!("invalid {} {} in date {}", field, value, self)));
+        if self.month < 1 || self.month > 12 {
+            return invalid("month", self.month);
+        }
+        if self.day < 1 || self.day > days_in_month(self.year, self.month) {
+            return invalid("day", self.day);
+        }
+        if self.hour < 0 || self.hour > 23 {
+            return invalid("hour", self.hour);
+        }
+        if self.minute < 0 || self.minute > 59 {
+            return invalid("minute", self.minute);
+        }
+        if self.second < 0 || self.second > 59 {
+            return invalid("second", self.second);
+        }
+        Ok(())
+    }
+
+    /// Converts the date into a chrono date.
+    ///
+    /// An error is returned if the date is invalid.
+    #[cfg(feature = "chrono")]
+    pub fn to_chrono(&self) -> Result<NaiveDateTime, Error> {
+        self.validate()?;
+        NaiveDate::from_ymd_opt(self.year as i32, self.month as u32,
+                self.day as u32)
+            .and_then(|d| d.and_hms_opt(self.hour as u32, self.minute as u32,
+                self.second as u32))
+            .ok_or_else(
+                || Error::InvalidValue(format!("invalid date {}", self)))
+    }
+}
+
+impl Library {
+    /// Sets the modification and access dates of the library to the current
+    /// date.
+    ///
+    /// Writing a library does not change its dates, call this function
+    /// before writing to record the time of the change or write with
+    /// [WriteOptions::date_mod](struct.WriteOptions.html).
+    pub fn touch(&mut self) {
+        self.date_mod = Date::now();
+        self.date_acc = self.date_mod.clone();
+    }
+}
+
+impl From<SystemTime> for Date {
+    fn from(t: SystemTime) -> Date {
+        Date::from_system_time(t)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<NaiveDateTime> for Date {
+    fn from(t: NaiveDateTime) -> Date {
+        use chrono::{Datelike, Timelike};
+        Date{year: t.year() as i16, month: t.month() as i16,
+            day: t.day() as i16, hour: t.hour() as i16,
+            minute: t.minute() as i16, second: t.second() as i16}
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<DateTime<Utc>> for Date {
+    fn from(t: DateTime<Utc>) -> Date {
+        Date::from(t.naive_utc())
+    }
+}
+
+fn is_leap_year(year: i16) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i16, month: i16) -> i16 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31
+    }
+}
+
+/// Returns the number of days since 1970-01-01 of a date of the proleptic
+/// Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era*400;
+    let doy = (153*((month + 9) % 12) + 2)/5 + day - 1;
+    let doe = yoe*365 + yoe/4 - yoe/100 + doy;
+    era*146097 + doe - 719468
+}
+
+/// Returns year, month and day of a number of days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era*146097;
+    let yoe = (doe - doe/1460 + doe/36524 - doe/146096)/365;
+    let doy = doe - (365*yoe + yoe/4 - yoe/100);
+    let mp = (5*doy + 2)/153;
+    let day = doy - (153*mp + 2)/5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era*400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i16, month: i16, day: i16, hour: i16, minute: i16,
+            second: i16) -> Date {
+        Date{year, month, day, hour, minute, second}
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(Date::from_fields(&[17, 3, 4, 5, 6, 7]),
+            date(2017, 3, 4, 5, 6, 7));
+        assert_eq!(Date::from_fields(&[117, 3, 4]), date(2017, 3, 4, 0, 0, 0));
+        assert_eq!(Date::from_fields(&[98, 12, 31]),
+            date(1998, 12, 31, 0, 0, 0));
+        assert_eq!(Date::from_fields(&[1998, 1, 1]), date(1998, 1, 1, 0, 0, 0));
+        assert!(Date::from_fields(&[0; 6]).is_zero());
+        assert!(Date::from_fields(&[]).is_zero());
+    }
+
+    #[test]
+    fn system_times() {
+        let epoch = Date::from_system_time(UNIX_EPOCH);
+        assert_eq!(epoch, date(1970, 1, 1, 0, 0, 0));
+        let t = UNIX_EPOCH + Duration::new(951_782_400 + 3723, 500);
+        assert_eq!(Date::from(t), date(2000, 2, 29, 1, 2, 3));
+        // times before the epoch round down to full seconds
+        let t = UNIX_EPOCH - Duration::from_millis(500);
+        assert_eq!(Date::from(t), date(1969, 12, 31, 23, 59, 59));
+        for d in &[date(1600, 3, 1, 0, 0, 0), date(1969, 7, 20, 20, 17, 40),
+                date(2038, 1, 19, 3, 14, 8), date(2400, 2, 29, 23, 59, 59)] {
+            assert_eq!(&Date::from(d.to_system_time().unwrap()), d);
+        }
+        for days in -800_000..800_000 {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y, m, d), days);
+        }
+    }
+
+    #[test]
+    fn validation() {
+        assert!(date(2000, 2, 29, 0, 0, 0).validate().is_ok());
+        assert!(date(2024, 12, 31, 23, 59, 59).validate().is_ok());
+        for d in &[date(1900, 2, 29, 0, 0, 0), date(2001, 4, 31, 0, 0, 0),
+                date(2001, 0, 1, 0, 0, 0), date(2001, 13, 1, 0, 0, 0),
+                date(2001, 1, 0, 0, 0, 0), date(2001, 1, 1, 24, 0, 0),
+                date(2001, 1, 1, 0, 60, 0), date(2001, 1, 1, 0, 0, 60),
+                date(2001, 1, 1, -1, 0, 0), Date::from_fields(&[])] {
+            assert!(matches!(d.to_system_time(), Err(Error::InvalidValue(_))),
+                "{}", d);
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn chrono() {
+        let d = date(2021, 6, 30, 12, 0, 1);
+        assert_eq!(Date::from(d.to_chrono().unwrap()), d);
+        assert!(date(2021, 6, 31, 0, 0, 0).to_chrono().is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Contains the removal of duplicate structures.
+//!
+//! Merged libraries often contain several copies of the same structure under
+//! different names. Two structures are identical if their normalised
+//! elements are equal independent of their order, where references to
+//! identical structures count as equal.
+
+use std::collections::{HashMap, HashSet};
+
+use {Library, Structure};
+use diff::content_key;
+use normalize::NormalizeOptions;
+
+/// Description of the structures removed by a deduplication.
+#[derive(Debug,Clone,Default,PartialEq)]
+pub struct DedupReport {
+    /// Removed structures given as tuple of removed and kept name.
+    pub removed: Vec<(String, String)>,
+    /// Number of elements in the removed structures.
+    pub elements: usize,
+    /// Size of the removed structures in a GDS file in bytes.
+    pub bytes: u64
+}
+
+impl Library {
+    /// Removes structures identical to another structure.
+    ///
+    /// Of every group of identical structures the first one in the library
+    /// is kept and all references to the others are changed to it. The
+    /// elements of the structures are compared after
+    /// [normalisation](normalize/index.html) with the default options, the
+    /// kept structures are not changed. Structures with the same name are
+    /// not handled. Removed structures may be top structures.
+    pub fn deduplicate(&mut self) -> DedupReport {
+        let mut names: HashSet<&str> = HashSet::new();
+        let duplicate_names: HashSet<&str> = self.structures.iter()
+            .map(|s| s.name.as_str()).filter(|n| !names.insert(n)).collect();
+        // every structure is mapped to the first structure with the same
+        // content in bottom up order, referenced structures come first
+        let mut group: HashMap<&str, &str> = HashMap::new();
+        let mut keys: HashMap<String, &str> = HashMap::new();
+        for stru in self.bottom_up() {
+            if duplicate_names.contains(stru.name.as_str()) {
+                continue;
+            }
+            let mut elements = stru.elements.clone();
+            for elem in &mut elements {
+                elem.normalize(&NormalizeOptions::default());
+            }
+            let key = content_key(&elements, &group);
+            let first = *keys.entry(key).or_insert(&stru.name);
+            group.insert(&stru.name, first);
+        }
+        // keep the first structure of every group in library order
+        let mut kept: HashMap<&str, &str> = HashMap::new();
+        for stru in &self.structures {
+            if let Some(g) = group.get(stru.name.as_str()) {
+                kept.entry(g).or_insert(&stru.name);
+            }
+        }
+        let rename: HashMap<String, String> = group.iter()
+            .map(|(&n, g)| (n, kept[g]))
+            .filter(|&(n, k)| n != k)
+            .map(|(n, k)| (n.to_string(), k.to_string())).collect();
+
+        let mut report = DedupReport::default();
+        let mut structures = Vec::with_capacity(self.structures.len());
+        for mut stru in self.structures.drain(..) {
+            match rename.get(&stru.name) {
+                Some(k) => {
+                    report.elements += stru.elements.len();
+                    report.bytes += gds_size(&stru);
+                    report.removed.push((stru.name.clone(), k.clone()));
+                },
+                None => {
+                    stru.rename_references(&rename);
+                    structures.push(stru);
+                }
+            }
+        }
+        self.structures = structures;
+        report
+    }
+}
+
+/// Returns the number of bytes of the structure in a GDS file.
+fn gds_size(stru: &Structure) -> u64 {
+    // BGNSTR with two dates, STRNAME padded to an even length and ENDSTR
+    let header = 28 + 4 + (stru.name.len() + stru.name.len() % 2) + 4;
+    header as u64 + stru.elements.iter().flat_map(|e| e.to_records())
+        .map(|r| r.size as u64).sum::<u64>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use builder::{LibraryBuilder, Strans, StructureBuilder};
+
+    fn size(lib: &Library) -> u64 {
+        let mut buf = Vec::new();
+        lib.to_writer(&mut buf).unwrap();
+        buf.len() as u64
+    }
+
+    #[test]
+    fn identical_cells() {
+        let lib = LibraryBuilder::new("LIB")
+            .structure(StructureBuilder::new("A")
+                .polygon(1, 0, &[(10, 10), (0, 10), (0, 0), (10, 0)])
+                .rect(2, 0, 0, 0, 5, 5).build())
+            .structure(StructureBuilder::new("B_COPY")
+                .rect(2, 0, 5, 5, 0, 0).rect(1, 0, 0, 0, 10, 10).build())
+            .structure(StructureBuilder::new("C")
+                .rect(1, 0, 0, 0, 10, 20).build())
+            .structure(StructureBuilder::new("X")
+                .sref("A", (0, 0), Strans::identity()).build())
+            .structure(StructureBuilder::new("Y")
+                .sref("B_COPY", (0, 0), Strans::identity()).build())
+            .structure(StructureBuilder::new("TOP")
+                .sref("X", (0, 0), Strans::identity())
+                .sref("Y", (100, 0), Strans::identity())
+                .sref("C", (200, 0), Strans::identity()).build())
+            .build();
+        let mut dedup = lib.clone();
+        let report = dedup.deduplicate();
+        assert_eq!(report.removed, vec![
+            (String::from("B_COPY"), String::from("A")),
+            (String::from("Y"), String::from("X"))]);
+        assert_eq!(report.elements, 3);
+        assert_eq!(report.bytes, size(&lib) - size(&dedup));
+        let names: Vec<&str> = dedup.structures.iter()
+            .map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["A", "C", "X", "TOP"]);
+        let top = dedup.structure("TOP").unwrap();
+        let refs: Vec<&str> = top.elements.iter()
+            .filter_map(|e| e.structure_name()).collect();
+        assert_eq!(refs, vec!["X", "X", "C"]);
+        // the kept structures are not normalised
+        assert_eq!(dedup.structure("A").unwrap().elements[0].xy(),
+            lib.structure("A").unwrap().elements[0].xy());
+        assert_eq!(dedup.deduplicate(), DedupReport::default());
+    }
+
+    #[test]
+    fn kept_structures() {
+        // different layers, reference transformations and duplicate names
+        // are not merged
+        let lib = LibraryBuilder::new("LIB")
+            .structure(StructureBuilder::new("A")
+                .rect(1, 0, 0, 0, 10, 10).build())
+            .structure(StructureBuilder::new("B")
+                .rect(1, 1, 0, 0, 10, 10).build())
+            .structure(StructureBuilder::new("X")
+                .sref("A", (0, 0), Strans::identity()).build())
+            .structure(StructureBuilder::new("Y")
+                .sref("A", (0, 0), Strans::rotation(90.)).build())
+            .structure(StructureBuilder::new("D")
+                .rect(2, 0, 0, 0, 1, 1).build())
+            .structure(StructureBuilder::new("D")
+                .rect(2, 0, 0, 0, 1, 1).build())
+            .structure(StructureBuilder::new("E")
+                .rect(2, 0, 0, 0, 1, 1).build())
+            .build();
+        let mut dedup = lib.clone();
+        let report = dedup.deduplicate();
+        assert_eq!(report.removed, vec![]);
+        assert_eq!(dedup.structures.len(), lib.structures.len());
+    }
+}
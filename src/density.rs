@@ -0,0 +1,365 @@
+//! Contains the computation of layer densities.
+//!
+//! The density of a layer is the fraction of a window covered by shapes on
+//! the layer. The windows form a regular grid starting at the lower left
+//! corner of the bounding box of the structure. If the step between windows
+//! is smaller than their size, the windows overlap and slide over the
+//! structure. Windows at the upper and right border are clipped to the
+//! bounding box.
+
+use {Element, Error, Library};
+use boolean::{self, Trapezoid};
+use diff::layer_polygons;
+use generators::boundary;
+use geometry::BoundingBox;
+
+/// Size and step of the density windows and the allowed density range.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct DensityRule {
+    /// Width and height of a window in metres.
+    pub window: f64,
+    /// Distance between neighbouring windows in metres.
+    pub step: f64,
+    /// Minimum allowed density between 0 and 1.
+    pub min: f64,
+    /// Maximum allowed density between 0 and 1.
+    pub max: f64
+}
+
+/// The density of a single window.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct DensityWindow {
+    /// Column of the window, starting with zero at the left.
+    pub col: usize,
+    /// Row of the window, starting with zero at the bottom.
+    pub row: usize,
+    /// Area of the window in database units.
+    pub bbox: BoundingBox,
+    /// Fraction of the window covered by shapes.
+    pub density: f64
+}
+
+/// The densities of a layer.
+#[derive(Debug,Clone,PartialEq)]
+pub struct DensityMap {
+    /// Layer of the shapes.
+    pub layer: i16,
+    /// Datatype of the shapes.
+    pub datatype: i16,
+    /// Number of columns of windows.
+    pub columns: usize,
+    /// Number of rows of windows.
+    pub rows: usize,
+    /// Width and height of a window in database units.
+    pub window: i32,
+    /// Distance between neighbouring windows in database units.
+    pub step: i32,
+    /// The windows ordered by row and column.
+    pub windows: Vec<DensityWindow>
+}
+
+impl DensityRule {
+    /// Creates a rule for tiled windows of `window` metres.
+    pub fn new(window: f64, min: f64, max: f64) -> DensityRule {
+        DensityRule{window, step: window, min, max}
+    }
+
+    /// Creates a rule for sliding windows of `window` metres moved by `step`
+    /// metres.
+    pub fn sliding(window: f64, step: f64, min: f64, max: f64)
+            -> DensityRule {
+        DensityRule{window, step, min, max}
+    }
+
+    /// Returns true if `density` lies within the allowed range.
+    pub fn allows(&self, density: f64) -> bool {
+        density >= self.min && density <= self.max
+    }
+
+    /// Returns the window size and step in database units of `units_m`
+    /// metres.
+    pub(crate) fn grid(&self, units_m: f64) -> Result<(i32, i32), Error> {
+        if !(units_m.is_finite() && units_m > 0.) {
+            return Err(Error::InvalidValue(
+                format!("invalid database unit {}", units_m)));
+        }
+        let window = (self.window/units_m).round();
+        let step = (self.step/units_m).round();
+        if !(window >= 1. && window <= i32::MAX as f64) {
+            return Err(Error::InvalidValue(
+                format!("invalid window size {}", self.window)));
+        }
+        if !(step >= 1. && step <= window) {
+            return Err(Error::InvalidValue(
+                format!("invalid window step {}", self.step)));
+        }
+        Ok((window as i32, step as i32))
+    }
+}
+
+impl DensityMap {
+    /// Returns the density of the window in column `col` and row `row`.
+    pub fn get(&self, col: usize, row: usize) -> Option<f64> {
+        if col >= self.columns || row >= self.rows {
+            return None;
+        }
+        Some(self.windows[row*self.columns + col].density)
+    }
+
+    /// Returns the lowest density of all windows.
+    pub fn min(&self) -> Option<f64> {
+        self.windows.iter().map(|w| w.density).fold(None,
+            |a, x| Some(a.map_or(x, |a: f64| a.min(x))))
+    }
+
+    /// Returns the highest density of all windows.
+    pub fn max(&self) -> Option<f64> {
+        self.windows.iter().map(|w| w.density).fold(None,
+            |a, x| Some(a.map_or(x, |a: f64| a.max(x))))
+    }
+
+    /// Returns the windows whose density lies outside the range of `rule`.
+    pub fn violations(&self, rule: &DensityRule) -> Vec<&DensityWindow> {
+        self.windows.iter().filter(|w| !rule.allows(w.density)).collect()
+    }
+
+    /// Returns the indices of the windows overlapping the given area.
+    pub(crate) fn windows_in(&self, x_min: f64, y_min: f64, x_max: f64,
+            y_max: f64) -> Vec<usize> {
+        let origin = match self.windows.first() {
+            Some(w) => (w.bbox.x_min as f64, w.bbox.y_min as f64),
+            None => return Vec::new()
+        };
+        let (window, step) = (self.window as f64, self.step as f64);
+        let first = |v: f64, min: f64|
+            (((v - min - window)/step).floor() + 1.).max(0.) as usize;
+        let last = |v: f64, min: f64, n: usize|
+            ((((v - min)/step).floor()).max(0.) as usize).min(n - 1);
+        let mut res = Vec::new();
+        for row in first(y_min, origin.1)..
+                last(y_max, origin.1, self.rows) + 1 {
+            for col in first(x_min, origin.0)..
+                    last(x_max, origin.0, self.columns) + 1 {
+                res.push(row*self.columns + col);
+            }
+        }
+        res
+    }
+
+    /// Returns a heat map of the densities as boundaries on `layer`.
+    ///
+    /// Every window is drawn as a square of the size of the step centred in
+    /// the window, so the squares do not overlap. Squares are clipped to
+    /// the window. The datatype is the density in percent.
+    pub fn heat_map(&self, layer: i16) -> Vec<Element> {
+        let margin = (self.window - self.step)/2;
+        self.windows.iter().filter_map(|w| {
+            let x0 = w.bbox.x_min.saturating_add(margin);
+            let y0 = w.bbox.y_min.saturating_add(margin);
+            let x1 = x0.saturating_add(self.step).min(w.bbox.x_max);
+            let y1 = y0.saturating_add(self.step).min(w.bbox.y_max);
+            if x1 <= x0 || y1 <= y0 {
+                return None;
+            }
+            let percent = (w.density*100.).round() as i16;
+            Some(boundary(layer, percent, vec![(x0, y0), (x1, y0), (x1, y1),
+                (x0, y1), (x0, y0)]))
+        }).collect()
+    }
+}
+
+impl Library {
+    /// Computes the densities of the flattened structure `name`.
+    ///
+    /// The densities are computed for every layer and datatype of `layers`,
+    /// an empty slice selects all layers containing shapes. Overlapping
+    /// shapes are counted once, texts and nodes are ignored. The window size
+    /// and step of `rule` are converted into database units using
+    /// `units_m`. An empty vector is returned if the structure does not
+    /// contain any shapes.
+    pub fn density(&self, name: &str, layers: &[(i16,i16)],
+            rule: &DensityRule) -> Result<Vec<DensityMap>, Error> {
+        let (window, step) = rule.grid(self.units_m)?;
+        let flat = self.flatten(name)
+            .ok_or_else(|| Error::MissingStructure(name.to_string()))?;
+        let polys = layer_polygons(&flat);
+        let mut bbox: Option<BoundingBox> = None;
+        for p in polys.values().flat_map(|x| x.iter()) {
+            if let Some(b) = BoundingBox::from_points(p) {
+                bbox = Some(bbox.map_or(b, |x| x.union(&b)));
+            }
+        }
+        let bbox = match bbox {
+            Some(x) => x,
+            None => return Ok(Vec::new())
+        };
+        let keys: Vec<(i16,i16)> = if layers.is_empty() {
+            polys.keys().cloned().collect()
+        } else {
+            layers.to_vec()
+        };
+        let empty = Vec::new();
+        Ok(keys.into_iter().map(|key| {
+            let traps = boolean::union(polys.get(&key).unwrap_or(&empty));
+            let mut map = density_map(&bbox, window, step, &traps);
+            map.layer = key.0;
+            map.datatype = key.1;
+            map
+        }).collect())
+    }
+}
+
+/// Returns the number of windows of `size` moved by `step` needed to cover
+/// `length`.
+fn window_count(length: i64, size: i32, step: i32) -> usize {
+    if length <= size as i64 {
+        1
+    } else {
+        ((length - size as i64 + step as i64 - 1)/step as i64) as usize + 1
+    }
+}
+
+/// Computes the densities of the trapezoids in windows covering `bbox`.
+pub(crate) fn density_map(bbox: &BoundingBox, window: i32, step: i32,
+        traps: &[Trapezoid]) -> DensityMap {
+    let columns = window_count(bbox.width(), window, step);
+    let rows = window_count(bbox.height(), window, step);
+    let mut map = DensityMap{layer: 0, datatype: 0, columns, rows, window,
+        step, windows: Vec::with_capacity(columns*rows)};
+    for row in 0..rows {
+        for col in 0..columns {
+            let x0 = bbox.x_min as i64 + (col as i64)*step as i64;
+            let y0 = bbox.y_min as i64 + (row as i64)*step as i64;
+            let x1 = (x0 + window as i64).min(bbox.x_max as i64);
+            let y1 = (y0 + window as i64).min(bbox.y_max as i64);
+            map.windows.push(DensityWindow{col, row,
+                bbox: BoundingBox{x_min: x0 as i32, y_min: y0 as i32,
+                    x_max: x1 as i32, y_max: y1 as i32},
+                density: 0.});
+        }
+    }
+    for t in traps {
+        let x_min = t.x_bottom_left.min(t.x_top_left);
+        let x_max = t.x_bottom_right.max(t.x_top_right);
+        for i in map.windows_in(x_min, t.y_bottom, x_max, t.y_top) {
+            let w = &mut map.windows[i];
+            w.density += clipped_area(t, &w.bbox);
+        }
+    }
+    for w in &mut map.windows {
+        let area = w.bbox.width() as f64*w.bbox.height() as f64;
+        w.density = if area > 0. { (w.density/area).min(1.) } else { 0. };
+    }
+    map
+}
+
+/// Returns the area of the part of the trapezoid inside `bbox`.
+pub(crate) fn clipped_area(t: &Trapezoid, bbox: &BoundingBox) -> f64 {
+    let (x0, x1) = (bbox.x_min as f64, bbox.x_max as f64);
+    let ya = t.y_bottom.max(bbox.y_min as f64);
+    let yb = t.y_top.min(bbox.y_max as f64);
+    let h = t.y_top - t.y_bottom;
+    if yb <= ya || h <= 0. {
+        return 0.;
+    }
+    let edge = |bottom: f64, top: f64, y: f64|
+        bottom + (top - bottom)*(y - t.y_bottom)/h;
+    // the covered width is linear between the points where an edge of the
+    // trapezoid crosses a border of the window
+    let mut ys = vec![ya, yb];
+    for &(bottom, top) in &[(t.x_bottom_left, t.x_top_left),
+            (t.x_bottom_right, t.x_top_right)] {
+        if bottom == top {
+            continue;
+        }
+        for &x in &[x0, x1] {
+            let y = t.y_bottom + (x - bottom)*h/(top - bottom);
+            if y > ya && y < yb {
+                ys.push(y);
+            }
+        }
+    }
+    ys.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let width = |y: f64| (edge(t.x_bottom_right, t.x_top_right, y).min(x1) -
+        edge(t.x_bottom_left, t.x_top_left, y).max(x0)).max(0.);
+    ys.windows(2).map(|s| (width(s[0]) + width(s[1]))/2.*(s[1] - s[0]))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use boolean::Operation;
+    use testing::{library, rect};
+
+    #[test]
+    fn clipped_areas() {
+        let polys = vec![vec![(0, 0), (100, 0), (70, 60), (10, 60), (0, 0)],
+            vec![(0, 0), (80, 10), (20, 90), (0, 0)]];
+        for poly in &polys {
+            let traps = boolean::union(std::slice::from_ref(poly));
+            for &(x0, y0, x1, y1) in &[(0, 0, 100, 100), (20, 10, 50, 40),
+                    (-10, 30, 15, 70), (60, -5, 120, 35), (200, 0, 300, 10)] {
+                let bbox = BoundingBox{x_min: x0, y_min: y0, x_max: x1,
+                    y_max: y1};
+                let area: f64 = traps.iter()
+                    .map(|t| clipped_area(t, &bbox)).sum();
+                let window = vec![(x0, y0), (x1, y0), (x1, y1), (x0, y1),
+                    (x0, y0)];
+                let expected = boolean::area(&boolean::boolean(
+                    std::slice::from_ref(poly), &[window],
+                    Operation::Intersection));
+                assert!((area - expected).abs() < 1e-6, "{} {}", area,
+                    expected);
+            }
+        }
+    }
+
+    #[test]
+    fn tiled_and_sliding_windows() {
+        let lib = library(vec![("TOP", vec![rect(1, 0, 0, 250, 100),
+            rect(2, 0, 0, 50, 100), rect(2, 25, 0, 75, 50)])]);
+        let rule = DensityRule::new(100e-9, 0.2, 0.8);
+        let maps = lib.density("TOP", &[(2, 0), (3, 0)], &rule).unwrap();
+        assert_eq!(maps.len(), 2);
+        let map = &maps[0];
+        assert_eq!((map.layer, map.columns, map.rows), (2, 3, 1));
+        // overlapping shapes are counted once, the last window is clipped
+        assert_eq!(map.get(0, 0), Some(0.625));
+        assert_eq!(map.get(1, 0), Some(0.));
+        assert_eq!(map.windows[2].bbox.x_max - map.windows[2].bbox.x_min, 50);
+        assert_eq!(map.get(3, 0), None);
+        assert_eq!(map.violations(&rule).len(), 2);
+        assert_eq!(maps[1].max(), Some(0.));
+
+        let rule = DensityRule::sliding(100e-9, 50e-9, 0., 1.);
+        let maps = lib.density("TOP", &[], &rule).unwrap();
+        let map = maps.iter().find(|m| m.layer == 2).unwrap();
+        assert_eq!(map.columns, 4);
+        let densities: Vec<f64> = map.windows.iter().map(|w| w.density)
+            .collect();
+        assert_eq!(densities, vec![0.625, 0.125, 0., 0.]);
+        assert_eq!(maps.iter().find(|m| m.layer == 1).unwrap().min(),
+            Some(1.));
+        let heat = map.heat_map(100);
+        assert_eq!(heat.len(), 4);
+        assert_eq!(heat[0].xy().unwrap(),
+            &[(25, 25), (75, 25), (75, 75), (25, 75), (25, 25)]);
+        assert_eq!(heat[0].datatype(), Some(63));
+    }
+
+    #[test]
+    fn invalid_rules() {
+        let lib = library(vec![("TOP", vec![rect(1, 0, 0, 10, 10)])]);
+        let density = |window: f64, step: f64| lib.density("TOP", &[],
+            &DensityRule::sliding(window, step, 0., 1.));
+        assert!(density(1e-9, 1e-9).is_ok());
+        assert!(density(0.1e-9, 0.1e-9).is_err());
+        assert!(density(10e-9, 20e-9).is_err());
+        assert!(density(10e-9, 0.).is_err());
+        assert!(lib.density("MISSING", &[], &DensityRule::new(1e-9, 0., 1.))
+            .is_err());
+        assert!(library(vec![("TOP", vec![])]).density("TOP", &[],
+            &DensityRule::new(1e-9, 0., 1.)).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,361 @@
+//! Contains the comparison of two libraries.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use {Element, ElementParameter, ElementType, Error, Library, Structure};
+use boolean::{self, Operation};
+
+/// Differences between two libraries.
+#[derive(Debug,Clone)]
+pub struct LibraryDiff {
+    /// Names of structures only contained in the second library.
+    pub added: Vec<String>,
+    /// Names of structures only contained in the first library.
+    pub removed: Vec<String>,
+    /// Structures with identical content but different names given as tuple
+    /// of old and new name.
+    pub renamed: Vec<(String, String)>,
+    /// Structures contained in both libraries with different content.
+    pub changed: Vec<StructureDiff>
+}
+
+/// Differences between two versions of a structure.
+#[derive(Debug,Clone)]
+pub struct StructureDiff {
+    /// Name of the structure.
+    pub name: String,
+    /// References only contained in the new version.
+    pub references_added: Vec<Element>,
+    /// References only contained in the old version.
+    pub references_removed: Vec<Element>,
+    /// Differences of the other elements sorted by layer and datatype.
+    pub layers: Vec<LayerDiff>
+}
+
+/// Differences of the elements on a single layer.
+#[derive(Debug,Clone)]
+pub struct LayerDiff {
+    /// Layer of the elements.
+    pub layer: i16,
+    /// Datatype of the elements.
+    pub datatype: i16,
+    /// Elements only contained in the new version.
+    pub added: Vec<Element>,
+    /// Elements only contained in the old version.
+    pub removed: Vec<Element>
+}
+
+/// Result of the comparison of flattened geometry on a single layer.
+#[derive(Debug,Clone)]
+pub struct LayerXor {
+    /// Layer of the geometry.
+    pub layer: i16,
+    /// Datatype of the geometry.
+    pub datatype: i16,
+    /// Area covered by only one of the versions in square database units.
+    pub area: f64,
+    /// Closed polygons describing the differing area.
+    pub polygons: Vec<Vec<(i32,i32)>>
+}
+
+impl LibraryDiff {
+    /// Returns true if no differences were found.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() &&
+            self.renamed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl Library {
+    /// Compares the structures of the library with those of `other`.
+    ///
+    /// Structures are matched by their name. The elements of matching
+    /// structures are compared independent of their order. Removed and added
+    /// structures with identical content are reported as renamed, references
+    /// to them are not reported as changes. Header information like dates is
+    /// not compared.
+    pub fn diff(&self, other: &Library) -> LibraryDiff {
+        let old = self.structure_index();
+        let new = other.structure_index();
+        let mut removed: Vec<&Structure> = self.structures.iter()
+            .filter(|s| !new.contains_key(s.name.as_str())).collect();
+        let mut added: Vec<&Structure> = other.structures.iter()
+            .filter(|s| !old.contains_key(s.name.as_str())).collect();
+
+        let mut renamed = Vec::new();
+        let mut added_keys: Vec<String> = added.iter()
+            .map(|a| content_key(&a.elements, &HashMap::new())).collect();
+        removed.retain(|r| {
+            let key = content_key(&r.elements, &HashMap::new());
+            match added_keys.iter().position(|a| *a == key) {
+                Some(i) => {
+                    added_keys.remove(i);
+                    let a = added.remove(i);
+                    renamed.push((r.name.clone(), a.name.clone()));
+                    false
+                },
+                None => true
+            }
+        });
+        let names: HashMap<&str, &str> = renamed.iter()
+            .map(|(a, b)| (a.as_str(), b.as_str())).collect();
+
+        let mut changed = Vec::new();
+        for stru in &self.structures {
+            if let Some(o) = new.get(stru.name.as_str()) {
+                if let Some(d) = diff_structure(stru, o, &names) {
+                    changed.push(d);
+                }
+            }
+        }
+        LibraryDiff{
+            added: added.iter().map(|s| s.name.clone()).collect(),
+            removed: removed.iter().map(|s| s.name.clone()).collect(),
+            renamed, changed}
+    }
+
+    /// Compares the flattened geometry of the structure `name`.
+    ///
+    /// The structure is flattened in both libraries and the area covered by
+    /// only one of them is computed for every layer. Only layers with
+    /// differences are returned. Texts and nodes are ignored.
+    pub fn xor(&self, other: &Library, name: &str)
+            -> Result<Vec<LayerXor>, Error> {
+        let missing = || Error::MissingStructure(name.to_string());
+        let a = layer_polygons(&self.flatten(name).ok_or_else(missing)?);
+        let b = layer_polygons(&other.flatten(name).ok_or_else(missing)?);
+        let mut keys: Vec<&(i16,i16)> = a.keys().chain(b.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        let empty = Vec::new();
+        let mut res = Vec::new();
+        for key in keys {
+            let traps = boolean::boolean(a.get(key).unwrap_or(&empty),
+                b.get(key).unwrap_or(&empty), Operation::Xor);
+            let area = boolean::area(&traps);
+            if area > 0. {
+                res.push(LayerXor{layer: key.0, datatype: key.1, area,
+                    polygons: traps.iter().map(|t| t.to_polygon())
+                        .filter(|p| p.len() > 3).collect()});
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// Polygons sorted by layer and datatype.
+pub(crate) type LayerPolygons = BTreeMap<(i16,i16), Vec<Vec<(i32,i32)>>>;
+
+pub(crate) fn layer_polygons(stru: &Structure) -> LayerPolygons {
+    let mut res = LayerPolygons::new();
+    for elem in &stru.elements {
+        let polys = elem.polygons();
+        if !polys.is_empty() {
+            let key = (elem.layer().unwrap_or(0), elem.datatype().unwrap_or(0));
+            res.entry(key).or_default().extend(polys);
+        }
+    }
+    res
+}
+
+/// Returns a string identifying the element independent of the parameter
+/// order.
+///
+/// Referenced structures are renamed according to `names`.
+fn element_key(elem: &Element, names: &HashMap<&str, &str>) -> String {
+    let mut params: Vec<String> = elem.parameters.iter().map(|p| match *p {
+        ElementParameter::StructureName(ref x) => match names.get(x.as_str()) {
+            Some(n) => format!("{:?}",
+                ElementParameter::StructureName(n.to_string())),
+            None => format!("{:?}", p)
+        },
+        _ => format!("{:?}", p)
+    }).collect();
+    params.sort();
+    format!("{:?} {}", elem.element_type, params.join(" "))
+}
+
+/// Returns a string identifying the content of a structure independent of
+/// the element order.
+///
+/// Referenced structures are renamed according to `names`.
+pub(crate) fn content_key(elements: &[Element], names: &HashMap<&str, &str>)
+        -> String {
+    let mut keys: Vec<String> = elements.iter()
+        .map(|e| element_key(e, names)).collect();
+    keys.sort();
+    keys.join("\n")
+}
+
+/// Returns the elements of `a` which are not in `b` and vice versa.
+///
+/// Both vectors are treated as multisets.
+fn compare<'a>(a: &[&'a Element], b: &[&'a Element],
+        names: &HashMap<&str, &str>) -> (Vec<Element>, Vec<Element>) {
+    let mut counts: HashMap<String, i64> = HashMap::new();
+    for e in a {
+        *counts.entry(element_key(e, names)).or_insert(0) += 1;
+    }
+    for e in b {
+        *counts.entry(element_key(e, &HashMap::new())).or_insert(0) -= 1;
+    }
+    let mut removed = Vec::new();
+    for e in a {
+        let c = counts.get_mut(&element_key(e, names)).unwrap();
+        if *c > 0 {
+            *c -= 1;
+            removed.push((*e).clone());
+        }
+    }
+    let mut added = Vec::new();
+    for e in b {
+        let c = counts.get_mut(&element_key(e, &HashMap::new())).unwrap();
+        if *c < 0 {
+            *c += 1;
+            added.push((*e).clone());
+        }
+    }
+    (removed, added)
+}
+
+fn diff_structure(old: &Structure, new: &Structure,
+        names: &HashMap<&str, &str>) -> Option<StructureDiff> {
+    let (references_removed, references_added) =
+        compare(&references(old), &references(new), names);
+
+    // old and new elements sorted by layer and datatype
+    let mut layers: BTreeMap<(i16,i16), [Vec<&Element>;2]> = BTreeMap::new();
+    for (i, s) in [old, new].iter().enumerate() {
+        for elem in s.elements.iter().filter(|e| !e.is_reference()) {
+            let key = (elem.layer().unwrap_or(0), elem.datatype().unwrap_or(0));
+            layers.entry(key).or_default()[i].push(elem);
+        }
+    }
+    let mut layer_diffs = Vec::new();
+    for (&(layer, datatype), elems) in &layers {
+        let (removed, added) = compare(&elems[0], &elems[1], &HashMap::new());
+        if !removed.is_empty() || !added.is_empty() {
+            layer_diffs.push(LayerDiff{layer, datatype, added, removed});
+        }
+    }
+
+    if references_added.is_empty() && references_removed.is_empty() &&
+            layer_diffs.is_empty() {
+        None
+    } else {
+        Some(StructureDiff{name: old.name.clone(), references_added,
+            references_removed, layers: layer_diffs})
+    }
+}
+
+fn references(s: &Structure) -> Vec<&Element> {
+    s.elements.iter().filter(|e| e.is_reference()).collect()
+}
+
+/// Returns a short description of an element.
+fn describe(elem: &Element) -> String {
+    let name = match elem.element_type {
+        ElementType::None => "element",
+        ElementType::Boundary => "BOUNDARY",
+        ElementType::Path => "PATH",
+        ElementType::StructureRef => "SREF",
+        ElementType::ArrayRef => "AREF",
+        ElementType::Text => "TEXT",
+        ElementType::Node => "NODE",
+        ElementType::Box => "BOX"
+    };
+    let mut res = String::from(name);
+    if let Some(n) = elem.structure_name() {
+        res += &format!(" {}", n);
+    }
+    match elem.xy() {
+        Some(xy) if !xy.is_empty() =>
+            res += &format!(" at ({}, {}) with {} points", xy[0].0, xy[0].1,
+                xy.len()),
+        _ => {}
+    }
+    res
+}
+
+impl fmt::Display for LibraryDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for name in &self.removed {
+            writeln!(f, "- structure {}", name)?;
+        }
+        for name in &self.added {
+            writeln!(f, "+ structure {}", name)?;
+        }
+        for (old, new) in &self.renamed {
+            writeln!(f, "~ structure {} renamed to {}", old, new)?;
+        }
+        for s in &self.changed {
+            writeln!(f, "~ structure {}", s.name)?;
+            for e in &s.references_removed {
+                writeln!(f, "    - {}", describe(e))?;
+            }
+            for e in &s.references_added {
+                writeln!(f, "    + {}", describe(e))?;
+            }
+            for l in &s.layers {
+                writeln!(f, "    layer {}/{}:", l.layer, l.datatype)?;
+                for e in &l.removed {
+                    writeln!(f, "        - {}", describe(e))?;
+                }
+                for e in &l.added {
+                    writeln!(f, "        + {}", describe(e))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use testing::{boundary, library, sref};
+
+    fn square(x: i32, size: i32) -> Vec<(i32,i32)> {
+        vec![(x, 0), (x + size, 0), (x + size, size), (x, size), (x, 0)]
+    }
+
+    #[test]
+    fn element_order_and_renames() {
+        let a = library(vec![
+            ("TOP", vec![boundary(1, square(0, 10)), sref("OLD", (0, 0)),
+                boundary(2, square(20, 5))]),
+            ("OLD", vec![boundary(3, square(0, 1))]),
+            ("GONE", vec![boundary(4, square(0, 2))])]);
+        let b = library(vec![
+            ("TOP", vec![boundary(2, square(20, 5)), sref("NEW", (0, 0)),
+                boundary(1, square(0, 10))]),
+            ("NEW", vec![boundary(3, square(0, 1))]),
+            ("ADDED", vec![boundary(4, square(0, 3))])]);
+        let d = a.diff(&b);
+        assert_eq!(d.renamed, vec![(String::from("OLD"), String::from("NEW"))]);
+        assert_eq!(d.removed, vec![String::from("GONE")]);
+        assert_eq!(d.added, vec![String::from("ADDED")]);
+        assert!(d.changed.is_empty());
+        assert!(a.diff(&a).is_empty());
+    }
+
+    #[test]
+    fn changed_layers() {
+        let a = library(vec![("TOP", vec![boundary(1, square(0, 10)),
+            boundary(2, square(0, 10))])]);
+        let b = library(vec![("TOP", vec![boundary(1, square(5, 10)),
+            boundary(2, square(0, 10))])]);
+        let d = a.diff(&b);
+        assert_eq!(d.changed.len(), 1);
+        let layers = &d.changed[0].layers;
+        assert_eq!(layers.len(), 1);
+        assert_eq!((layers[0].layer, layers[0].datatype), (1, 0));
+        assert_eq!((layers[0].added.len(), layers[0].removed.len()), (1, 1));
+
+        let xor = a.xor(&b, "TOP").unwrap();
+        assert_eq!(xor.len(), 1);
+        assert_eq!(xor[0].layer, 1);
+        assert_eq!(xor[0].area, 100.);
+        assert!(a.xor(&b, "MISSING").is_err());
+    }
+}
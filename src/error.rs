@@ -0,0 +1,60 @@
+//! Contains the error type of the gds crate.
+
+use std::error;
+use std::fmt;
+use std::io;
+
+/// Enumeration of errors which can occur while handling GDS files.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading from or writing to a file failed.
+    Io(io::Error),
+    /// The data does not describe a valid library.
+    Parse(String),
+    /// The requested file format is not supported.
+    UnsupportedFormat(String),
+    /// A structure with the given name does not exist.
+    MissingStructure(String),
+    /// A structure with the given name already exists.
+    DuplicateStructure(String),
+    /// A given value is not valid.
+    InvalidValue(String),
+    /// A value does not fit into the range of its data type.
+    Overflow(String)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "I/O error: {}", e),
+            Error::Parse(ref s) => write!(f, "parse error: {}", s),
+            Error::UnsupportedFormat(ref s) =>
+                write!(f, "unsupported format: {}", s),
+            Error::MissingStructure(ref s) =>
+                write!(f, "structure {} does not exist", s),
+            Error::DuplicateStructure(ref s) =>
+                write!(f, "structure {} already exists", s),
+            Error::InvalidValue(ref s) => write!(f, "invalid value: {}", s),
+            Error::Overflow(ref s) => write!(f, "overflow: {}", s)
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            Error::Parse(String::from("unexpected end of file"))
+        } else {
+            Error::Io(e)
+        }
+    }
+}
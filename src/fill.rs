@@ -0,0 +1,377 @@
+//! Contains the generation of dummy fill.
+//!
+//! Fill squares are placed on a regular grid starting at the lower left
+//! corner of the bounding box of the structure. Only windows below the
+//! minimum density of a [DensityRule](../density/struct.DensityRule.html) are
+//! filled, aiming at the middle of the allowed range. Squares are spread
+//! evenly over the free area of a window and are never placed if they would
+//! push a window above the maximum density.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use {Element, ElementParameter, ElementType, Error, Library, Structure};
+use boolean::{self, Trapezoid};
+use density::{clipped_area, density_map, DensityMap, DensityRule};
+use diff::layer_polygons;
+use generators::boundary;
+use geometry::BoundingBox;
+use index::RTree;
+
+/// Settings of the fill generation.
+#[derive(Debug,Clone,PartialEq)]
+pub struct FillOptions {
+    /// Layer to be filled.
+    pub layer: i16,
+    /// Datatype of the fill squares.
+    pub datatype: i16,
+    /// Side length of the fill squares in metres.
+    pub size: f64,
+    /// Distance between neighbouring fill squares in metres.
+    pub spacing: f64,
+    /// Minimum distance between fill squares and existing shapes on the
+    /// layer in metres.
+    pub keep_out: f64,
+    /// Name of a new structure containing a single fill square. If set, the
+    /// fill is placed as array references to this structure instead of
+    /// boundaries.
+    pub cell: Option<String>
+}
+
+/// Result of the fill generation.
+#[derive(Debug,Clone,PartialEq)]
+pub struct FillReport {
+    /// Number of inserted fill squares.
+    pub squares: usize,
+    /// Densities of the layer including the fill. The datatype of the map is
+    /// the fill datatype.
+    pub density: DensityMap
+}
+
+impl FillOptions {
+    /// Creates options placing squares of `size` metres with `spacing` and
+    /// `keep_out` as boundaries on `layer` and `datatype`.
+    pub fn new(layer: i16, datatype: i16, size: f64, spacing: f64,
+            keep_out: f64) -> FillOptions {
+        FillOptions{layer, datatype, size, spacing, keep_out, cell: None}
+    }
+}
+
+impl Library {
+    /// Adds fill squares to the structure `name` to reach the density range
+    /// of `rule`.
+    ///
+    /// The densities and the keep-out area are computed from all datatypes
+    /// of the fill layer in the flattened structure, the keep-out distance
+    /// is measured along the axes. The fill is added to the structure
+    /// itself. An error is returned if the structure does not exist, the
+    /// sizes are invalid or the fill cell already exists.
+    pub fn fill(&mut self, name: &str, rule: &DensityRule,
+            options: &FillOptions) -> Result<FillReport, Error> {
+        let (window, step) = rule.grid(self.units_m)?;
+        let to_db = |x: f64| (x/self.units_m).round();
+        let (size, spacing) = (to_db(options.size), to_db(options.spacing));
+        let keep_out = to_db(options.keep_out);
+        if !(size >= 1. && size <= window as f64) {
+            return Err(Error::InvalidValue(
+                format!("invalid fill size {}", options.size)));
+        }
+        if !(spacing >= 0. && keep_out >= 0. && keep_out < i32::MAX as f64 &&
+                size + spacing <= i32::MAX as f64) {
+            return Err(Error::InvalidValue(format!(
+                "invalid fill spacing {} or keep-out {}", options.spacing,
+                options.keep_out)));
+        }
+        let (size, keep_out) = (size as i32, keep_out as i32);
+        let pitch = size as i64 + spacing as i64;
+        if let Some(ref cell) = options.cell {
+            if self.structure(cell).is_some() {
+                return Err(Error::DuplicateStructure(cell.clone()));
+            }
+        }
+        let flat = self.flatten(name)
+            .ok_or_else(|| Error::MissingStructure(name.to_string()))?;
+
+        let polys = layer_polygons(&flat);
+        let mut bbox: Option<BoundingBox> = None;
+        for p in polys.values().flat_map(|x| x.iter()) {
+            if let Some(b) = BoundingBox::from_points(p) {
+                bbox = Some(bbox.map_or(b, |x| x.union(&b)));
+            }
+        }
+        let bbox = match bbox {
+            Some(x) => x,
+            None => return Ok(FillReport{squares: 0, density: DensityMap{
+                layer: options.layer, datatype: options.datatype, columns: 0,
+                rows: 0, window, step, windows: Vec::new()}})
+        };
+        let shapes: Vec<Vec<(i32,i32)>> = polys.iter()
+            .filter(|x| (x.0).0 == options.layer)
+            .flat_map(|x| x.1.iter().cloned()).collect();
+        let traps = boolean::union(&shapes);
+        let mut map = density_map(&bbox, window, step, &traps);
+        map.layer = options.layer;
+        map.datatype = options.datatype;
+        let obstacles = RTree::new(traps.iter()
+            .map(|t| (trapezoid_bbox(t), *t)).collect());
+
+        // indices of the chosen squares on the grid
+        let mut used: HashSet<(i64,i64)> = HashSet::new();
+        let mut squares: Vec<(i64,i64)> = Vec::new();
+        let square = |i: i64, j: i64| -> BoundingBox {
+            let x = bbox.x_min as i64 + i*pitch;
+            let y = bbox.y_min as i64 + j*pitch;
+            BoundingBox{x_min: x as i32, y_min: y as i32,
+                x_max: (x + size as i64) as i32,
+                y_max: (y + size as i64) as i32}
+        };
+        let target = (rule.min + rule.max)/2.;
+        for w in 0..map.windows.len() {
+            let win = map.windows[w];
+            if win.density >= rule.min {
+                continue;
+            }
+            // squares completely inside the window and inside the bounding
+            // box of the structure
+            let first = |min: i32, origin: i32|
+                ((min as i64 - origin as i64) + pitch - 1).div_euclid(pitch);
+            let last = |max: i32, origin: i32|
+                (max as i64 - origin as i64 - size as i64).div_euclid(pitch);
+            let mut free = Vec::new();
+            for j in first(win.bbox.y_min, bbox.y_min)..
+                    last(win.bbox.y_max, bbox.y_min) + 1 {
+                for i in first(win.bbox.x_min, bbox.x_min)..
+                        last(win.bbox.x_max, bbox.x_min) + 1 {
+                    if used.contains(&(i, j)) {
+                        continue;
+                    }
+                    let grown = square(i, j).grow(keep_out);
+                    if obstacles.window(&grown).iter()
+                            .all(|t| clipped_area(t, &grown) <= 0.) {
+                        free.push((i, j));
+                    }
+                }
+            }
+            let area = win.bbox.width() as f64*win.bbox.height() as f64;
+            let needed = ((target - win.density)*area/
+                (size as f64*size as f64)).ceil().max(0.) as usize;
+            let count = needed.min(free.len());
+            for k in 0..count {
+                let (i, j) = free[k*free.len()/count];
+                let b = square(i, j);
+                let affected = map.windows_in(b.x_min as f64, b.y_min as f64,
+                    b.x_max as f64, b.y_max as f64);
+                let added: Vec<(usize, f64)> = affected.iter().map(|&a| {
+                    let wb = map.windows[a].bbox;
+                    let overlap = (b.x_max.min(wb.x_max) as f64 -
+                        b.x_min.max(wb.x_min) as f64).max(0.)*
+                        (b.y_max.min(wb.y_max) as f64 -
+                        b.y_min.max(wb.y_min) as f64).max(0.);
+                    (a, overlap/(wb.width() as f64*wb.height() as f64))
+                }).collect();
+                if added.iter()
+                        .any(|&(a, d)| map.windows[a].density + d > rule.max) {
+                    continue;
+                }
+                for (a, d) in added {
+                    map.windows[a].density += d;
+                }
+                used.insert((i, j));
+                squares.push((i, j));
+            }
+        }
+
+        let elements: Vec<Element> = match options.cell {
+            None => squares.iter().map(|&(i, j)| {
+                let b = square(i, j);
+                let c = b.corners();
+                boundary(options.layer, options.datatype,
+                    vec![c[0], c[1], c[2], c[3], c[0]])
+            }).collect(),
+            Some(ref cell) => {
+                let mut stru = Structure::new();
+                stru.name = cell.clone();
+                stru.date_mod = flat.date_mod.clone();
+                stru.date_acc = flat.date_acc.clone();
+                stru.elements.push(boundary(options.layer, options.datatype,
+                    vec![(0, 0), (size, 0), (size, size), (0, size), (0, 0)]));
+                self.structures.push(stru);
+                let origin = |i: i64, j: i64|
+                    (square(i, j).x_min, square(i, j).y_min);
+                arrays(&squares).into_iter().map(|(i, j, cols, rows)|
+                    array(cell, origin(i, j), origin(i + cols, j),
+                        origin(i, j + rows), cols, rows)).collect()
+            }
+        };
+        if let Some(stru) = self.structures.iter_mut()
+                .find(|s| s.name == name) {
+            stru.elements.extend(elements);
+        }
+        Ok(FillReport{squares: squares.len(), density: map})
+    }
+}
+
+/// Returns the smallest bounding box with integer coordinates containing the
+/// trapezoid.
+fn trapezoid_bbox(t: &Trapezoid) -> BoundingBox {
+    BoundingBox{x_min: t.x_bottom_left.min(t.x_top_left).floor() as i32,
+        y_min: t.y_bottom.floor() as i32,
+        x_max: t.x_bottom_right.max(t.x_top_right).ceil() as i32,
+        y_max: t.y_top.ceil() as i32}
+}
+
+/// Groups grid positions into rectangular arrays given by the lower left
+/// position and the number of columns and rows.
+fn arrays(squares: &[(i64,i64)]) -> Vec<(i64, i64, i64, i64)> {
+    // consecutive runs in every row
+    let mut rows: BTreeMap<i64, Vec<i64>> = BTreeMap::new();
+    for &(i, j) in squares {
+        rows.entry(j).or_default().push(i);
+    }
+    let mut runs: BTreeSet<(i64, i64, i64)> = BTreeSet::new();
+    for (&j, cols) in &mut rows {
+        cols.sort();
+        let mut start = 0;
+        for k in 1..cols.len() + 1 {
+            if k == cols.len() || cols[k] != cols[k - 1] + 1 {
+                let (i, n) = (cols[start], (k - start) as i64);
+                // the number of columns and rows is limited to 32767
+                let mut c = 0;
+                while c < n {
+                    runs.insert((i + c, n.min(c + 32767) - c, j));
+                    c += 32767;
+                }
+                start = k;
+            }
+        }
+    }
+    // join identical runs of consecutive rows
+    let mut res = Vec::new();
+    let keys: Vec<(i64, i64, i64)> = runs.iter().cloned().collect();
+    for (i, n, j) in keys {
+        if !runs.remove(&(i, n, j)) {
+            continue;
+        }
+        let mut rows = 1;
+        while rows < 32767 && runs.remove(&(i, n, j + rows)) {
+            rows += 1;
+        }
+        res.push((i, j, n, rows));
+    }
+    res
+}
+
+/// Creates a reference to `cell` or an array if there are several columns or
+/// rows.
+fn array(cell: &str, origin: (i32,i32), col: (i32,i32), row: (i32,i32),
+        cols: i64, rows: i64) -> Element {
+    let mut elem = Element::new();
+    elem.parameters.push(ElementParameter::StructureName(cell.to_string()));
+    if cols == 1 && rows == 1 {
+        elem.element_type = ElementType::StructureRef;
+        elem.parameters.push(ElementParameter::XY(vec![origin]));
+    } else {
+        elem.element_type = ElementType::ArrayRef;
+        elem.parameters.push(ElementParameter::ColRow(
+            vec![cols as i16, rows as i16]));
+        elem.parameters.push(ElementParameter::XY(vec![origin, col, row]));
+    }
+    elem
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::{library, rect};
+
+    /// Returns a library with a top structure containing a region on layer
+    /// 1 and two blockages on layer 2.
+    fn layout() -> Library {
+        library(vec![("TOP", vec![rect(1, 0, 0, 2000, 1000),
+            rect(2, 0, 0, 300, 1000), rect(2, 1000, 0, 1900, 1000)])])
+    }
+
+    /// Returns the bounding boxes of the fill squares in the flattened top
+    /// structure.
+    fn fill_squares(lib: &Library) -> Vec<BoundingBox> {
+        let flat = lib.flatten("TOP").unwrap();
+        let mut res: Vec<BoundingBox> = flat.elements.iter()
+            .filter(|e| e.datatype() == Some(5))
+            .map(|e| e.bounding_box().unwrap()).collect();
+        res.sort_by_key(|b| (b.y_min, b.x_min));
+        res
+    }
+
+    #[test]
+    fn fill_windows() {
+        let rule = DensityRule::new(1000e-9, 0.4, 0.8);
+        let options = FillOptions::new(2, 5, 50e-9, 10e-9, 20e-9);
+        let mut lib = layout();
+        let report = lib.fill("TOP", &rule, &options).unwrap();
+        let squares = fill_squares(&lib);
+        assert_eq!(report.squares, squares.len());
+        // only the first window is filled, aiming at a density of 0.6
+        assert_eq!((report.density.columns, report.density.rows), (2, 1));
+        let density = report.density.get(0, 0).unwrap();
+        assert!((density - 0.6).abs() < 0.01, "{}", density);
+        assert_eq!(report.density.get(1, 0), Some(0.9));
+        assert!((density - (300000. + 2500.*squares.len() as f64)/1e6).abs() <
+            1e-9);
+        for b in &squares {
+            assert_eq!((b.width(), b.height()), (50, 50));
+            assert!(b.x_min >= 320 && b.x_max <= 980);
+            assert!(b.x_min % 60 == 0 && b.y_min % 60 == 0);
+        }
+    }
+
+    #[test]
+    fn fill_cell() {
+        let rule = DensityRule::new(1000e-9, 0.4, 0.8);
+        let mut options = FillOptions::new(2, 5, 50e-9, 50e-9, 20e-9);
+        let mut boundaries = layout();
+        boundaries.fill("TOP", &rule, &options).unwrap();
+        options.cell = Some(String::from("FILL"));
+        let mut cells = layout();
+        let report = cells.fill("TOP", &rule, &options).unwrap();
+        assert_eq!(fill_squares(&cells), fill_squares(&boundaries));
+        let refs = cells.structure("TOP").unwrap().elements.iter()
+            .filter(|e| matches!(e.element_type, ElementType::ArrayRef |
+                ElementType::StructureRef)).count();
+        assert!(refs < report.squares);
+        assert!(matches!(cells.fill("TOP", &rule, &options),
+            Err(Error::DuplicateStructure(_))));
+    }
+
+    #[test]
+    fn invalid_options() {
+        let rule = DensityRule::new(1000e-9, 0.4, 0.8);
+        let mut lib = layout();
+        let mut fill = |size: f64, spacing: f64, name: &str| lib.fill(name,
+            &rule, &FillOptions::new(2, 5, size, spacing, 0.));
+        assert!(fill(0., 50e-9, "TOP").is_err());
+        assert!(fill(2000e-9, 50e-9, "TOP").is_err());
+        assert!(fill(50e-9, -50e-9, "TOP").is_err());
+        assert!(fill(50e-9, 50e-9, "MISSING").is_err());
+    }
+
+    #[test]
+    fn grouped_arrays() {
+        let mut squares: Vec<(i64,i64)> = Vec::new();
+        for j in 0..4 {
+            for i in 0..5 {
+                if (i, j) != (2, 0) {
+                    squares.push((i, j));
+                }
+            }
+        }
+        squares.push((9, 9));
+        let groups = arrays(&squares);
+        let mut covered: Vec<(i64,i64)> = groups.iter()
+            .flat_map(|&(i, j, n, m)| (0..m)
+                .flat_map(move |r| (0..n).map(move |c| (i + c, j + r))))
+            .collect();
+        covered.sort_by_key(|&(i, j)| (j, i));
+        assert_eq!(covered, squares);
+        assert!(groups.len() < squares.len()/2);
+        assert!(groups.contains(&(0, 1, 5, 3)));
+    }
+}
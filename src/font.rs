@@ -0,0 +1,409 @@
+//! Contains the conversion of texts into polygons using a built-in stroke
+//! font.
+//!
+//! Text elements are not manufactured, this module creates boundaries which
+//! can be used as labels on mask layers. The glyphs are drawn on a grid of
+//! five by seven units with strokes one unit wide, `height` is the height of
+//! capital letters. Lowercase letters are drawn as capitals and unsupported
+//! characters as question marks. Lines are separated by `\n`.
+
+use {Element, ElementParameter, ElementType, Structure};
+use boolean;
+use geometry::Transform;
+use generators::boundary;
+
+/// Horizontal justification of a text.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Horizontal {
+    /// The anchor is at the left edge.
+    Left,
+    /// The anchor is in the centre.
+    Center,
+    /// The anchor is at the right edge.
+    Right
+}
+
+/// Vertical justification of a text.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Vertical {
+    /// The anchor is at the top of the first line.
+    Top,
+    /// The anchor is in the middle.
+    Middle,
+    /// The anchor is at the baseline of the last line.
+    Bottom
+}
+
+/// Position of a text relative to its anchor point.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Anchor {
+    /// The anchor point.
+    pub origin: (i32,i32),
+    /// Horizontal justification.
+    pub horizontal: Horizontal,
+    /// Vertical justification.
+    pub vertical: Vertical
+}
+
+impl Anchor {
+    /// Creates an anchor at `origin` with the given justification.
+    pub fn new(origin: (i32,i32), horizontal: Horizontal, vertical: Vertical)
+            -> Anchor {
+        Anchor{origin, horizontal, vertical}
+    }
+
+    /// Creates an anchor at `origin` with the justification of a
+    /// `Presentation` parameter.
+    ///
+    /// The two lowest bits select the horizontal justification (left,
+    /// centre, right) and the next two bits the vertical justification (top,
+    /// middle, bottom). Unused values are treated as left and top.
+    pub fn from_presentation(origin: (i32,i32), presentation: u16) -> Anchor {
+        let horizontal = match presentation & 3 {
+            1 => Horizontal::Center,
+            2 => Horizontal::Right,
+            _ => Horizontal::Left
+        };
+        let vertical = match (presentation >> 2) & 3 {
+            1 => Vertical::Middle,
+            2 => Vertical::Bottom,
+            _ => Vertical::Top
+        };
+        Anchor{origin, horizontal, vertical}
+    }
+
+    /// Returns the justification bits of a `Presentation` parameter.
+    pub fn presentation(&self) -> u16 {
+        let h = match self.horizontal {
+            Horizontal::Left => 0,
+            Horizontal::Center => 1,
+            Horizontal::Right => 2
+        };
+        let v = match self.vertical {
+            Vertical::Top => 0,
+            Vertical::Middle => 1,
+            Vertical::Bottom => 2
+        };
+        v << 2 | h
+    }
+}
+
+/// Returns boundaries on `layer` and `datatype` drawing `text` with capital
+/// letters of `height` database units placed relative to `anchor`.
+///
+/// The boundaries do not overlap.
+pub fn text_to_polygons(text: &str, height: f64, anchor: &Anchor, layer: i16,
+        datatype: i16) -> Vec<Element> {
+    let t = Transform::translation(anchor.origin.0 as f64,
+        anchor.origin.1 as f64);
+    render(text, height, anchor.horizontal, anchor.vertical, &t).into_iter()
+        .map(|xy| boundary(layer, datatype, xy)).collect()
+}
+
+impl Element {
+    /// Returns boundaries drawing the string of a text element.
+    ///
+    /// The boundaries are placed on the layer of the text with the texttype
+    /// as datatype. `height` is the height of capital letters before the
+    /// magnification of the text is applied. The justification of the
+    /// Presentation parameter, the reflection, the magnification and the
+    /// angle are taken into account. Other elements result in an empty
+    /// vector.
+    pub fn text_to_polygons(&self, height: f64) -> Vec<Element> {
+        if !matches!(self.element_type, ElementType::Text) {
+            return Vec::new();
+        }
+        let (mut layer, mut texttype, mut presentation) = (0, 0, 0);
+        let (mut reflect, mut mag, mut angle) = (false, 1., 0.);
+        let mut string = "";
+        for param in &self.parameters {
+            match *param {
+                ElementParameter::Layer(x) => layer = x,
+                ElementParameter::TextType(x) => texttype = x,
+                ElementParameter::Presentation(x) => presentation = x,
+                ElementParameter::StrTransf(x) => reflect = x & 0x8000 != 0,
+                ElementParameter::Magnification(x) => mag = x,
+                ElementParameter::Angle(x) => angle = x,
+                ElementParameter::String(ref x) => string = x,
+                _ => {}
+            }
+        }
+        let origin = match self.xy() {
+            Some(xy) if !xy.is_empty() => xy[0],
+            _ => return Vec::new()
+        };
+        let anchor = Anchor::from_presentation(origin, presentation);
+        let t = Transform::new(reflect, mag, angle,
+            (origin.0 as f64, origin.1 as f64));
+        render(string, height, anchor.horizontal, anchor.vertical, &t)
+            .into_iter().map(|xy| boundary(layer, texttype, xy)).collect()
+    }
+}
+
+impl Structure {
+    /// Replaces all text elements by boundaries drawing their strings.
+    ///
+    /// The boundaries are inserted at the position of the text they replace.
+    /// See `Element::text_to_polygons` for details. Returns the number of
+    /// converted texts.
+    pub fn texts_to_polygons(&mut self, height: f64) -> usize {
+        let mut count = 0;
+        let mut elements = Vec::with_capacity(self.elements.len());
+        for elem in self.elements.drain(..) {
+            if let ElementType::Text = elem.element_type {
+                elements.extend(elem.text_to_polygons(height));
+                count += 1;
+            } else {
+                elements.push(elem);
+            }
+        }
+        self.elements = elements;
+        count
+    }
+}
+
+/// Width of a glyph in grid units.
+const GLYPH_WIDTH: f64 = 5.;
+/// Height of a glyph in grid units.
+const GLYPH_HEIGHT: f64 = 7.;
+/// Horizontal distance of two glyphs in grid units.
+const ADVANCE: f64 = 6.;
+/// Vertical distance of two lines in grid units.
+const LINE_PITCH: f64 = 10.;
+
+/// Returns the non-overlapping polygons of `text` transformed by `t`.
+fn render(text: &str, height: f64, horizontal: Horizontal,
+        vertical: Vertical, t: &Transform) -> Vec<Vec<(i32,i32)>> {
+    let unit = height/GLYPH_HEIGHT;
+    if unit <= 0. || !unit.is_finite() {
+        return Vec::new();
+    }
+    let lines: Vec<&str> = text.lines().collect();
+    let block = (lines.len().max(1) - 1) as f64*LINE_PITCH + GLYPH_HEIGHT;
+    let top = match vertical {
+        Vertical::Top => 0.,
+        Vertical::Middle => block/2.,
+        Vertical::Bottom => block
+    };
+    let mut polys = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let n = line.chars().count();
+        if n == 0 {
+            continue;
+        }
+        let width = (n - 1) as f64*ADVANCE + GLYPH_WIDTH;
+        let left = match horizontal {
+            Horizontal::Left => 0.,
+            Horizontal::Center => -width/2.,
+            Horizontal::Right => -width
+        };
+        let bottom = top - GLYPH_HEIGHT - i as f64*LINE_PITCH;
+        for (j, c) in line.chars().enumerate() {
+            // the strokes are centred on the grid lines
+            let x0 = left + j as f64*ADVANCE + 0.5;
+            let y0 = bottom + 0.5;
+            for stroke in glyph(c).split(' ').filter(|s| !s.is_empty()) {
+                let pts: Vec<(f64,f64)> = stroke.as_bytes().chunks(2)
+                    .map(|p| (x0 + (p[0] - b'0') as f64,
+                        y0 + (p[1] - b'0') as f64))
+                    .collect();
+                for p in &pts {
+                    polys.push(square(*p, unit, t));
+                }
+                for s in pts.windows(2) {
+                    if s[0] != s[1] {
+                        polys.push(segment(s[0], s[1], unit, t));
+                    }
+                }
+            }
+        }
+    }
+    boolean::union(&polys).iter().map(|x| x.to_polygon())
+        .filter(|x| x.len() > 3).collect()
+}
+
+/// Returns a square of one unit around the grid point `p`.
+///
+/// The squares cover the joints between the segments of a stroke.
+fn square(p: (f64,f64), unit: f64, t: &Transform) -> Vec<(i32,i32)> {
+    [(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)].iter()
+        .map(|&(dx, dy)| point((p.0 + dx, p.1 + dy), unit, t)).collect()
+}
+
+/// Returns a rectangle of one unit width along the segment from `a` to `b`.
+fn segment(a: (f64,f64), b: (f64,f64), unit: f64, t: &Transform)
+        -> Vec<(i32,i32)> {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let l = (dx*dx + dy*dy).sqrt();
+    let (nx, ny) = (-dy/l/2., dx/l/2.);
+    vec![point((a.0 - nx, a.1 - ny), unit, t),
+        point((b.0 - nx, b.1 - ny), unit, t),
+        point((b.0 + nx, b.1 + ny), unit, t),
+        point((a.0 + nx, a.1 + ny), unit, t)]
+}
+
+/// Scales a point in grid units, transforms and rounds it.
+fn point(p: (f64,f64), unit: f64, t: &Transform) -> (i32,i32) {
+    let (x, y) = t.apply((p.0*unit, p.1*unit));
+    (x.round() as i32, y.round() as i32)
+}
+
+/// Returns the strokes of a character.
+///
+/// Strokes are separated by spaces and consist of points on a grid from 0 to
+/// 4 in x- and 0 to 6 in y-direction, every point is given by two digits.
+fn glyph(c: char) -> &'static str {
+    match c.to_ascii_uppercase() {
+        ' ' => "",
+        '0' => "0040460600 0046",
+        '1' => "142620 1030",
+        '2' => "064643030040",
+        '3' => "06464000 1343",
+        '4' => "060343 4640",
+        '5' => "460603434000",
+        '6' => "460600404303",
+        '7' => "064610",
+        '8' => "0040460600 0343",
+        '9' => "004046060343",
+        'A' => "0004264440 0343",
+        'B' => "00063645443303 3342413000",
+        'C' => "46060040",
+        'D' => "00062644422000",
+        'E' => "46060040 0333",
+        'F' => "460600 0333",
+        'G' => "460600404323",
+        'H' => "0006 4640 0343",
+        'I' => "1636 2620 1030",
+        'J' => "46400002",
+        'K' => "0006 4602 1340",
+        'L' => "060040",
+        'M' => "0006234640",
+        'N' => "00064046",
+        'O' => "0040460600",
+        'P' => "0006464303",
+        'Q' => "0040460600 2240",
+        'R' => "0006464303 2340",
+        'S' => "460603434000",
+        'T' => "0646 2620",
+        'U' => "06004046",
+        'V' => "062046",
+        'W' => "0600234046",
+        'X' => "0046 0640",
+        'Y' => "062346 2320",
+        'Z' => "06460040",
+        '!' => "2623 2020",
+        '"' => "1615 3635",
+        '#' => "1016 3036 0242 0444",
+        '$' => "460603434000 2026",
+        '\'' => "2625",
+        '(' => "36141230",
+        ')' => "16343210",
+        '*' => "1533 1335 0343",
+        '+' => "0343 2125",
+        ',' => "2110",
+        '-' => "0343",
+        '.' => "2020",
+        '/' => "0046",
+        ':' => "2121 2424",
+        ';' => "2424 2110",
+        '<' => "450341",
+        '=' => "0242 0444",
+        '>' => "054301",
+        '[' => "36161030",
+        '\\' => "0640",
+        ']' => "16363010",
+        '^' => "142634",
+        '_' => "0040",
+        '|' => "2026",
+        _ => "05163645442322 2020"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geometry::BoundingBox;
+    use testing::element;
+
+    fn points(elements: &[Element]) -> Vec<Vec<(i32,i32)>> {
+        elements.iter().map(|e| e.xy().unwrap().to_vec()).collect()
+    }
+
+    fn bbox(elements: &[Element]) -> BoundingBox {
+        elements.iter().map(|e| e.bounding_box().unwrap())
+            .fold(None, |b: Option<BoundingBox>, x|
+                Some(b.map_or(x, |b| b.union(&x)))).unwrap()
+    }
+
+    #[test]
+    fn presentation() {
+        for p in 0..16 {
+            let a = Anchor::from_presentation((1, 2), p);
+            if p & 3 != 3 && p >> 2 != 3 {
+                assert_eq!(a.presentation(), p);
+            }
+        }
+        assert_eq!(Anchor::from_presentation((0, 0), 0b1001),
+            Anchor::new((0, 0), Horizontal::Center, Vertical::Bottom));
+        assert_eq!(Anchor::from_presentation((0, 0), 0xfff3).presentation(),
+            0);
+    }
+
+    #[test]
+    fn glyphs() {
+        let anchor = Anchor::new((0, 0), Horizontal::Left, Vertical::Bottom);
+        let h = text_to_polygons("H", 70., &anchor, 3, 4);
+        assert!(h.iter().all(|e| e.layer() == Some(3) &&
+            e.datatype() == Some(4)));
+        assert_eq!(bbox(&h),
+            BoundingBox{x_min: 0, y_min: 0, x_max: 50, y_max: 70});
+        // two vertical strokes and the bar between them without overlaps
+        let polys = points(&h);
+        let sum: f64 = polys.iter().map(|p| boolean::area(
+            &boolean::union(std::slice::from_ref(p)))).sum();
+        assert_eq!(sum, 1700.);
+        assert_eq!(boolean::area(&boolean::union(&polys)), 1700.);
+        assert_eq!(points(&text_to_polygons("h", 70., &anchor, 3, 4)), polys);
+        assert_eq!(points(&text_to_polygons("~", 70., &anchor, 0, 0)),
+            points(&text_to_polygons("?", 70., &anchor, 0, 0)));
+        assert!(text_to_polygons(" ", 70., &anchor, 0, 0).is_empty());
+        assert!(text_to_polygons("H", 0., &anchor, 0, 0).is_empty());
+    }
+
+    #[test]
+    fn justification() {
+        let b = |height: f64, h: Horizontal, v: Vertical| bbox(
+            &text_to_polygons("AB\nC", height,
+                &Anchor::new((100, 100), h, v), 0, 0));
+        // two glyphs of 5 units with one unit between them, two lines with a
+        // pitch of 10 units
+        assert_eq!(b(7., Horizontal::Left, Vertical::Top),
+            BoundingBox{x_min: 100, y_min: 83, x_max: 111, y_max: 100});
+        assert_eq!(b(7., Horizontal::Right, Vertical::Bottom),
+            BoundingBox{x_min: 89, y_min: 100, x_max: 100, y_max: 117});
+        assert_eq!(b(14., Horizontal::Center, Vertical::Middle),
+            BoundingBox{x_min: 89, y_min: 83, x_max: 111, y_max: 117});
+    }
+
+    #[test]
+    fn text_elements() {
+        let text = element(ElementType::Text, vec![
+            ElementParameter::Layer(5), ElementParameter::TextType(2),
+            ElementParameter::Presentation(0b1000),
+            ElementParameter::StrTransf(0),
+            ElementParameter::Magnification(2.),
+            ElementParameter::Angle(90.), ElementParameter::XY(vec![(10, 0)]),
+            ElementParameter::String(String::from("H"))]);
+        let polys = text.text_to_polygons(70.);
+        assert!(polys.iter().all(|e| e.layer() == Some(5) &&
+            e.datatype() == Some(2)));
+        assert_eq!(bbox(&polys),
+            BoundingBox{x_min: -130, y_min: 0, x_max: 10, y_max: 100});
+        let mut stru = Structure::new();
+        stru.elements = vec![text, polys[0].clone()];
+        assert_eq!(stru.texts_to_polygons(70.), 1);
+        assert_eq!(stru.elements.len(), polys.len() + 1);
+        assert!(stru.elements.iter().all(|e|
+            matches!(e.element_type, ElementType::Boundary)));
+    }
+}
@@ -0,0 +1,282 @@
+//! Contains the decomposition of boundaries into simple figures.
+//!
+//! Mask writers and some simulators only accept rectangles and trapezoids.
+//! The boundaries are decomposed with the scanline algorithm of the
+//! [boolean](../boolean/index.html) module into non-overlapping trapezoids
+//! with horizontal top and bottom edges, which are rectangles for Manhattan
+//! input. Holes connected to the outline by cut-lines are left out.
+
+use {Element, ElementType, Library, Structure};
+use boolean::{self, Trapezoid};
+
+/// Settings of the fracturing.
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub struct FractureOptions {
+    /// Maximum width of a figure in database units.
+    pub max_width: Option<i32>,
+    /// Maximum height of a figure in database units.
+    pub max_height: Option<i32>
+}
+
+impl FractureOptions {
+    /// Creates options limiting width and height of the figures to
+    /// `max_size`.
+    pub fn max_size(max_size: i32) -> FractureOptions {
+        FractureOptions{max_width: Some(max_size), max_height: Some(max_size)}
+    }
+}
+
+/// Decomposes polygons into non-overlapping trapezoids.
+///
+/// Overlapping polygons are merged. Trapezoids exceeding the maximum size
+/// are cut into equal pieces. Cuts through slanted edges create
+/// intersections which are rounded to database units.
+pub fn fracture(polys: &[Vec<(i32,i32)>], options: &FractureOptions)
+        -> Vec<Trapezoid> {
+    let mut res = Vec::new();
+    for t in boolean::union(polys) {
+        for band in split_height(&t, options.max_height) {
+            res.extend(split_width(&band, options.max_width));
+        }
+    }
+    res
+}
+
+/// Cuts the trapezoid horizontally into pieces of at most `max` height.
+fn split_height(t: &Trapezoid, max: Option<i32>) -> Vec<Trapezoid> {
+    let ys = match max {
+        Some(m) if m > 0 && t.y_top - t.y_bottom > m as f64 =>
+            cuts(t.y_bottom, t.y_top, m),
+        _ => return vec![*t]
+    };
+    ys.windows(2).map(|y| band(t, y[0], y[1])).collect()
+}
+
+/// Cuts the trapezoid vertically into pieces of at most `max` width.
+fn split_width(t: &Trapezoid, max: Option<i32>) -> Vec<Trapezoid> {
+    let x_min = t.x_bottom_left.min(t.x_top_left);
+    let x_max = t.x_bottom_right.max(t.x_top_right);
+    let xs = match max {
+        Some(m) if m > 0 && x_max - x_min > m as f64 => cuts(x_min, x_max, m),
+        _ => return vec![*t]
+    };
+    // split where a slanted edge crosses a cut, so every cut lies completely
+    // inside or outside of each band
+    let mut ys = vec![t.y_bottom, t.y_top];
+    for &(bottom, top) in &[(t.x_bottom_left, t.x_top_left),
+            (t.x_bottom_right, t.x_top_right)] {
+        if bottom == top {
+            continue;
+        }
+        for &x in &xs[1..xs.len() - 1] {
+            let y = t.y_bottom + (x - bottom)*(t.y_top - t.y_bottom)/
+                (top - bottom);
+            if y > t.y_bottom && y < t.y_top {
+                ys.push(y.round());
+            }
+        }
+    }
+    ys.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    ys.dedup();
+    let mut res = Vec::new();
+    for y in ys.windows(2) {
+        if y[1] <= y[0] {
+            continue;
+        }
+        let b = band(t, y[0], y[1]);
+        for x in xs.windows(2) {
+            let piece = Trapezoid{y_bottom: b.y_bottom, y_top: b.y_top,
+                x_bottom_left: b.x_bottom_left.max(x[0]),
+                x_bottom_right: b.x_bottom_right.min(x[1]),
+                x_top_left: b.x_top_left.max(x[0]),
+                x_top_right: b.x_top_right.min(x[1])};
+            if piece.x_bottom_right > piece.x_bottom_left ||
+                    piece.x_top_right > piece.x_top_left {
+                res.push(Trapezoid{
+                    x_bottom_right: piece.x_bottom_right
+                        .max(piece.x_bottom_left),
+                    x_top_right: piece.x_top_right.max(piece.x_top_left),
+                    ..piece});
+            }
+        }
+    }
+    res
+}
+
+/// Returns evenly spaced positions from `min` to `max` with a distance of at
+/// most `max_size`.
+///
+/// The inner positions are rounded to database units.
+fn cuts(min: f64, max: f64, max_size: i32) -> Vec<f64> {
+    let length = max - min;
+    let mut n = (length/max_size as f64).ceil() as usize;
+    loop {
+        let mut res: Vec<f64> = (1..n)
+            .map(|k| (min + length*k as f64/n as f64).round())
+            .filter(|&x| x > min && x < max).collect();
+        res.insert(0, min);
+        res.push(max);
+        res.dedup();
+        // rounding may enlarge a piece beyond the maximum size
+        if res.windows(2).all(|x| x[1] - x[0] <= max_size as f64) ||
+                n as f64 > length {
+            return res;
+        }
+        n += 1;
+    }
+}
+
+/// Returns the part of the trapezoid between `y0` and `y1`.
+fn band(t: &Trapezoid, y0: f64, y1: f64) -> Trapezoid {
+    let h = t.y_top - t.y_bottom;
+    let x = |bottom: f64, top: f64, y: f64| if h > 0. {
+        bottom + (top - bottom)*(y - t.y_bottom)/h
+    } else {
+        bottom
+    };
+    Trapezoid{y_bottom: y0, y_top: y1,
+        x_bottom_left: x(t.x_bottom_left, t.x_top_left, y0),
+        x_bottom_right: x(t.x_bottom_right, t.x_top_right, y0),
+        x_top_left: x(t.x_bottom_left, t.x_top_left, y1),
+        x_top_right: x(t.x_bottom_right, t.x_top_right, y1)}
+}
+
+impl Element {
+    /// Decomposes a boundary into boundaries of rectangles and trapezoids.
+    ///
+    /// The figures keep all parameters of the boundary except the points.
+    /// Other elements are returned unchanged.
+    pub fn fracture(&self, options: &FractureOptions) -> Vec<Element> {
+        let xy = match (&self.element_type, self.xy()) {
+            (&ElementType::Boundary, Some(xy)) => xy,
+            _ => return vec![self.clone()]
+        };
+        fracture(&[xy.to_vec()], options).iter()
+            .map(|t| t.to_polygon()).filter(|p| p.len() > 3)
+            .map(|p| self.with_xy(p)).collect()
+    }
+}
+
+impl Structure {
+    /// Replaces all boundaries by rectangles and trapezoids.
+    ///
+    /// The figures are inserted at the position of the boundary they
+    /// replace. Returns the number of figures.
+    pub fn fracture(&mut self, options: &FractureOptions) -> usize {
+        let mut count = 0;
+        let mut elements = Vec::with_capacity(self.elements.len());
+        for elem in self.elements.drain(..) {
+            if let ElementType::Boundary = elem.element_type {
+                let figures = elem.fracture(options);
+                count += figures.len();
+                elements.extend(figures);
+            } else {
+                elements.push(elem);
+            }
+        }
+        self.elements = elements;
+        count
+    }
+}
+
+impl Library {
+    /// Replaces all boundaries by rectangles and trapezoids.
+    ///
+    /// Returns the number of figures.
+    pub fn fracture(&mut self, options: &FractureOptions) -> usize {
+        self.structures.iter_mut().map(|s| s.fracture(options)).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ElementParameter;
+    use testing::element;
+
+    fn area(polys: &[Vec<(i32,i32)>]) -> f64 {
+        boolean::area(&boolean::union(polys))
+    }
+
+    /// Checks that the figures cover the polygon without overlaps up to
+    /// `error` square database units and returns them.
+    fn check(poly: Vec<(i32,i32)>, options: &FractureOptions, error: f64)
+            -> Vec<Vec<(i32,i32)>> {
+        let figures: Vec<Vec<(i32,i32)>> =
+            fracture(std::slice::from_ref(&poly), options).iter()
+            .map(|t| t.to_polygon()).filter(|p| p.len() > 3).collect();
+        let sum: f64 = figures.iter()
+            .map(|f| area(std::slice::from_ref(f))).sum();
+        assert!((sum - area(&figures)).abs() <= error);
+        assert!((sum - area(std::slice::from_ref(&poly))).abs() <= error);
+        assert!(boolean::area(&boolean::boolean(&[poly], &figures,
+            boolean::Operation::Xor)) <= error);
+        for f in &figures {
+            assert!(f.len() <= 5);
+            let (x0, x1) = (f.iter().map(|p| p.0).min().unwrap(),
+                f.iter().map(|p| p.0).max().unwrap());
+            let (y0, y1) = (f.iter().map(|p| p.1).min().unwrap(),
+                f.iter().map(|p| p.1).max().unwrap());
+            assert!(x1 - x0 <= options.max_width.unwrap_or(x1 - x0));
+            assert!(y1 - y0 <= options.max_height.unwrap_or(y1 - y0));
+        }
+        figures
+    }
+
+    #[test]
+    fn manhattan() {
+        let l = vec![(0, 0), (30, 0), (30, 10), (10, 10), (10, 30), (0, 30),
+            (0, 0)];
+        assert_eq!(check(l.clone(), &FractureOptions::default(), 0.).len(),
+            2);
+        let figures = check(l, &FractureOptions::max_size(7), 0.);
+        // 30 is cut into 5 pieces of 6, 20 into 3 and 10 into 2 pieces
+        assert_eq!(figures.len(), 5*2 + 3*2);
+    }
+
+    #[test]
+    fn slanted() {
+        let triangle = vec![(0, 0), (100, 0), (37, 61), (0, 0)];
+        assert_eq!(check(triangle.clone(), &FractureOptions::default(), 0.),
+            vec![triangle.clone()]);
+        // intersections of cuts with slanted edges are rounded
+        check(triangle.clone(), &FractureOptions{max_width: Some(30),
+            max_height: None}, 30.);
+        check(triangle, &FractureOptions{max_width: Some(13),
+            max_height: Some(17)}, 60.);
+        // diagonal edges cross cuts at integer coordinates
+        let octagon = vec![(10, 0), (20, 0), (30, 10), (30, 20), (20, 30),
+            (10, 30), (0, 20), (0, 10), (10, 0)];
+        check(octagon, &FractureOptions::max_size(4), 0.);
+    }
+
+    #[test]
+    fn cut_positions() {
+        assert_eq!(cuts(0., 30., 7), vec![0., 6., 12., 18., 24., 30.]);
+        assert_eq!(cuts(0., 10., 10), vec![0., 10.]);
+        assert_eq!(cuts(0.5, 3.5, 1), vec![0.5, 1., 2., 3., 3.5]);
+        for w in cuts(-3., 100., 9).windows(2) {
+            assert!(w[1] - w[0] <= 9.);
+        }
+    }
+
+    #[test]
+    fn elements() {
+        let mut stru = Structure::new();
+        let elem = element(ElementType::Boundary, vec![
+            ElementParameter::Layer(3), ElementParameter::Datatype(4),
+            ElementParameter::XY(vec![(0, 0), (20, 0), (20, 10), (0, 10),
+                (0, 0)])]);
+        let text = element(ElementType::Text, vec![
+            ElementParameter::XY(vec![(0, 0)])]);
+        stru.elements = vec![text, elem];
+        assert_eq!(stru.fracture(&FractureOptions::max_size(10)), 2);
+        assert_eq!(stru.elements.len(), 3);
+        assert!(matches!(stru.elements[0].element_type, ElementType::Text));
+        for e in &stru.elements[1..] {
+            assert_eq!((e.layer(), e.datatype()), (Some(3), Some(4)));
+        }
+        assert_eq!(stru.elements[2].xy().unwrap(),
+            &[(10, 0), (20, 0), (20, 10), (10, 10), (10, 0)]);
+    }
+}
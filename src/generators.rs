@@ -0,0 +1,324 @@
+//! Contains generators for curved shapes.
+//!
+//! All functions return boundaries on the given layer and datatype with
+//! closed polygons rounded to database units, or `None` if the parameters do
+//! not describe a shape. Curves are approximated by chords deviating at most
+//! `tolerance` database units from the exact curve. The number of points is
+//! limited so that every polygon fits into a single XY record, which may
+//! result in a larger deviation for very small tolerances. Angles are given
+//! in degrees and measured counterclockwise from the x-axis.
+
+use std::f64::consts::PI;
+
+use {Element, ElementParameter, ElementType};
+use constants::MAX_POINTS;
+
+/// Width profiles of tapers.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum TaperProfile {
+    /// The width changes linearly.
+    Linear,
+    /// The width changes exponentially.
+    Exponential
+}
+
+/// Creates a boundary on `layer` and `datatype` with the points `xy`.
+pub fn boundary(layer: i16, datatype: i16, xy: Vec<(i32,i32)>) -> Element {
+    Element{element_type: ElementType::Boundary,
+        parameters: vec![ElementParameter::Layer(layer),
+            ElementParameter::Datatype(datatype), ElementParameter::XY(xy)]}
+}
+
+/// Returns a circle around `center`.
+pub fn circle(layer: i16, datatype: i16, center: (i32,i32), radius: f64,
+        tolerance: f64) -> Option<Element> {
+    ellipse(layer, datatype, center, radius, radius, 0., tolerance)
+}
+
+/// Returns an ellipse around `center` with the semi-axes `rx` and `ry`
+/// rotated by `rotation`.
+pub fn ellipse(layer: i16, datatype: i16, center: (i32,i32), rx: f64,
+        ry: f64, rotation: f64, tolerance: f64) -> Option<Element> {
+    if rx <= 0. || ry <= 0. {
+        return None;
+    }
+    // radius of a circle with the same error for the same parameter step
+    let r = rx.max(ry).powi(3)/rx.min(ry).powi(2);
+    let n = segments(r, 2.*PI, tolerance, MAX_POINTS - 1).max(3);
+    let (s, c) = rotation.to_radians().sin_cos();
+    let pts = (0..n).map(|i| {
+        let t = 2.*PI*i as f64/n as f64;
+        let (x, y) = (rx*t.cos(), ry*t.sin());
+        (center.0 as f64 + c*x - s*y, center.1 as f64 + s*x + c*y)
+    }).collect();
+    polygon(layer, datatype, pts)
+}
+
+/// Returns a ring around `center`.
+///
+/// As boundaries cannot contain holes, the inner and outer circle are
+/// connected along the positive x-axis.
+pub fn annulus(layer: i16, datatype: i16, center: (i32,i32), inner: f64,
+        outer: f64, tolerance: f64) -> Option<Element> {
+    arc(layer, datatype, center, inner, outer, 0., 360., tolerance)
+}
+
+/// Returns the part of a ring between the angles `start` and `end`.
+///
+/// An inner radius of zero results in a circular sector.
+#[allow(clippy::too_many_arguments)]
+pub fn arc(layer: i16, datatype: i16, center: (i32,i32), inner: f64,
+        outer: f64, start: f64, end: f64, tolerance: f64) -> Option<Element> {
+    if outer <= 0. || inner < 0. || inner >= outer || start == end {
+        return None;
+    }
+    let (a, b) = (start.to_radians(), end.to_radians());
+    // outer points, inner points or the centre and the closing point
+    let max = if inner > 0. { MAX_POINTS/2 - 1 } else { MAX_POINTS - 3 };
+    let n = segments(outer, (b - a).abs(), tolerance, max).max(1);
+    let c = (center.0 as f64, center.1 as f64);
+    let at = |r: f64, i: usize, n: usize| {
+        let t = a + (b - a)*i as f64/n as f64;
+        (c.0 + r*t.cos(), c.1 + r*t.sin())
+    };
+    let mut pts: Vec<(f64,f64)> = (0..n + 1).map(|i| at(outer, i, n))
+        .collect();
+    if inner > 0. {
+        let m = segments(inner, (b - a).abs(), tolerance, max).max(1);
+        pts.extend((0..m + 1).rev().map(|i| at(inner, i, m)));
+    } else {
+        pts.push(c);
+    }
+    polygon(layer, datatype, pts)
+}
+
+/// Returns a rectangle with the corners `(x0, y0)` and `(x1, y1)` and
+/// corners rounded with `radius`.
+///
+/// The radius is limited to half of the shorter side.
+#[allow(clippy::too_many_arguments)]
+pub fn rounded_rect(layer: i16, datatype: i16, x0: i32, y0: i32, x1: i32,
+        y1: i32, radius: f64, tolerance: f64) -> Option<Element> {
+    if x0 == x1 || y0 == y1 {
+        return None;
+    }
+    let (xa, xb) = (x0.min(x1) as f64, x0.max(x1) as f64);
+    let (ya, yb) = (y0.min(y1) as f64, y0.max(y1) as f64);
+    let r = radius.min((xb - xa)/2.).min((yb - ya)/2.).max(0.);
+    let n = segments(r, PI/2., tolerance, MAX_POINTS/4 - 1).max(1);
+    let centres = [(xb - r, ya + r), (xb - r, yb - r), (xa + r, yb - r),
+        (xa + r, ya + r)];
+    let mut pts = Vec::new();
+    for (k, &(cx, cy)) in centres.iter().enumerate() {
+        for i in 0..n + 1 {
+            let t = (k as f64 - 1. + i as f64/n as f64)*PI/2.;
+            pts.push((cx + r*t.cos(), cy + r*t.sin()));
+        }
+    }
+    polygon(layer, datatype, pts)
+}
+
+/// Returns a waveguide bend following an Euler spiral.
+///
+/// The bend starts at `origin` in `direction` and turns by `angle`, positive
+/// angles turn left. The curvature increases linearly from zero to
+/// `1/radius` in the middle of the bend and decreases to zero again, so the
+/// bend can be joined with straight waveguides without a kink in the
+/// curvature. `width` is the width of the waveguide.
+#[allow(clippy::too_many_arguments)]
+pub fn euler_bend(layer: i16, datatype: i16, origin: (i32,i32),
+        direction: f64, radius: f64, angle: f64, width: f64, tolerance: f64)
+        -> Option<Element> {
+    if radius <= 0. || angle == 0. || width <= 0. {
+        return None;
+    }
+    let theta = angle.to_radians().abs();
+    let sign = angle.signum();
+    let half = theta*radius;
+    // the curvature is k(s) = s/(half*radius) in the first half
+    let curvature = |s: f64| s.min(2.*half - s)/(half*radius);
+    let kmax = 1./(radius - width/2.).max(tolerance.max(1e-3));
+    let step = (8.*tolerance.max(1e-3)/kmax).sqrt();
+    let n = ((2.*half/step).ceil() as usize).clamp(1, MAX_POINTS/2 - 1);
+
+    // integrate the heading and the position with small sub steps
+    let mut centre = Vec::with_capacity(n + 1);
+    let (mut x, mut y, mut phi) = (origin.0 as f64, origin.1 as f64,
+        direction.to_radians());
+    let sub = 16;
+    let ds = 2.*half/(n*sub) as f64;
+    centre.push((x, y, phi));
+    for i in 0..n*sub {
+        let s = (i as f64 + 0.5)*ds;
+        let mid = phi + sign*curvature(s)*ds/2.;
+        x += ds*mid.cos();
+        y += ds*mid.sin();
+        phi += sign*curvature(s)*ds;
+        if (i + 1) % sub == 0 {
+            centre.push((x, y, phi));
+        }
+    }
+    let h = width/2.;
+    let mut pts: Vec<(f64,f64)> = centre.iter()
+        .map(|&(x, y, p)| (x - h*p.sin(), y + h*p.cos())).collect();
+    pts.extend(centre.iter().rev()
+        .map(|&(x, y, p)| (x + h*p.sin(), y - h*p.cos())));
+    polygon(layer, datatype, pts)
+}
+
+/// Returns a taper starting at `origin` in `direction`.
+///
+/// The width changes from `width_start` to `width_end` over `length`
+/// according to `profile`.
+#[allow(clippy::too_many_arguments)]
+pub fn taper(layer: i16, datatype: i16, origin: (i32,i32), direction: f64,
+        length: f64, width_start: f64, width_end: f64, profile: TaperProfile,
+        tolerance: f64) -> Option<Element> {
+    if length <= 0. || width_start < 0. || width_end < 0. ||
+            width_start + width_end == 0. {
+        return None;
+    }
+    let n = match profile {
+        TaperProfile::Exponential if width_start > 0. && width_end > 0. &&
+                width_start != width_end => {
+            // the error of a chord is at most step^2*max(h'')/8
+            let k = (width_end/width_start).ln()/length;
+            let h2 = width_start.max(width_end)/2.*k*k;
+            let step = (8.*tolerance.max(1e-3)/h2).sqrt();
+            ((length/step).ceil() as usize).clamp(1, MAX_POINTS/2 - 1)
+        },
+        _ => 1
+    };
+    let width = |x: f64| match profile {
+        TaperProfile::Exponential if width_start > 0. && width_end > 0. =>
+            width_start*(width_end/width_start).powf(x/length),
+        _ => width_start + (width_end - width_start)*x/length
+    };
+    let (s, c) = direction.to_radians().sin_cos();
+    let at = |x: f64, y: f64|
+        (origin.0 as f64 + c*x - s*y, origin.1 as f64 + s*x + c*y);
+    let xs: Vec<f64> = (0..n + 1).map(|i| length*i as f64/n as f64).collect();
+    let mut pts: Vec<(f64,f64)> = xs.iter()
+        .map(|&x| at(x, -width(x)/2.)).collect();
+    pts.extend(xs.iter().rev().map(|&x| at(x, width(x)/2.)));
+    polygon(layer, datatype, pts)
+}
+
+/// Returns the number of chords needed to approximate an arc with `radius`
+/// and `sweep` radians.
+fn segments(radius: f64, sweep: f64, tolerance: f64, max: usize) -> usize {
+    if radius <= 0. {
+        return 1;
+    }
+    let e = tolerance.max(0.).min(radius);
+    let step = 2.*(1. - e/radius).acos();
+    if step <= 0. {
+        return max;
+    }
+    ((sweep.abs()/step).ceil() as usize).clamp(1, max)
+}
+
+/// Returns a boundary with the points rounded, repeated points removed and
+/// the polygon closed.
+fn polygon(layer: i16, datatype: i16, pts: Vec<(f64,f64)>)
+        -> Option<Element> {
+    let mut res: Vec<(i32,i32)> = Vec::with_capacity(pts.len() + 1);
+    for p in pts {
+        let p = (p.0.round() as i32, p.1.round() as i32);
+        if res.last() != Some(&p) {
+            res.push(p);
+        }
+    }
+    while res.len() > 1 && res[0] == res[res.len() - 1] {
+        res.pop();
+    }
+    if res.len() < 3 {
+        return None;
+    }
+    res.push(res[0]);
+    Some(boundary(layer, datatype, res))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(elem: Option<Element>) -> Vec<(i32,i32)> {
+        let elem = elem.unwrap();
+        assert!(matches!(elem.element_type, ElementType::Boundary));
+        let xy = elem.xy().unwrap().to_vec();
+        assert_eq!(xy.first(), xy.last());
+        xy
+    }
+
+    /// Returns the smallest and largest distance of the vertices and the
+    /// midpoints of the chords from the origin.
+    fn distances(xy: &[(i32,i32)]) -> (f64, f64) {
+        let mut res = (f64::INFINITY, 0f64);
+        for p in xy.windows(2) {
+            let mid = ((p[0].0 + p[1].0) as f64/2.,
+                (p[0].1 + p[1].1) as f64/2.);
+            for &(x, y) in &[(p[0].0 as f64, p[0].1 as f64), mid] {
+                let d = x.hypot(y);
+                res = (res.0.min(d), res.1.max(d));
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn chord_error() {
+        // rounding to database units adds up to half a diagonal
+        let rounding = 0.5f64.hypot(0.5);
+        for &tolerance in &[0.5, 2., 10.] {
+            let xy = points(circle(1, 2, (0, 0), 1000., tolerance));
+            let (min, max) = distances(&xy);
+            assert!(min >= 1000. - tolerance - rounding, "{}", min);
+            assert!(max <= 1000. + rounding, "{}", max);
+            let coarse = points(circle(1, 2, (0, 0), 1000., tolerance*4.));
+            assert!(coarse.len() < xy.len());
+
+            let xy = points(annulus(1, 2, (0, 0), 500., 1000., tolerance));
+            let outer: Vec<(i32,i32)> = xy.iter().cloned()
+                .take_while(|p| p.0 >= 0 || p.1 != 0).collect();
+            let (min, _) = distances(&xy[..xy.len()/2]);
+            assert!(min >= 500. - tolerance - rounding);
+            assert!(distances(&outer).1 <= 1000. + rounding);
+        }
+        let ellipse = points(ellipse(1, 0, (0, 0), 1000., 250., 90., 1.));
+        assert!(ellipse.iter().all(|p| p.0.abs() <= 250 && p.1.abs() <= 1000));
+        let (min, _) = distances(&ellipse);
+        assert!(min >= 250. - 1. - rounding);
+    }
+
+    #[test]
+    fn point_limit() {
+        let shapes = vec![circle(1, 0, (0, 0), 1e8, 1e-6),
+            ellipse(1, 0, (0, 0), 1e8, 1e3, 30., 1e-6),
+            annulus(1, 0, (0, 0), 5e7, 1e8, 1e-6),
+            arc(1, 0, (0, 0), 0., 1e8, 10., 350., 1e-6),
+            rounded_rect(1, 0, -100000000, -100000000, 100000000, 100000000,
+                5e7, 1e-6),
+            euler_bend(1, 0, (0, 0), 0., 1e8, 90., 1e3, 1e-6),
+            taper(1, 0, (0, 0), 0., 1e8, 1e3, 1e6, TaperProfile::Exponential,
+                1e-6)];
+        for xy in shapes.into_iter().map(points) {
+            assert!(xy.len() > MAX_POINTS/2 && xy.len() <= MAX_POINTS,
+                "{}", xy.len());
+        }
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        assert!(circle(1, 0, (0, 0), 0., 1.).is_none());
+        assert!(annulus(1, 0, (0, 0), 10., 5., 1.).is_none());
+        assert!(arc(1, 0, (0, 0), 0., 10., 45., 45., 1.).is_none());
+        assert!(rounded_rect(1, 0, 0, 0, 0, 10, 1., 1.).is_none());
+        assert!(taper(1, 0, (0, 0), 0., 10., 0., 0., TaperProfile::Linear,
+            1.).is_none());
+        // a circle smaller than a database unit
+        assert!(circle(1, 0, (0, 0), 0.1, 1.).is_none());
+        assert_eq!(points(rounded_rect(1, 0, 10, 0, 0, 5, 0., 1.)),
+            vec![(10, 0), (10, 5), (0, 5), (0, 0), (10, 0)]);
+    }
+}
@@ -0,0 +1,837 @@
+//! Contains geometric helper types like bounding boxes and transformations.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use {Element, ElementParameter, ElementType, Error, Library};
+use boolean;
+
+/// An axis aligned rectangle in database units.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct BoundingBox {
+    /// Smallest x-coordinate.
+    pub x_min: i32,
+    /// Smallest y-coordinate.
+    pub y_min: i32,
+    /// Largest x-coordinate.
+    pub x_max: i32,
+    /// Largest y-coordinate.
+    pub y_max: i32
+}
+
+/// An affine transformation of the plane.
+///
+/// A point `(x,y)` is mapped to `(xx*x + xy*y + dx, yx*x + yy*y + dy)`.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Transform {
+    pub xx: f64,
+    pub xy: f64,
+    pub yx: f64,
+    pub yy: f64,
+    pub dx: f64,
+    pub dy: f64
+}
+
+impl BoundingBox {
+    /// Creates a bounding box containing only the point `p`.
+    pub fn new(p: (i32,i32)) -> BoundingBox {
+        BoundingBox{x_min: p.0, y_min: p.1, x_max: p.0, y_max: p.1}
+    }
+
+    /// Creates the smallest bounding box containing all points `pts`.
+    ///
+    /// Returns `None` if `pts` is empty.
+    pub fn from_points(pts: &[(i32,i32)]) -> Option<BoundingBox> {
+        let mut iter = pts.iter();
+        let mut res = BoundingBox::new(*iter.next()?);
+        for &p in iter {
+            res.add_point(p);
+        }
+        Some(res)
+    }
+
+    /// Enlarges the bounding box so that it contains the point `p`.
+    pub fn add_point(&mut self, p: (i32,i32)) {
+        self.x_min = self.x_min.min(p.0);
+        self.y_min = self.y_min.min(p.1);
+        self.x_max = self.x_max.max(p.0);
+        self.y_max = self.y_max.max(p.1);
+    }
+
+    /// Returns the smallest bounding box containing `self` and `other`.
+    pub fn union(&self, other: &BoundingBox) -> BoundingBox {
+        BoundingBox{x_min: self.x_min.min(other.x_min),
+            y_min: self.y_min.min(other.y_min),
+            x_max: self.x_max.max(other.x_max),
+            y_max: self.y_max.max(other.y_max)}
+    }
+
+    /// Returns a bounding box grown by `d` in every direction.
+    pub fn grow(&self, d: i32) -> BoundingBox {
+        BoundingBox{x_min: self.x_min.saturating_sub(d),
+            y_min: self.y_min.saturating_sub(d),
+            x_max: self.x_max.saturating_add(d),
+            y_max: self.y_max.saturating_add(d)}
+    }
+
+    /// Width of the bounding box.
+    pub fn width(&self) -> i64 {
+        self.x_max as i64 - self.x_min as i64
+    }
+
+    /// Height of the bounding box.
+    pub fn height(&self) -> i64 {
+        self.y_max as i64 - self.y_min as i64
+    }
+
+    /// Returns the four corners in counterclockwise order.
+    pub fn corners(&self) -> [(i32,i32);4] {
+        [(self.x_min,self.y_min), (self.x_max,self.y_min),
+            (self.x_max,self.y_max), (self.x_min,self.y_max)]
+    }
+
+    /// Returns true if the point `p` lies inside or on the border.
+    pub fn contains(&self, p: (i32,i32)) -> bool {
+        p.0 >= self.x_min && p.0 <= self.x_max &&
+            p.1 >= self.y_min && p.1 <= self.y_max
+    }
+
+    /// Returns the squared distance between the point `p` and the bounding
+    /// box. Points inside have a distance of zero.
+    pub fn distance_squared(&self, p: (i32,i32)) -> f64 {
+        let d = |v: i32, min: i32, max: i32| if v < min {
+            min as f64 - v as f64
+        } else if v > max {
+            v as f64 - max as f64
+        } else {
+            0.
+        };
+        let dx = d(p.0, self.x_min, self.x_max);
+        let dy = d(p.1, self.y_min, self.y_max);
+        dx*dx + dy*dy
+    }
+
+    /// Returns true if both bounding boxes share at least one point.
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        self.x_min <= other.x_max && other.x_min <= self.x_max &&
+            self.y_min <= other.y_max && other.y_min <= self.y_max
+    }
+}
+
+impl Transform {
+    /// Creates the identity transformation.
+    pub fn identity() -> Transform {
+        Transform{xx: 1., xy: 0., yx: 0., yy: 1., dx: 0., dy: 0.}
+    }
+
+    /// Creates a pure translation by `(dx,dy)`.
+    pub fn translation(dx: f64, dy: f64) -> Transform {
+        Transform{xx: 1., xy: 0., yx: 0., yy: 1., dx, dy}
+    }
+
+    /// Creates a transformation as described in a GDS file.
+    ///
+    /// The point is first reflected about the x-axis if `reflect` is set, then
+    /// magnified by `mag`, rotated counterclockwise by `angle` degrees and
+    /// finally moved by `offset`.
+    pub fn new(reflect: bool, mag: f64, angle: f64, offset: (f64,f64))
+            -> Transform {
+        let (sin, cos) = exact_sin_cos(angle);
+        let r = if reflect { -1. } else { 1. };
+        Transform{xx: mag*cos, xy: -mag*sin*r, yx: mag*sin, yy: mag*cos*r,
+            dx: offset.0, dy: offset.1}
+    }
+
+    /// Returns the transformation applying `inner` first and `self` second.
+    pub fn compose(&self, inner: &Transform) -> Transform {
+        Transform{
+            xx: self.xx*inner.xx + self.xy*inner.yx,
+            xy: self.xx*inner.xy + self.xy*inner.yy,
+            yx: self.yx*inner.xx + self.yy*inner.yx,
+            yy: self.yx*inner.xy + self.yy*inner.yy,
+            dx: self.xx*inner.dx + self.xy*inner.dy + self.dx,
+            dy: self.yx*inner.dx + self.yy*inner.dy + self.dy}
+    }
+
+    /// Transforms the point `p`.
+    pub fn apply(&self, p: (f64,f64)) -> (f64,f64) {
+        (self.xx*p.0 + self.xy*p.1 + self.dx,
+            self.yx*p.0 + self.yy*p.1 + self.dy)
+    }
+
+    /// Transforms the point `p` given in database units.
+    ///
+    /// The result is rounded to the nearest database unit.
+    pub fn apply_point(&self, p: (i32,i32)) -> (i32,i32) {
+        let (x, y) = self.apply((p.0 as f64, p.1 as f64));
+        (x.round() as i32, y.round() as i32)
+    }
+
+    /// Returns the bounding box of the transformed bounding box `bbox`.
+    pub fn apply_bbox(&self, bbox: &BoundingBox) -> BoundingBox {
+        let corners = bbox.corners();
+        let mut res = BoundingBox::new(self.apply_point(corners[0]));
+        for &c in &corners[1..] {
+            res.add_point(self.apply_point(c));
+        }
+        res
+    }
+
+    /// Returns the inverse transformation.
+    ///
+    /// Returns `None` if the transformation is not invertible.
+    pub fn inverse(&self) -> Option<Transform> {
+        let det = self.xx*self.yy - self.xy*self.yx;
+        if det == 0. || !det.is_finite() {
+            return None;
+        }
+        let (xx, xy, yx, yy) = (self.yy/det, -self.xy/det, -self.yx/det,
+            self.xx/det);
+        Some(Transform{xx, xy, yx, yy, dx: -(xx*self.dx + xy*self.dy),
+            dy: -(yx*self.dx + yy*self.dy)})
+    }
+
+    /// Returns true if the transformation mirrors the plane.
+    pub fn is_reflection(&self) -> bool {
+        self.xx*self.yy - self.xy*self.yx < 0.
+    }
+
+    /// Returns the scaling factor of the transformation.
+    pub fn magnification(&self) -> f64 {
+        (self.xx*self.yy - self.xy*self.yx).abs().sqrt()
+    }
+}
+
+/// Returns sine and cosine of `angle` given in degrees.
+///
+/// Multiples of 90 degrees result in exact values.
+fn exact_sin_cos(angle: f64) -> (f64,f64) {
+    let a = angle % 360.;
+    let a = if a < 0. { a + 360. } else { a };
+    if a == 0. {
+        (0., 1.)
+    } else if a == 90. {
+        (1., 0.)
+    } else if a == 180. {
+        (0., -1.)
+    } else if a == 270. {
+        (-1., 0.)
+    } else {
+        (a*PI/180.).sin_cos()
+    }
+}
+
+impl Element {
+    /// Returns the number of columns and rows of a reference.
+    ///
+    /// Structure references have a single column and row, array references
+    /// use the ColRow parameter. Other element types result in `(0,0)`.
+    pub fn array_size(&self) -> (i32,i32) {
+        match self.element_type {
+            ElementType::StructureRef => (1, 1),
+            ElementType::ArrayRef => self.parameters.iter()
+                .filter_map(|p| match *p {
+                    ElementParameter::ColRow(ref x) if x.len() >= 2 =>
+                        Some((x[0].max(0) as i32, x[1].max(0) as i32)),
+                    _ => None
+                }).next().unwrap_or((0, 0)),
+            _ => (0, 0)
+        }
+    }
+
+    /// Returns the transformation of a single instance of a reference.
+    ///
+    /// `col` and `row` select the instance of an array reference and have to
+    /// be zero for structure references. The absolute magnification and
+    /// absolute angle flags are not supported and treated as relative.
+    pub fn instance_transform(&self, col: i32, row: i32) -> Option<Transform> {
+        let (cols, rows) = self.array_size();
+        if col < 0 || row < 0 || col >= cols || row >= rows {
+            return None;
+        }
+        let xy = self.xy()?;
+        let mut reflect = false;
+        let mut mag = 1.;
+        let mut angle = 0.;
+        for param in &self.parameters {
+            match *param {
+                ElementParameter::StrTransf(x) => reflect = x & 0x8000 != 0,
+                ElementParameter::Magnification(x) => mag = x,
+                ElementParameter::Angle(x) => angle = x,
+                _ => {}
+            }
+        }
+        let origin = *xy.first()?;
+        let mut offset = (origin.0 as f64, origin.1 as f64);
+        if let ElementType::ArrayRef = self.element_type {
+            if xy.len() < 3 {
+                return None;
+            }
+            let (c, r) = (col as f64, row as f64);
+            offset.0 += c*(xy[1].0 as f64 - origin.0 as f64)/cols as f64 +
+                r*(xy[2].0 as f64 - origin.0 as f64)/rows as f64;
+            offset.1 += c*(xy[1].1 as f64 - origin.1 as f64)/cols as f64 +
+                r*(xy[2].1 as f64 - origin.1 as f64)/rows as f64;
+        }
+        Some(Transform::new(reflect, mag, angle, offset))
+    }
+
+    /// Returns the transformations of all instances of a reference.
+    ///
+    /// A structure reference results in a single transformation and an array
+    /// reference in one transformation per column and row. Other element
+    /// types result in an empty vector.
+    pub fn instance_transforms(&self) -> Vec<Transform> {
+        let (cols, rows) = self.array_size();
+        let mut res = Vec::new();
+        for r in 0..rows {
+            for c in 0..cols {
+                if let Some(t) = self.instance_transform(c, r) {
+                    res.push(t);
+                }
+            }
+        }
+        res
+    }
+
+    /// Returns the bounding box of the element itself.
+    ///
+    /// References are not resolved and result in `None`. Paths are enlarged
+    /// by half of their width and their extension.
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        if self.is_reference() {
+            return None;
+        }
+        let bbox = BoundingBox::from_points(self.xy()?)?;
+        match self.element_type {
+            ElementType::Path => {
+                let mut grow = 0;
+                for param in &self.parameters {
+                    match *param {
+                        ElementParameter::Width(x) => grow += x.abs()/2,
+                        ElementParameter::BeginExt(x) => grow += x.abs(),
+                        _ => {}
+                    }
+                }
+                Some(bbox.grow(grow))
+            },
+            _ => Some(bbox)
+        }
+    }
+}
+
+impl Library {
+    /// Returns the bounding box of the structure named `name`.
+    ///
+    /// All references are resolved recursively. Returns `None` if the
+    /// structure does not exist or does not contain any geometry.
+    pub fn bounding_box(&self, name: &str) -> Option<BoundingBox> {
+        let mut cache = HashMap::new();
+        self.bounding_box_cached(name, &mut cache)
+    }
+
+    fn bounding_box_cached(&self, name: &str,
+            cache: &mut HashMap<String, Option<BoundingBox>>)
+            -> Option<BoundingBox> {
+        if let Some(bbox) = cache.get(name) {
+            return *bbox;
+        }
+        // guard against recursive references
+        cache.insert(name.to_string(), None);
+        let stru = self.structure(name)?;
+        let mut res: Option<BoundingBox> = None;
+        for elem in &stru.elements {
+            if let Some(b) = self.element_bounding_box_cached(elem, cache) {
+                res = Some(res.map_or(b, |x| x.union(&b)));
+            }
+        }
+        cache.insert(name.to_string(), res);
+        res
+    }
+
+    /// Returns the bounding box of an element with references resolved.
+    pub(crate) fn element_bounding_box_cached(&self, elem: &Element,
+            cache: &mut HashMap<String, Option<BoundingBox>>)
+            -> Option<BoundingBox> {
+        if !elem.is_reference() {
+            return elem.bounding_box();
+        }
+        let child = self.bounding_box_cached(elem.structure_name()?, cache)?;
+        // the corner instances of an array span its bounding box
+        let (cols, rows) = elem.array_size();
+        let mut bbox: Option<BoundingBox> = None;
+        for &(c, r) in &[(0, 0), (cols-1, 0), (0, rows-1), (cols-1, rows-1)] {
+            let b = match elem.instance_transform(c, r) {
+                Some(t) => t.apply_bbox(&child),
+                None => continue
+            };
+            bbox = Some(bbox.map_or(b, |x| x.union(&b)));
+        }
+        bbox
+    }
+}
+
+impl Transform {
+    /// Splits the transformation into reflection, magnification and angle.
+    ///
+    /// This is the inverse of [Transform::new](#method.new) ignoring the
+    /// offset. Angles close to a multiple of 90 degrees are snapped.
+    pub fn decompose(&self) -> (bool, f64, f64) {
+        let reflect = self.is_reflection();
+        let mag = self.magnification();
+        let mut angle = self.yx.atan2(self.xx)*180./PI;
+        if (angle - (angle/90.).round()*90.).abs() < 1e-9 {
+            angle = (angle/90.).round()*90.;
+        }
+        if angle < 0. {
+            angle += 360.;
+        }
+        if angle >= 360. {
+            angle -= 360.;
+        }
+        (reflect, mag, angle)
+    }
+}
+
+impl Element {
+    /// Returns a copy of the element transformed by `t`.
+    ///
+    /// All coordinates are transformed. Widths and extensions of paths are
+    /// scaled by the magnification. Texts and references get their
+    /// transformation parameters adjusted accordingly.
+    pub fn transformed(&self, t: &Transform) -> Element {
+        let mag = t.magnification();
+        let mut res = Element::new();
+        res.element_type = self.element_type.clone();
+        let mut orientation: Option<Transform> = None;
+        match self.element_type {
+            ElementType::Text | ElementType::StructureRef |
+                ElementType::ArrayRef => {
+                let mut reflect = false;
+                let mut m = 1.;
+                let mut angle = 0.;
+                for param in &self.parameters {
+                    match *param {
+                        ElementParameter::StrTransf(x) =>
+                            reflect = x & 0x8000 != 0,
+                        ElementParameter::Magnification(x) => m = x,
+                        ElementParameter::Angle(x) => angle = x,
+                        _ => {}
+                    }
+                }
+                let linear = Transform{dx: 0., dy: 0., ..*t};
+                orientation = Some(linear.compose(
+                    &Transform::new(reflect, m, angle, (0., 0.))));
+            },
+            _ => {}
+        }
+        // the transformation has to precede COLROW and XY
+        let mut strans = orientation.map(|o| orientation_parameters(
+            &o, &self.parameters));
+        for param in &self.parameters {
+            match *param {
+                ElementParameter::ColRow(_) | ElementParameter::XY(_) =>
+                    if let Some(x) = strans.take() {
+                        res.parameters.extend(x);
+                    },
+                _ => {}
+            }
+            let p = match *param {
+                ElementParameter::XY(ref x) => ElementParameter::XY(
+                    x.iter().map(|&p| t.apply_point(p)).collect()),
+                ElementParameter::Width(x) if x > 0 && self.is_path() =>
+                    ElementParameter::Width((x as f64*mag).round() as i32),
+                ElementParameter::BeginExt(x) =>
+                    ElementParameter::BeginExt((x as f64*mag).round() as i32),
+                ElementParameter::StrTransf(_) |
+                    ElementParameter::Magnification(_) |
+                    ElementParameter::Angle(_) if orientation.is_some() =>
+                    continue,
+                ref x => x.clone()
+            };
+            res.parameters.push(p);
+        }
+        if let Some(x) = strans {
+            res.parameters.extend(x);
+        }
+        res
+    }
+
+    fn is_path(&self) -> bool {
+        matches!(self.element_type, ElementType::Path)
+    }
+}
+
+/// Returns the StrTransf, Magnification and Angle parameters describing the
+/// orientation `o`.
+///
+/// Flags other than the reflection are taken over from `params`.
+fn orientation_parameters(o: &Transform, params: &[ElementParameter])
+        -> Vec<ElementParameter> {
+    let (reflect, mag, angle) = o.decompose();
+    let mut flags = 0;
+    for param in params {
+        if let ElementParameter::StrTransf(x) = *param {
+            flags = x & 0x7fff;
+        }
+    }
+    if reflect {
+        flags |= 0x8000;
+    }
+    let mut res = vec![ElementParameter::StrTransf(flags)];
+    if (mag - 1.).abs() > 1e-12 {
+        res.push(ElementParameter::Magnification(mag));
+    }
+    if angle != 0. {
+        res.push(ElementParameter::Angle(angle));
+    }
+    res
+}
+
+impl Element {
+    /// Returns the polygons covered by the element.
+    ///
+    /// Boundaries and boxes result in their outline. Paths are converted into
+    /// one polygon per segment, joint and end which may overlap. Round ends
+    /// are approximated by polygons. Other element types result in an empty
+    /// vector.
+    pub fn polygons(&self) -> Vec<Vec<(i32,i32)>> {
+        let xy = match self.xy() {
+            Some(x) if !x.is_empty() => x,
+            _ => return Vec::new()
+        };
+        match self.element_type {
+            ElementType::Boundary | ElementType::Box => vec![xy.to_vec()],
+            ElementType::Path => path_polygons(self, xy),
+            _ => Vec::new()
+        }
+    }
+}
+
+impl Element {
+    /// Splits the element into elements with at most `max_points` points.
+    ///
+    /// Boundaries are decomposed into trapezoids. Coordinates of intersections
+    /// are rounded, so non-orthogonal boundaries might change slightly.
+    /// Paths are split into consecutive paths sharing their end points, only
+    /// the first one keeps the begin extension. Elements which are small
+    /// enough are returned unchanged, other elements with too many points
+    /// result in `Error::Overflow`.
+    pub fn split(&self, max_points: usize) -> Result<Vec<Element>, Error> {
+        let xy = match self.xy() {
+            Some(x) if x.len() > max_points => x,
+            _ => return Ok(vec![self.clone()])
+        };
+        Ok(match self.element_type {
+            ElementType::Boundary => boolean::union(&[xy.to_vec()]).iter()
+                .map(|t| self.with_xy(t.to_polygon()))
+                .filter(|e| match e.xy() {
+                    Some(x) => x.len() > 3,
+                    None => false
+                }).collect(),
+            ElementType::Path => {
+                let step = max_points.max(2) - 1;
+                let mut res = Vec::new();
+                let mut i = 0;
+                while i + 1 < xy.len() {
+                    let mut e = self.with_xy(
+                        xy[i..xy.len().min(i + step + 1)].to_vec());
+                    if i > 0 {
+                        e.parameters.retain(|p|
+                            !matches!(*p, ElementParameter::BeginExt(_)));
+                    }
+                    res.push(e);
+                    i += step;
+                }
+                res
+            },
+            _ => return Err(Error::Overflow(format!(
+                "{} points in element which can not be split", xy.len())))
+        })
+    }
+
+    /// Returns a copy with the points replaced by `xy`.
+    pub(crate) fn with_xy(&self, xy: Vec<(i32,i32)>) -> Element {
+        let mut res = self.clone();
+        for param in &mut res.parameters {
+            if let ElementParameter::XY(ref mut x) = *param {
+                *x = xy;
+                break;
+            }
+        }
+        res
+    }
+}
+
+fn path_polygons(elem: &Element, xy: &[(i32,i32)]) -> Vec<Vec<(i32,i32)>> {
+    let mut width = 0;
+    let mut pathtype = 0;
+    let mut begin_ext = 0;
+    for param in &elem.parameters {
+        match *param {
+            ElementParameter::Width(x) => width = x.abs(),
+            ElementParameter::Pathtype(x) => pathtype = x,
+            ElementParameter::BeginExt(x) => begin_ext = x,
+            _ => {}
+        }
+    }
+    let h = width as f64/2.;
+    let mut pts: Vec<(f64,f64)> = Vec::new();
+    for &p in xy {
+        let p = (p.0 as f64, p.1 as f64);
+        if pts.last() != Some(&p) {
+            pts.push(p);
+        }
+    }
+    if h == 0. || pts.len() < 2 {
+        return Vec::new();
+    }
+    let r = |p: (f64,f64)| (p.0.round() as i32, p.1.round() as i32);
+    let dir = |a: (f64,f64), b: (f64,f64)| {
+        let l = ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt();
+        ((b.0 - a.0)/l, (b.1 - a.1)/l)
+    };
+    let n = pts.len();
+    let (ext_begin, ext_end) = match pathtype {
+        2 => (h, h),
+        4 => (begin_ext as f64, 0.),
+        _ => (0., 0.)
+    };
+    let mut res = Vec::new();
+    for i in 0..n - 1 {
+        let d = dir(pts[i], pts[i+1]);
+        let nrm = (-d.1*h, d.0*h);
+        let a = if i == 0 { ext_begin } else { 0. };
+        let b = if i == n - 2 { ext_end } else { 0. };
+        let p = (pts[i].0 - d.0*a, pts[i].1 - d.1*a);
+        let q = (pts[i+1].0 + d.0*b, pts[i+1].1 + d.1*b);
+        res.push(vec![r((p.0 - nrm.0, p.1 - nrm.1)),
+            r((q.0 - nrm.0, q.1 - nrm.1)), r((q.0 + nrm.0, q.1 + nrm.1)),
+            r((p.0 + nrm.0, p.1 + nrm.1))]);
+    }
+    // mitered joints
+    for i in 1..n - 1 {
+        let d1 = dir(pts[i-1], pts[i]);
+        let d2 = dir(pts[i], pts[i+1]);
+        let cross = d1.0*d2.1 - d1.1*d2.0;
+        if cross == 0. {
+            continue;
+        }
+        // the outer side is right of a left turn and vice versa
+        let s = if cross > 0. { -1. } else { 1. };
+        let n1 = (-d1.1*s, d1.0*s);
+        let n2 = (-d2.1*s, d2.0*s);
+        let v = pts[i];
+        let a = (v.0 + n1.0*h, v.1 + n1.1*h);
+        let b = (v.0 + n2.0*h, v.1 + n2.1*h);
+        let m = (n1.0 + n2.0, n1.1 + n2.1);
+        let ml = (m.0*m.0 + m.1*m.1).sqrt();
+        let cos = ml/2.;
+        if cos > 0.1 {
+            let len = h/cos;
+            let c = (v.0 + m.0/ml*len, v.1 + m.1/ml*len);
+            res.push(vec![r(v), r(a), r(c), r(b)]);
+        } else {
+            res.push(vec![r(v), r(a), r(b)]);
+        }
+    }
+    // round ends
+    if pathtype == 1 {
+        for &c in &[pts[0], pts[n-1]] {
+            let k = 16;
+            res.push((0..k).map(|j| {
+                let t = j as f64*2.*PI/k as f64;
+                r((c.0 + h*t.cos(), c.1 + h*t.sin()))
+            }).collect());
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::{element, library, rect, sref};
+
+    #[test]
+    fn bounding_boxes() {
+        assert!(BoundingBox::from_points(&[]).is_none());
+        let a = BoundingBox::from_points(&[(3, -2), (-1, 4), (0, 0)]).unwrap();
+        assert_eq!(a, BoundingBox{x_min: -1, y_min: -2, x_max: 3, y_max: 4});
+        assert_eq!((a.width(), a.height()), (4, 6));
+        assert_eq!(a.corners(), [(-1, -2), (3, -2), (3, 4), (-1, 4)]);
+        assert!(a.contains((3, 4)) && !a.contains((4, 4)));
+        let b = BoundingBox::new((10, 10));
+        assert!(!a.intersects(&b));
+        assert!(!a.grow(6).intersects(&b) && a.grow(7).intersects(&b));
+        assert_eq!(a.union(&b),
+            BoundingBox{x_min: -1, y_min: -2, x_max: 10, y_max: 10});
+        let big = BoundingBox{x_min: i32::MIN, y_min: 0, x_max: i32::MAX,
+            y_max: 0};
+        assert_eq!(big.width(), u32::MAX as i64);
+        assert_eq!(big.grow(1).x_max, i32::MAX);
+    }
+
+    #[test]
+    fn transforms() {
+        let t = Transform::new(true, 2., 90., (10., 20.));
+        // reflected to (1,-2), magnified to (2,-4) and rotated to (4,2)
+        assert_eq!(t.apply_point((1, 2)), (14, 22));
+        assert!(t.is_reflection());
+        assert_eq!(t.magnification(), 2.);
+        let s = Transform::translation(-10., 0.).compose(&t);
+        assert_eq!(s.apply_point((1, 2)), (4, 22));
+        let bbox = BoundingBox{x_min: 0, y_min: 0, x_max: 10, y_max: 4};
+        assert_eq!(Transform::new(false, 1., 90., (0., 0.)).apply_bbox(&bbox),
+            BoundingBox{x_min: -4, y_min: 0, x_max: 0, y_max: 10});
+        assert_eq!(Transform::new(false, 1., 45., (0., 0.)).apply_bbox(&bbox),
+            BoundingBox{x_min: -3, y_min: 0, x_max: 7, y_max: 10});
+    }
+
+    #[test]
+    fn inverse_and_distance() {
+        let t = Transform::new(true, 2., 90., (10., 20.));
+        let inv = t.inverse().unwrap();
+        assert_eq!(inv.apply_point((14, 22)), (1, 2));
+        assert_eq!(t.compose(&inv).apply_point((7, -3)), (7, -3));
+        assert!(Transform{xx: 0., ..Transform::identity()}.compose(
+            &Transform{yy: 0., ..Transform::identity()}).inverse().is_none());
+        let a = BoundingBox{x_min: -1, y_min: -2, x_max: 3, y_max: 4};
+        assert_eq!(a.distance_squared((6, 8)), 25.);
+        assert_eq!(a.distance_squared((-4, 0)), 9.);
+        assert_eq!(a.distance_squared((0, 0)), 0.);
+    }
+
+    #[test]
+    fn instances() {
+        let aref = element(ElementType::ArrayRef, vec![
+            ElementParameter::StructureName(String::from("A")),
+            ElementParameter::StrTransf(0), ElementParameter::Angle(90.),
+            ElementParameter::ColRow(vec![3, 2]),
+            ElementParameter::XY(vec![(0, 0), (30, 0), (0, 40)])]);
+        assert_eq!(aref.array_size(), (3, 2));
+        let t = aref.instance_transforms();
+        assert_eq!(t.len(), 6);
+        let origins: Vec<(i32,i32)> = t.iter()
+            .map(|x| x.apply_point((0, 0))).collect();
+        assert_eq!(origins, vec![(0, 0), (10, 0), (20, 0), (0, 20), (10, 20),
+            (20, 20)]);
+        assert_eq!(t[4].apply_point((1, 0)), (10, 21));
+        assert!(aref.instance_transform(3, 0).is_none());
+        let sref = sref("A", (5, 6));
+        assert_eq!(sref.instance_transforms(),
+            vec![Transform::translation(5., 6.)]);
+        assert!(sref.instance_transform(0, 1).is_none());
+    }
+
+    #[test]
+    fn element_bounding_boxes() {
+        let path = element(ElementType::Path, vec![
+            ElementParameter::Width(4), ElementParameter::Pathtype(4),
+            ElementParameter::BeginExt(3),
+            ElementParameter::XY(vec![(0, 0), (10, 0)])]);
+        assert_eq!(path.bounding_box(),
+            Some(BoundingBox{x_min: -5, y_min: -5, x_max: 15, y_max: 5}));
+        let mut rotated = sref("A", (100, 0));
+        rotated.parameters.insert(1, ElementParameter::StrTransf(0));
+        rotated.parameters.insert(2, ElementParameter::Angle(90.));
+        let lib = library(vec![
+            ("A", vec![rect(1, 0, 0, 10, 5)]),
+            ("TOP", vec![rotated, element(ElementType::ArrayRef, vec![
+                ElementParameter::StructureName(String::from("A")),
+                ElementParameter::ColRow(vec![3, 2]),
+                ElementParameter::XY(vec![(0, 0), (60, 0), (0, 20)])])]),
+            ("LOOP", vec![sref("LOOP", (0, 0))])]);
+        assert_eq!(lib.bounding_box("TOP"),
+            Some(BoundingBox{x_min: 0, y_min: 0, x_max: 100, y_max: 15}));
+        assert!(lib.bounding_box("LOOP").is_none());
+        assert!(lib.bounding_box("MISSING").is_none());
+    }
+
+    #[test]
+    fn transformed_elements() {
+        for &angle in &[0., 30., 180., 270., 359.5] {
+            let (reflect, mag, a) =
+                Transform::new(false, 0.5, angle, (0., 0.)).decompose();
+            assert!(!reflect && (mag - 0.5).abs() < 1e-12);
+            assert!((a - angle).abs() < 1e-9);
+        }
+        assert_eq!(Transform::new(true, 2., 90., (1., 2.)).decompose(),
+            (true, 2., 90.));
+        let mut sref = sref("A", (5, 0));
+        sref.parameters.insert(1, ElementParameter::StrTransf(0x8000));
+        let t = Transform::new(false, 2., 90., (1., 1.));
+        let res = sref.transformed(&t);
+        assert_eq!(res.xy().unwrap(), &[(1, 11)]);
+        assert!(matches!(res.parameters[1..4], [
+            ElementParameter::StrTransf(0x8000),
+            ElementParameter::Magnification(m), ElementParameter::Angle(a)]
+            if m == 2. && a == 90.));
+        // the instances of the result equal the transformed instances
+        let a = res.instance_transform(0, 0).unwrap();
+        let b = t.compose(&sref.instance_transform(0, 0).unwrap());
+        assert_eq!(a.apply_point((3, 7)), b.apply_point((3, 7)));
+        let path = element(ElementType::Path, vec![
+            ElementParameter::Width(4), ElementParameter::Pathtype(4),
+            ElementParameter::BeginExt(3),
+            ElementParameter::XY(vec![(0, 0), (10, 0)])]);
+        let res = path.transformed(&t);
+        assert!(matches!(res.parameters[..3], [ElementParameter::Width(8),
+            ElementParameter::Pathtype(4), ElementParameter::BeginExt(6), ..]));
+        assert_eq!(res.xy().unwrap(), &[(1, 1), (1, 21)]);
+    }
+
+    #[test]
+    fn path_polygons() {
+        let path = element(ElementType::Path, vec![
+            ElementParameter::Width(4), ElementParameter::Pathtype(2),
+            ElementParameter::XY(vec![(0, 0), (10, 0), (10, 10)])]);
+        let polys = path.polygons();
+        assert_eq!(polys.len(), 3);
+        assert_eq!(polys[0], vec![(-2, -2), (10, -2), (10, 2), (-2, 2)]);
+        assert_eq!(polys[1], vec![(12, 0), (12, 12), (8, 12), (8, 0)]);
+        assert_eq!(polys[2], vec![(10, 0), (10, -2), (12, -2), (12, 0)]);
+        let round = element(ElementType::Path, vec![
+            ElementParameter::Width(4), ElementParameter::Pathtype(1),
+            ElementParameter::XY(vec![(0, 0), (10, 0)])]);
+        assert_eq!(round.polygons().len(), 3);
+        let text = element(ElementType::Text, vec![
+            ElementParameter::XY(vec![(0, 0)])]);
+        assert!(text.polygons().is_empty());
+    }
+
+    #[test]
+    fn split() {
+        let xy: Vec<(i32,i32)> = (0..10).map(|i| (i*10, i%2)).collect();
+        let path = element(ElementType::Path, vec![
+            ElementParameter::Width(1), ElementParameter::Pathtype(4),
+            ElementParameter::BeginExt(2), ElementParameter::XY(xy.clone())]);
+        let parts = path.split(4).unwrap();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0].xy().unwrap(), &xy[0..4]);
+        assert_eq!(parts[1].xy().unwrap(), &xy[3..7]);
+        assert_eq!(parts[2].xy().unwrap(), &xy[6..10]);
+        assert!(parts[1].parameters.iter().all(|p|
+            !matches!(*p, ElementParameter::BeginExt(_))));
+        let parts = path.split(10).unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].xy().unwrap(), &xy[..]);
+        let comb: Vec<(i32,i32)> = vec![(0, 0), (50, 0), (50, 20), (40, 20),
+            (40, 10), (30, 10), (30, 20), (20, 20), (20, 10), (10, 10),
+            (10, 20), (0, 20), (0, 0)];
+        let boundary = element(ElementType::Boundary, vec![
+            ElementParameter::Layer(1), ElementParameter::Datatype(0),
+            ElementParameter::XY(comb)]);
+        let parts = boundary.split(8).unwrap();
+        assert!(parts.iter().all(|e| e.xy().unwrap().len() <= 8));
+        let area: i64 = parts.iter().map(|e| {
+            let b = BoundingBox::from_points(e.xy().unwrap()).unwrap();
+            b.width()*b.height()
+        }).sum();
+        assert_eq!(area, 50*10 + 3*10*10);
+        let node = element(ElementType::Node, vec![ElementParameter::XY(
+            xy.clone())]);
+        assert_eq!(node.split(10).unwrap().len(), 1);
+        assert!(matches!(node.split(9), Err(Error::Overflow(_))));
+    }
+}
@@ -0,0 +1,402 @@
+//! Contains functions working on the hierarchy of structures in a library.
+
+use std::collections::{HashMap, HashSet};
+
+use {Element, ElementParameter, Error, Library, Structure};
+use geometry::Transform;
+
+impl Library {
+    /// Returns the structure `name` and all structures referenced by it.
+    ///
+    /// References are followed recursively. The structures are returned in
+    /// the order of the library. References to missing structures are
+    /// ignored.
+    pub fn subtree(&self, name: &str) -> Vec<&Structure> {
+        let index = self.structure_index();
+        let mut found: HashSet<&str> = HashSet::new();
+        let mut todo: Vec<&str> = vec![name];
+        while let Some(n) = todo.pop() {
+            if let Some(stru) = index.get(n) {
+                if found.insert(n) {
+                    todo.extend(stru.references());
+                }
+            }
+        }
+        self.structures.iter()
+            .filter(|s| found.contains(s.name.as_str())).collect()
+    }
+
+    /// Creates a flat copy of the structure `name`.
+    ///
+    /// All structure and array references are replaced by the transformed
+    /// elements of the referenced structures. Recursive references and
+    /// references to missing structures are dropped. Returns `None` if the
+    /// structure does not exist.
+    pub fn flatten(&self, name: &str) -> Option<Structure> {
+        let index = self.structure_index();
+        let stru = *index.get(name)?;
+        let mut res = Structure{name: stru.name.clone(),
+            date_mod: stru.date_mod.clone(), date_acc: stru.date_acc.clone(),
+            elements: Vec::new()};
+        let mut stack = vec![name];
+        flatten_into(&index, stru, None, &mut stack, &mut res.elements);
+        Some(res)
+    }
+
+    /// Removes all structures which are not reachable from the structures in
+    /// `keep_tops`.
+    ///
+    /// If `keep_tops` is empty, the current top structures are kept, which
+    /// only removes unreachable recursive structures. The names of the
+    /// removed structures are returned. An error is returned if one of the
+    /// requested structures does not exist, the library is not changed in
+    /// that case.
+    pub fn prune_unreferenced(&mut self, keep_tops: &[&str])
+            -> Result<Vec<String>, Error> {
+        let tops: Vec<String> = if keep_tops.is_empty() {
+            self.top_structures().iter().map(|s| s.name.clone()).collect()
+        } else {
+            keep_tops.iter().map(|s| s.to_string()).collect()
+        };
+        let mut keep: HashSet<String> = HashSet::new();
+        for top in &tops {
+            if self.structure(top).is_none() {
+                return Err(Error::MissingStructure(top.clone()));
+            }
+            keep.extend(self.subtree(top).iter().map(|s| s.name.clone()));
+        }
+        let mut removed = Vec::new();
+        self.structures.retain(|s| {
+            let k = keep.contains(&s.name);
+            if !k {
+                removed.push(s.name.clone());
+            }
+            k
+        });
+        Ok(removed)
+    }
+
+    /// Creates a new library containing the structure `name` and all
+    /// structures referenced by it.
+    ///
+    /// The header fields like name, dates and units are copied.
+    pub fn extract(&self, name: &str) -> Result<Library, Error> {
+        if self.structure(name).is_none() {
+            return Err(Error::MissingStructure(name.to_string()));
+        }
+        Ok(Library{version: self.version, name: self.name.clone(),
+            date_mod: self.date_mod.clone(), date_acc: self.date_acc.clone(),
+            units_user: self.units_user, units_m: self.units_m,
+            structures: self.subtree(name).into_iter().cloned().collect()})
+    }
+
+    /// Returns all structures sorted so that every structure is preceded by
+    /// the structures it references.
+    ///
+    /// Recursive references are ignored.
+    pub fn bottom_up(&self) -> Vec<&Structure> {
+        let index = self.structure_index();
+        let mut done: HashSet<*const Structure> = HashSet::new();
+        let mut res = Vec::new();
+        for stru in &self.structures {
+            // depth first search with an explicit stack of unvisited children
+            let mut stack: Vec<(&Structure, Vec<&str>)> = Vec::new();
+            if done.insert(stru) {
+                stack.push((stru, stru.references()));
+            }
+            while let Some(&mut (s, ref mut children)) = stack.last_mut() {
+                match children.pop() {
+                    Some(c) => if let Some(&child) = index.get(c) {
+                        if done.insert(child) {
+                            stack.push((child, child.references()));
+                        }
+                    },
+                    None => {
+                        res.push(s);
+                        stack.pop();
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    /// Renames the structure `old` to `new`.
+    ///
+    /// All references to the structure are updated. An error is returned if
+    /// `old` does not exist, `new` is not a valid name or already used by
+    /// another structure.
+    pub fn rename_structure(&mut self, old: &str, new: &str)
+            -> Result<(), Error> {
+        if self.structure(old).is_none() {
+            return Err(Error::MissingStructure(old.to_string()));
+        }
+        if old == new {
+            return Ok(());
+        }
+        let mut names = HashMap::new();
+        names.insert(old.to_string(), new.to_string());
+        self.rename_structures(&names)
+    }
+
+    /// Prepends `prefix` to the names of all structures except those in
+    /// `except`.
+    ///
+    /// All references are updated. If a new name is invalid or collides
+    /// with another structure, an error naming all affected structures is
+    /// returned and the library is not changed.
+    pub fn prefix_all(&mut self, prefix: &str, except: &[&str])
+            -> Result<(), Error> {
+        let names: HashMap<String, String> = self.structures.iter()
+            .filter(|s| !except.contains(&s.name.as_str()))
+            .map(|s| (s.name.clone(), format!("{}{}", prefix, s.name)))
+            .collect();
+        self.rename_structures(&names)
+    }
+
+    /// Renames structures according to `names` and updates all references.
+    ///
+    /// The new names are checked before anything is changed.
+    fn rename_structures(&mut self, names: &HashMap<String, String>)
+            -> Result<(), Error> {
+        let mut invalid: Vec<&str> = names.values()
+            .filter(|n| !is_valid_name(n)).map(|n| n.as_str()).collect();
+        if !invalid.is_empty() {
+            invalid.sort();
+            return Err(Error::InvalidValue(format!("invalid structure names {}",
+                invalid.join(", "))));
+        }
+        // number of structures using a name and whether one of them is renamed
+        let mut used: HashMap<&str, (usize, bool)> = HashMap::new();
+        for stru in &self.structures {
+            let new = names.get(&stru.name);
+            let entry = used.entry(new.unwrap_or(&stru.name))
+                .or_insert((0, false));
+            entry.0 += 1;
+            entry.1 |= new.is_some();
+        }
+        let mut collisions: Vec<&str> = used.iter()
+            .filter(|&(_, &(n, renamed))| n > 1 && renamed)
+            .map(|(&name, _)| name).collect();
+        if !collisions.is_empty() {
+            collisions.sort();
+            return Err(Error::DuplicateStructure(collisions.join(", ")));
+        }
+        for stru in &mut self.structures {
+            if let Some(n) = names.get(&stru.name) {
+                stru.name = n.clone();
+            }
+            stru.rename_references(names);
+        }
+        Ok(())
+    }
+
+    /// Returns a map from structure names to structures.
+    ///
+    /// For duplicate names the first structure is used.
+    pub(crate) fn structure_index(&self) -> HashMap<&str, &Structure> {
+        let mut index = HashMap::new();
+        for stru in &self.structures {
+            index.entry(stru.name.as_str()).or_insert(stru);
+        }
+        index
+    }
+}
+
+impl Structure {
+    /// Changes the names of referenced structures.
+    ///
+    /// Every StructureName parameter of a structure or array reference found
+    /// in `names` is replaced by the corresponding value.
+    pub fn rename_references(&mut self, names: &HashMap<String, String>) {
+        for elem in &mut self.elements {
+            if !elem.is_reference() {
+                continue;
+            }
+            for param in &mut elem.parameters {
+                if let ElementParameter::StructureName(ref mut x) = *param {
+                    if let Some(n) = names.get(x) {
+                        *x = n.clone();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns true if `name` is a valid structure name.
+///
+/// Valid names have between 1 and 32 characters consisting of letters,
+/// digits, `_`, `?` and `$`.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= 32 && name.chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '?' || c == '$')
+}
+
+fn flatten_into<'a>(index: &HashMap<&'a str, &'a Structure>,
+        stru: &'a Structure, t: Option<&Transform>, stack: &mut Vec<&'a str>,
+        out: &mut Vec<Element>) {
+    for elem in &stru.elements {
+        if !elem.is_reference() {
+            out.push(match t {
+                Some(t) => elem.transformed(t),
+                None => elem.clone()
+            });
+            continue;
+        }
+        let child = match elem.structure_name().and_then(|n| index.get(n)) {
+            Some(x) => *x,
+            None => continue
+        };
+        if stack.contains(&child.name.as_str()) {
+            continue;
+        }
+        stack.push(&child.name);
+        for inst in elem.instance_transforms() {
+            let inst = match t {
+                Some(t) => t.compose(&inst),
+                None => inst
+            };
+            flatten_into(index, child, Some(&inst), stack, out);
+        }
+        stack.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ElementType;
+    use testing::{element, library, rect, sref};
+
+    fn cells() -> Library {
+        library(vec![
+            ("A", vec![rect(1, 0, 0, 10, 10)]),
+            ("B", vec![sref("A", (0, 0)), element(ElementType::ArrayRef, vec![
+                ElementParameter::StructureName(String::from("A")),
+                ElementParameter::ColRow(vec![2, 1]),
+                ElementParameter::XY(vec![(0, 20), (40, 20), (0, 40)])])]),
+            ("TOP", vec![sref("B", (100, 0))]),
+            ("UNUSED", vec![sref("MISSING", (0, 0))])])
+    }
+
+    fn references(lib: &Library, name: &str) -> Vec<String> {
+        lib.structure(name).unwrap().elements.iter()
+            .filter_map(|e| e.structure_name()).map(|n| n.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn flatten() {
+        let lib = cells();
+        let flat = lib.flatten("TOP").unwrap();
+        assert_eq!(flat.name, "TOP");
+        let boxes: Vec<(i32,i32)> = flat.elements.iter()
+            .map(|e| e.xy().unwrap()[0]).collect();
+        assert_eq!(boxes, vec![(100, 0), (100, 20), (120, 20)]);
+        assert!(flat.elements.iter().all(|e| !e.is_reference()));
+        assert!(lib.flatten("UNUSED").unwrap().elements.is_empty());
+        assert!(lib.flatten("C").is_none());
+        let names: Vec<&str> = lib.subtree("TOP").iter()
+            .map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["A", "B", "TOP"]);
+    }
+
+    #[test]
+    fn bottom_up() {
+        let mut lib = cells();
+        lib.structures.reverse();
+        lib.structures.push(Structure{name: String::from("LOOP"),
+            elements: vec![sref("LOOP", (0, 0)), sref("A", (0, 0))],
+            ..Structure::new()});
+        let order: Vec<&str> = lib.bottom_up().iter()
+            .map(|s| s.name.as_str()).collect();
+        assert_eq!(order, vec!["UNUSED", "A", "B", "TOP", "LOOP"]);
+    }
+
+    #[test]
+    fn names() {
+        assert!(is_valid_name("CELL_1$?"));
+        assert!(is_valid_name(&"X".repeat(32)));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name(&"X".repeat(33)));
+        assert!(!is_valid_name("A-B"));
+        assert!(!is_valid_name("Ä"));
+    }
+
+    #[test]
+    fn rename() {
+        let mut lib = cells();
+        lib.rename_structure("A", "CORE").unwrap();
+        assert!(lib.structure("A").is_none());
+        assert_eq!(references(&lib, "B"), vec!["CORE", "CORE"]);
+        lib.rename_structure("CORE", "CORE").unwrap();
+        assert!(matches!(lib.rename_structure("A", "X"),
+            Err(Error::MissingStructure(_))));
+        assert!(matches!(lib.rename_structure("CORE", "B"),
+            Err(Error::DuplicateStructure(ref x)) if x == "B"));
+        assert!(matches!(lib.rename_structure("CORE", "NO NAME"),
+            Err(Error::InvalidValue(_))));
+        assert_eq!(references(&lib, "TOP"), vec!["B"]);
+    }
+
+    #[test]
+    fn prefix() {
+        let mut lib = cells();
+        lib.prefix_all("P_", &["TOP"]).unwrap();
+        let names: Vec<&str> = lib.structures.iter()
+            .map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["P_A", "P_B", "TOP", "P_UNUSED"]);
+        assert_eq!(references(&lib, "P_B"), vec!["P_A", "P_A"]);
+        assert_eq!(references(&lib, "TOP"), vec!["P_B"]);
+        // swapping names does not collide
+        lib.rename_structures(&[("P_A", "P_B"), ("P_B", "P_A")].iter()
+            .map(|&(a, b)| (a.to_string(), b.to_string())).collect())
+            .unwrap();
+        assert_eq!(references(&lib, "P_A"), vec!["P_B", "P_B"]);
+        lib.structures.push(Structure{name: String::from("Q_TOP"),
+            ..Structure::new()});
+        let before = lib.clone();
+        assert!(matches!(lib.prefix_all("Q_", &["Q_TOP"]),
+            Err(Error::DuplicateStructure(ref x)) if x == "Q_TOP"));
+        assert!(matches!(lib.prefix_all(&"X".repeat(30), &[]),
+            Err(Error::InvalidValue(ref x))
+            if x.contains("P_A") && x.contains("TOP")));
+        assert_eq!(format!("{:?}", lib.structures),
+            format!("{:?}", before.structures));
+    }
+
+    #[test]
+    fn prune() {
+        let mut lib = cells();
+        lib.structures.push(Structure{name: String::from("L1"),
+            elements: vec![sref("L2", (0, 0))], ..Structure::new()});
+        lib.structures.push(Structure{name: String::from("L2"),
+            elements: vec![sref("L1", (0, 0))], ..Structure::new()});
+        let before = lib.clone();
+        assert!(matches!(lib.prune_unreferenced(&["B", "MISSING"]),
+            Err(Error::MissingStructure(ref x)) if x == "MISSING"));
+        assert_eq!(lib.structures.len(), before.structures.len());
+        // the recursive structures are not top structures
+        assert_eq!(lib.prune_unreferenced(&[]).unwrap(), vec!["L1", "L2"]);
+        assert_eq!(lib.prune_unreferenced(&["B"]).unwrap(),
+            vec!["TOP", "UNUSED"]);
+        let names: Vec<&str> = lib.structures.iter()
+            .map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["A", "B"]);
+    }
+
+    #[test]
+    fn extract() {
+        let mut lib = cells();
+        lib.units_user = 1e-2;
+        lib.units_m = 1e-8;
+        let cell = lib.extract("B").unwrap();
+        assert_eq!(cell.name, lib.name);
+        assert_eq!((cell.units_user, cell.units_m), (1e-2, 1e-8));
+        let names: Vec<&str> = cell.structures.iter()
+            .map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["A", "B"]);
+        assert!(matches!(lib.extract("C"), Err(Error::MissingStructure(_))));
+    }
+}
@@ -0,0 +1,576 @@
+//! Contains a spatial index for fast region queries.
+//!
+//! The index is an R-tree built once from the bounding boxes of the elements
+//! using the sort-tile-recursive algorithm. It can be used for a single
+//! structure or for a whole hierarchy, in which case queries descend through
+//! the structure and array references without flattening.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use {Element, Error, Library, Structure};
+use geometry::{BoundingBox, Transform};
+
+/// Maximum number of children of a node.
+const NODE_SIZE: usize = 16;
+
+/// An R-tree storing values together with their bounding boxes.
+#[derive(Debug,Clone)]
+pub struct RTree<T> {
+    items: Vec<(BoundingBox, T)>,
+    nodes: Vec<Node>,
+    root: Option<usize>
+}
+
+#[derive(Debug,Clone)]
+struct Node {
+    bbox: BoundingBox,
+    /// True if the children are indices of items instead of nodes.
+    leaf: bool,
+    children: Vec<usize>
+}
+
+/// A node or item waiting to be visited by a nearest neighbour search.
+#[derive(Debug,Clone,Copy)]
+struct Candidate {
+    dist: f64,
+    item: bool,
+    index: usize
+}
+
+impl<T> RTree<T> {
+    /// Builds the tree from values and their bounding boxes.
+    pub fn new(items: Vec<(BoundingBox, T)>) -> RTree<T> {
+        let mut nodes = Vec::new();
+        let mut root = None;
+        let mut level: Vec<(BoundingBox, usize)> = items.iter().enumerate()
+            .map(|(i, x)| (x.0, i)).collect();
+        let mut leaf = true;
+        while !level.is_empty() {
+            level = pack(level, leaf, &mut nodes);
+            leaf = false;
+            if level.len() == 1 {
+                root = Some(level[0].1);
+                break;
+            }
+        }
+        RTree{items, nodes, root}
+    }
+
+    /// Returns the number of values.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns true if the tree does not contain any values.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns the bounding box of all values.
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        self.root.map(|r| self.nodes[r].bbox)
+    }
+
+    /// Returns all values whose bounding box intersects `window`.
+    ///
+    /// Touching bounding boxes are included. The values are returned in no
+    /// particular order.
+    pub fn window(&self, window: &BoundingBox) -> Vec<&T> {
+        let mut res = Vec::new();
+        let mut todo: Vec<usize> = self.root.into_iter().collect();
+        while let Some(n) = todo.pop() {
+            let node = &self.nodes[n];
+            if !node.bbox.intersects(window) {
+                continue;
+            }
+            if node.leaf {
+                res.extend(node.children.iter().map(|&i| &self.items[i])
+                    .filter(|x| x.0.intersects(window)).map(|x| &x.1));
+            } else {
+                todo.extend(&node.children);
+            }
+        }
+        res
+    }
+
+    /// Returns all values whose bounding box contains the point `p`.
+    pub fn point(&self, p: (i32,i32)) -> Vec<&T> {
+        self.window(&BoundingBox::new(p))
+    }
+
+    /// Returns the value whose bounding box is closest to the point `p`.
+    ///
+    /// Bounding boxes containing the point have a distance of zero, ties are
+    /// broken arbitrarily.
+    pub fn nearest(&self, p: (i32,i32)) -> Option<&T> {
+        let mut heap = BinaryHeap::new();
+        if let Some(r) = self.root {
+            heap.push(Candidate{dist: self.nodes[r].bbox.distance_squared(p),
+                item: false, index: r});
+        }
+        while let Some(c) = heap.pop() {
+            if c.item {
+                return Some(&self.items[c.index].1);
+            }
+            let node = &self.nodes[c.index];
+            for &i in &node.children {
+                let bbox = if node.leaf {
+                    &self.items[i].0
+                } else {
+                    &self.nodes[i].bbox
+                };
+                heap.push(Candidate{dist: bbox.distance_squared(p),
+                    item: node.leaf, index: i});
+            }
+        }
+        None
+    }
+}
+
+/// Groups the entries into nodes and returns the new nodes with their
+/// bounding boxes.
+fn pack(mut entries: Vec<(BoundingBox, usize)>, leaf: bool,
+        nodes: &mut Vec<Node>) -> Vec<(BoundingBox, usize)> {
+    let count = (entries.len() as f64/NODE_SIZE as f64).ceil() as usize;
+    let slices = (count as f64).sqrt().ceil() as usize;
+    entries.sort_by_key(|e| e.0.x_min as i64 + e.0.x_max as i64);
+    let mut res = Vec::new();
+    for slice in entries.chunks_mut(slices*NODE_SIZE) {
+        slice.sort_by_key(|e| e.0.y_min as i64 + e.0.y_max as i64);
+        for chunk in slice.chunks(NODE_SIZE) {
+            let bbox = chunk[1..].iter().fold(chunk[0].0, |b, e| b.union(&e.0));
+            nodes.push(Node{bbox, leaf,
+                children: chunk.iter().map(|e| e.1).collect()});
+            res.push((bbox, nodes.len() - 1));
+        }
+    }
+    res
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Candidate) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Candidate) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    /// Orders by decreasing distance so the heap returns the closest first.
+    fn cmp(&self, other: &Candidate) -> Ordering {
+        other.dist.partial_cmp(&self.dist).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl Structure {
+    /// Builds a spatial index of the elements.
+    ///
+    /// The values of the index are the positions of the elements in
+    /// `elements`. References and elements without coordinates are not
+    /// included, use [HierarchicalIndex](index/struct.HierarchicalIndex.html)
+    /// to query through references.
+    pub fn spatial_index(&self) -> RTree<usize> {
+        RTree::new(self.elements.iter().enumerate()
+            .filter_map(|(i, e)| e.bounding_box().map(|b| (b, i))).collect())
+    }
+}
+
+/// Instance of a reference on the way from the top structure to an element.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Instance {
+    /// Position of the reference in the elements of its structure.
+    pub element: usize,
+    /// Column of the array instance, zero for structure references.
+    pub col: i32,
+    /// Row of the array instance, zero for structure references.
+    pub row: i32
+}
+
+/// An element found by a hierarchical query.
+#[derive(Debug,Clone)]
+pub struct Hit<'a> {
+    /// The structure containing the element.
+    pub structure: &'a Structure,
+    /// The element in its own coordinates.
+    pub element: &'a Element,
+    /// Position of the element in the elements of its structure.
+    pub index: usize,
+    /// The references leading from the top structure to the element.
+    pub path: Vec<Instance>,
+    /// Transformation from the coordinates of the element to those of the
+    /// top structure.
+    pub transform: Transform,
+    /// Bounding box of the element in the coordinates of the top structure.
+    pub bbox: BoundingBox
+}
+
+/// A spatial index of a structure and all structures referenced by it.
+///
+/// Every structure is indexed once, references are resolved during the
+/// queries.
+#[derive(Debug,Clone)]
+pub struct HierarchicalIndex<'a> {
+    top: &'a Structure,
+    structures: HashMap<&'a str, (&'a Structure, RTree<usize>)>
+}
+
+impl<'a> HierarchicalIndex<'a> {
+    /// Builds the index of the structure `top` of `lib`.
+    ///
+    /// Recursive references and references to missing structures are
+    /// ignored.
+    pub fn new(lib: &'a Library, top: &str)
+            -> Result<HierarchicalIndex<'a>, Error> {
+        let top = lib.structure(top)
+            .ok_or_else(|| Error::MissingStructure(top.to_string()))?;
+        let mut cache = HashMap::new();
+        let mut structures = HashMap::new();
+        for stru in lib.subtree(&top.name) {
+            let tree = RTree::new(stru.elements.iter().enumerate()
+                .filter_map(|(i, e)| lib.element_bounding_box_cached(e,
+                    &mut cache).map(|b| (b, i))).collect());
+            structures.insert(stru.name.as_str(), (stru, tree));
+        }
+        Ok(HierarchicalIndex{top, structures})
+    }
+
+    /// Returns the bounding box of the top structure.
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        self.structures.get(self.top.name.as_str())?.1.bounding_box()
+    }
+
+    /// Returns all elements whose transformed bounding box intersects
+    /// `window`.
+    ///
+    /// The window is given in the coordinates of the top structure.
+    /// Elements inside array references are returned once for every
+    /// instance touching the window.
+    pub fn window(&self, window: &BoundingBox) -> Vec<Hit<'a>> {
+        let mut res = Vec::new();
+        let mut stack = vec![self.top.name.as_str()];
+        self.query(self.top, &Transform::identity(), window, &mut Vec::new(),
+            &mut stack, &mut res);
+        res
+    }
+
+    /// Returns all elements whose transformed bounding box contains the point
+    /// `p`.
+    pub fn point(&self, p: (i32,i32)) -> Vec<Hit<'a>> {
+        self.window(&BoundingBox::new(p))
+    }
+
+    /// Returns the element whose transformed bounding box is closest to the
+    /// point `p`.
+    ///
+    /// Bounding boxes containing the point have a distance of zero, ties are
+    /// broken arbitrarily.
+    pub fn nearest(&self, p: (i32,i32)) -> Option<Hit<'a>> {
+        let bbox = self.bounding_box()?;
+        // windows of growing size around the point, an element with a
+        // distance up to the half size of the window intersects it
+        let mut size: i64 = 1;
+        loop {
+            let grow = |x: i32, d: i64| (x as i64 + d)
+                .max(i32::MIN as i64).min(i32::MAX as i64) as i32;
+            let window = BoundingBox{x_min: grow(p.0, -size),
+                y_min: grow(p.1, -size), x_max: grow(p.0, size),
+                y_max: grow(p.1, size)};
+            let best = self.window(&window).into_iter().map(|h| {
+                let d = h.bbox.distance_squared(p);
+                (d, h)
+            }).min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+            let covered = window.x_min <= bbox.x_min &&
+                window.y_min <= bbox.y_min && window.x_max >= bbox.x_max &&
+                window.y_max >= bbox.y_max;
+            match best {
+                Some((d, h)) if d <= (size*size) as f64 || covered =>
+                    return Some(h),
+                None if covered => return None,
+                _ => size *= 2
+            }
+        }
+    }
+
+    fn query(&self, stru: &'a Structure, t: &Transform, window: &BoundingBox,
+            path: &mut Vec<Instance>, stack: &mut Vec<&'a str>,
+            out: &mut Vec<Hit<'a>>) {
+        let tree = match self.structures.get(stru.name.as_str()) {
+            Some(x) => &x.1,
+            None => return
+        };
+        let local = match t.inverse() {
+            Some(x) => x.apply_bbox(window),
+            None => return
+        };
+        for &i in tree.window(&local) {
+            let elem = &stru.elements[i];
+            if !elem.is_reference() {
+                let bbox = match elem.bounding_box() {
+                    Some(b) => t.apply_bbox(&b),
+                    None => continue
+                };
+                if bbox.intersects(window) {
+                    out.push(Hit{structure: stru, element: elem, index: i,
+                        path: path.clone(), transform: *t, bbox});
+                }
+                continue;
+            }
+            let (child, child_bbox) = match elem.structure_name()
+                    .and_then(|n| self.structures.get(n)) {
+                Some(&(s, ref tree)) => match tree.bounding_box() {
+                    Some(b) => (s, b),
+                    None => continue
+                },
+                None => continue
+            };
+            if stack.contains(&child.name.as_str()) {
+                continue;
+            }
+            let (cols, rows) = elem.array_size();
+            let first = match elem.instance_transform(0, 0) {
+                Some(x) => x,
+                None => continue
+            };
+            stack.push(&child.name);
+            let ((c0, c1), (r0, r1)) = instance_range(elem, cols, rows,
+                &first.apply_bbox(&child_bbox), &local);
+            for row in r0..r1 + 1 {
+                for col in c0..c1 + 1 {
+                    let inst = match elem.instance_transform(col, row) {
+                        Some(x) => t.compose(&x),
+                        None => continue
+                    };
+                    if !inst.apply_bbox(&child_bbox).intersects(window) {
+                        continue;
+                    }
+                    path.push(Instance{element: i, col, row});
+                    self.query(child, &inst, window, path, stack, out);
+                    path.pop();
+                }
+            }
+            stack.pop();
+        }
+    }
+}
+
+/// Returns the ranges of columns and rows of an array reference whose
+/// instances may intersect `window`.
+///
+/// `first` is the bounding box of the first instance, the window is given in
+/// the coordinates of the structure containing the reference. The window is
+/// transformed into the lattice coordinates of the array, so only
+/// overlapping instances are visited. The ranges are inclusive and empty if
+/// the start is larger than the end.
+fn instance_range(elem: &Element, cols: i32, rows: i32, first: &BoundingBox,
+        window: &BoundingBox) -> ((i32, i32), (i32, i32)) {
+    let all = ((0, cols - 1), (0, rows - 1));
+    let xy = match elem.xy() {
+        Some(x) if x.len() >= 3 => x,
+        _ => return all
+    };
+    let vector = |p: (i32,i32), n: i32| ((p.0 as f64 - xy[0].0 as f64)/
+        n as f64, (p.1 as f64 - xy[0].1 as f64)/n as f64);
+    let (u, v) = (vector(xy[1], cols), vector(xy[2], rows));
+    // offsets of instances relative to the first one which intersect the
+    // window
+    let corners = [
+        (window.x_min as f64 - first.x_max as f64,
+            window.y_min as f64 - first.y_max as f64),
+        (window.x_max as f64 - first.x_min as f64,
+            window.y_min as f64 - first.y_max as f64),
+        (window.x_min as f64 - first.x_max as f64,
+            window.y_max as f64 - first.y_min as f64),
+        (window.x_max as f64 - first.x_min as f64,
+            window.y_max as f64 - first.y_min as f64)];
+    if corners[3].0 < corners[0].0 || corners[3].1 < corners[0].1 {
+        return ((0, -1), (0, -1));
+    }
+    let range = |values: &[f64], n: i32| {
+        let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        // a small tolerance for rounding of the lattice coordinates
+        ((min - 1e-6).ceil().max(0.).min(n as f64) as i32,
+            (max + 1e-6).floor().min(n as f64 - 1.).max(-1.) as i32)
+    };
+    let det = u.0*v.1 - u.1*v.0;
+    if det != 0. {
+        let c: Vec<f64> = corners.iter().map(|d| (d.0*v.1 - d.1*v.0)/det)
+            .collect();
+        let r: Vec<f64> = corners.iter().map(|d| (u.0*d.1 - u.1*d.0)/det)
+            .collect();
+        return (range(&c, cols), range(&r, rows));
+    }
+    // degenerate lattice, project onto the single used direction
+    let project = |w: (f64, f64), n: i32| {
+        let len = w.0*w.0 + w.1*w.1;
+        if n == 1 {
+            Some((0, 0))
+        } else if len == 0. {
+            None
+        } else {
+            let t: Vec<f64> = corners.iter().map(|d| (d.0*w.0 + d.1*w.1)/len)
+                .collect();
+            Some(range(&t, n))
+        }
+    };
+    match (project(u, cols), project(v, rows)) {
+        (Some(c), Some(r)) if cols == 1 || rows == 1 => (c, r),
+        _ => all
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {ElementParameter, ElementType};
+    use testing::{boundary, element, library, sref};
+
+    /// Returns pseudo random numbers below `n`.
+    fn random(seed: &mut u64, n: i32) -> i32 {
+        *seed = seed.wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((*seed >> 33) % n as u64) as i32
+    }
+
+    fn boxes(seed: &mut u64, count: usize) -> Vec<BoundingBox> {
+        (0..count).map(|_| {
+            let (x, y) = (random(seed, 1000) - 500, random(seed, 1000) - 500);
+            BoundingBox{x_min: x, y_min: y, x_max: x + random(seed, 50),
+                y_max: y + random(seed, 50)}
+        }).collect()
+    }
+
+    #[test]
+    fn rtree_against_brute_force() {
+        let mut seed = 1;
+        let items = boxes(&mut seed, 500);
+        let tree = RTree::new(items.iter().cloned().enumerate()
+            .map(|(i, b)| (b, i)).collect());
+        assert_eq!(tree.len(), 500);
+        for window in boxes(&mut seed, 50) {
+            let mut found: Vec<usize> = tree.window(&window).into_iter()
+                .cloned().collect();
+            found.sort();
+            let expected: Vec<usize> = (0..items.len())
+                .filter(|&i| items[i].intersects(&window)).collect();
+            assert_eq!(found, expected);
+
+            let p = (window.x_min*2, window.y_max*2);
+            let best = items.iter().map(|b| b.distance_squared(p))
+                .fold(f64::INFINITY, f64::min);
+            let nearest = *tree.nearest(p).unwrap();
+            assert_eq!(items[nearest].distance_squared(p), best);
+        }
+        assert!(RTree::<usize>::new(Vec::new()).nearest((0, 0)).is_none());
+    }
+
+    fn triangle(x: i32, y: i32, size: i32) -> Element {
+        boundary(1, vec![(x, y), (x + size, y), (x + size, y + 2*size),
+            (x, y), (x, y)])
+    }
+
+    fn reference(name: &str, angle: f64, cols: i16, rows: i16,
+            xy: Vec<(i32,i32)>) -> Element {
+        let mut parameters = vec![
+            ElementParameter::StructureName(name.to_string()),
+            ElementParameter::StrTransf(0x8000),
+            ElementParameter::Angle(angle)];
+        let element_type = if xy.len() == 3 {
+            parameters.push(ElementParameter::ColRow(vec![cols, rows]));
+            ElementType::ArrayRef
+        } else {
+            ElementType::StructureRef
+        };
+        parameters.push(ElementParameter::XY(xy));
+        element(element_type, parameters)
+    }
+
+    fn cells() -> Library {
+        library(vec![
+            ("TOP", vec![triangle(0, 0, 10),
+                // skewed lattice
+                reference("CELL", 90., 40, 30,
+                    vec![(100, 100), (500, 300), (-200, 700)]),
+                // single row without row vector
+                reference("CELL", 180., 50, 1,
+                    vec![(-1000, -500), (0, -500), (-1000, -500)]),
+                reference("CELL", 270., 1, 1, vec![(-300, 900)])]),
+            ("CELL", vec![triangle(1, 2, 3), triangle(-4, 0, 1),
+                reference("LEAF", 0., 1, 1, vec![(5, -5)])]),
+            ("LEAF", vec![triangle(0, 0, 2)])])
+    }
+
+    #[test]
+    fn hierarchy_against_flat() {
+        let lib = cells();
+        let flat: Vec<BoundingBox> = lib.flatten("TOP").unwrap().elements
+            .iter().filter_map(|e| e.bounding_box()).collect();
+        let index = HierarchicalIndex::new(&lib, "TOP").unwrap();
+        let mut seed = 7;
+        for _ in 0..100 {
+            let (x, y) = (random(&mut seed, 3000) - 1500,
+                random(&mut seed, 3000) - 1500);
+            let window = BoundingBox{x_min: x, y_min: y,
+                x_max: x + random(&mut seed, 300),
+                y_max: y + random(&mut seed, 300)};
+            let mut found: Vec<BoundingBox> = index.window(&window)
+                .into_iter().map(|h| h.bbox).collect();
+            let mut expected: Vec<BoundingBox> = flat.iter().cloned()
+                .filter(|b| b.intersects(&window)).collect();
+            let key = |b: &BoundingBox| (b.x_min, b.y_min, b.x_max, b.y_max);
+            found.sort_by_key(key);
+            expected.sort_by_key(key);
+            assert_eq!(found, expected);
+
+            let p = (x*2, y*2);
+            let best = flat.iter().map(|b| b.distance_squared(p))
+                .fold(f64::INFINITY, f64::min);
+            assert_eq!(index.nearest(p).unwrap().bbox.distance_squared(p),
+                best);
+        }
+        // only the instances near the window are visited
+        let top = &lib.structures[0];
+        let first = top.elements[1].instance_transform(0, 0).unwrap()
+            .apply_bbox(&BoundingBox{x_min: -4, y_min: -5, x_max: 7,
+                y_max: 8});
+        let p = top.elements[1].instance_transform(5, 7).unwrap()
+            .apply_point((0, 0));
+        let ((c0, c1), (r0, r1)) = instance_range(&top.elements[1], 40, 30,
+            &first, &BoundingBox::new(p));
+        assert!(c0 <= 5 && c1 >= 5 && c1 - c0 < 3);
+        assert!(r0 <= 7 && r1 >= 7 && r1 - r0 < 3);
+        let hits = index.point((1, 2));
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].path.is_empty());
+        assert!(HierarchicalIndex::new(&lib, "MISSING").is_err());
+    }
+
+    #[test]
+    fn malformed_references() {
+        // an array without lattice vectors followed by a valid reference
+        let lib = library(vec![
+            ("TOP", vec![element(ElementType::ArrayRef, vec![
+                    ElementParameter::StructureName(String::from("LEAF")),
+                    ElementParameter::ColRow(vec![2, 2]),
+                    ElementParameter::XY(vec![(0, 0)])]),
+                sref("LEAF", (0, 0))]),
+            ("LEAF", vec![triangle(0, 0, 2)])]);
+        let mut index = HierarchicalIndex::new(&lib, "TOP").unwrap();
+        // the array has no bounding box, so it is indexed with the one of the
+        // valid reference to be visited by the query
+        let bbox = index.bounding_box().unwrap();
+        index.structures.get_mut("TOP").unwrap().1 =
+            RTree::new(vec![(bbox, 0), (bbox, 1)]);
+        let hits = index.point((1, 1));
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].path.len(), 1);
+        assert_eq!(hits[0].path[0].element, 1);
+    }
+}
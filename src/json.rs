@@ -0,0 +1,180 @@
+//! Contains an export of libraries into JSON.
+//!
+//! The library is written as an object containing the header information and
+//! an array of structures. Every element is an object with its type and one
+//! key per parameter named like the corresponding GDS record:
+//!
+//! ```text
+//! {"type": "boundary", "layer": 1, "datatype": 0, "xy": [[0, 0], [10, 0]]}
+//! ```
+
+use std::io::Write;
+
+use {Date, Element, ElementParameter, ElementType, Error, Library};
+
+impl Library {
+    /// Writes the library as JSON to `w`.
+    pub fn write_json<W: Write>(&self, w: &mut W) -> Result<(), Error> {
+        writeln!(w, "{{")?;
+        writeln!(w, "  \"name\": {},", quote(&self.name))?;
+        writeln!(w, "  \"version\": {},", self.version)?;
+        writeln!(w, "  \"date_mod\": {},", date(&self.date_mod))?;
+        writeln!(w, "  \"date_acc\": {},", date(&self.date_acc))?;
+        writeln!(w, "  \"units_user\": {},", number(self.units_user))?;
+        writeln!(w, "  \"units_m\": {},", number(self.units_m))?;
+        writeln!(w, "  \"structures\": [")?;
+        for (i, stru) in self.structures.iter().enumerate() {
+            writeln!(w, "    {{")?;
+            writeln!(w, "      \"name\": {},", quote(&stru.name))?;
+            writeln!(w, "      \"date_mod\": {},", date(&stru.date_mod))?;
+            writeln!(w, "      \"date_acc\": {},", date(&stru.date_acc))?;
+            writeln!(w, "      \"elements\": [")?;
+            for (j, elem) in stru.elements.iter().enumerate() {
+                let sep = if j + 1 < stru.elements.len() { "," } else { "" };
+                writeln!(w, "        {}{}", element(elem), sep)?;
+            }
+            writeln!(w, "      ]")?;
+            let sep = if i + 1 < self.structures.len() { "," } else { "" };
+            writeln!(w, "    }}{}", sep)?;
+        }
+        writeln!(w, "  ]")?;
+        writeln!(w, "}}")?;
+        Ok(())
+    }
+}
+
+/// Returns `s` as quoted JSON string.
+///
+/// Control characters are escaped, so the result fits on a single line. The
+/// text representation uses the same quoting.
+pub fn quote(s: &str) -> String {
+    let mut res = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            c if (c as u32) < 0x20 =>
+                res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c)
+        }
+    }
+    res.push('"');
+    res
+}
+
+/// Returns `x` as JSON number.
+///
+/// Values which can not be represented in JSON result in `null`.
+pub fn number(x: f64) -> String {
+    if x.is_finite() {
+        format!("{:?}", x)
+    } else {
+        String::from("null")
+    }
+}
+
+fn date(d: &Date) -> String {
+    format!("[{}, {}, {}, {}, {}, {}]", d.year, d.month, d.day, d.hour,
+        d.minute, d.second)
+}
+
+fn element(elem: &Element) -> String {
+    let t = match elem.element_type {
+        ElementType::None => "none",
+        ElementType::Boundary => "boundary",
+        ElementType::Path => "path",
+        ElementType::StructureRef => "sref",
+        ElementType::ArrayRef => "aref",
+        ElementType::Text => "text",
+        ElementType::Node => "node",
+        ElementType::Box => "box"
+    };
+    let mut res = format!("{{\"type\": \"{}\"", t);
+    for param in &elem.parameters {
+        let (key, value) = match *param {
+            ElementParameter::Layer(x) => ("layer", x.to_string()),
+            ElementParameter::XY(ref x) => ("xy", format!("[{}]",
+                x.iter().map(|p| format!("[{}, {}]", p.0, p.1))
+                .collect::<Vec<_>>().join(", "))),
+            ElementParameter::Datatype(x) => ("datatype", x.to_string()),
+            ElementParameter::Width(x) => ("width", x.to_string()),
+            ElementParameter::StructureName(ref x) => ("sname", quote(x)),
+            ElementParameter::ColRow(ref x) => ("colrow", format!("[{}]",
+                x.iter().map(|c| c.to_string())
+                .collect::<Vec<_>>().join(", "))),
+            ElementParameter::TextType(x) => ("texttype", x.to_string()),
+            ElementParameter::Presentation(x) =>
+                ("presentation", x.to_string()),
+            ElementParameter::String(ref x) => ("string", quote(x)),
+            ElementParameter::StrTransf(x) => ("strans", x.to_string()),
+            ElementParameter::Magnification(x) => ("mag", number(x)),
+            ElementParameter::Angle(x) => ("angle", number(x)),
+            ElementParameter::Pathtype(x) => ("pathtype", x.to_string()),
+            ElementParameter::EFlags(x) => ("eflags", x.to_string()),
+            ElementParameter::Nodetype(x) => ("nodetype", x.to_string()),
+            ElementParameter::Boxtype(x) => ("boxtype", x.to_string()),
+            ElementParameter::BeginExt(x) => ("bgnextn", x.to_string())
+        };
+        res += &format!(", \"{}\": {}", key, value);
+    }
+    res.push('}');
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::{element, library, rect, sref};
+
+    #[test]
+    fn values() {
+        assert_eq!(quote("a\"b\\c\nd\u{1}é"), "\"a\\\"b\\\\c\\nd\\u0001é\"");
+        assert_eq!(number(1.), "1.0");
+        assert_eq!(number(1e-9), "1e-9");
+        assert_eq!(number(f64::NAN), "null");
+        assert_eq!(number(f64::INFINITY), "null");
+    }
+
+    #[test]
+    fn export() {
+        let mut rotated = sref("A", (5, 5));
+        rotated.parameters.insert(1, ElementParameter::StrTransf(0));
+        rotated.parameters.insert(2, ElementParameter::Angle(90.));
+        let mut lib = library(vec![("A", vec![rect(1, 0, 0, 10, 10)]),
+            ("TOP", vec![rotated, element(ElementType::Text, vec![
+                ElementParameter::Layer(2), ElementParameter::TextType(0),
+                ElementParameter::XY(vec![(0, 0)]),
+                ElementParameter::String(String::from("say \"hi\""))])])]);
+        lib.date_mod = Date{year: 2020, month: 1, day: 2, hour: 3, minute: 4,
+            second: 5};
+        let mut buf = Vec::new();
+        lib.write_json(&mut buf).unwrap();
+        let s = String::from_utf8(buf).unwrap();
+        assert!(s.contains("\"date_mod\": [2020, 1, 2, 3, 4, 5],\n"));
+        assert!(s.contains("\"units_m\": 1e-9,\n"));
+        assert!(s.contains("        {\"type\": \"boundary\", \"layer\": 1, \
+            \"datatype\": 0, \"xy\": [[0, 0], [10, 0], [10, 10], [0, 10], \
+            [0, 0]]}\n"));
+        assert!(s.contains("        {\"type\": \"sref\", \"sname\": \"A\", \
+            \"strans\": 0, \"angle\": 90.0, \"xy\": [[5, 5]]},\n"));
+        assert!(s.contains("\"string\": \"say \\\"hi\\\"\"}\n"));
+        assert!(s.ends_with("    }\n  ]\n}\n"));
+        // brackets are balanced outside of strings
+        let mut depth = 0;
+        let mut in_string = false;
+        let mut escaped = false;
+        for c in s.chars() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = !in_string,
+                '{' | '[' if !in_string => depth += 1,
+                '}' | ']' if !in_string => depth -= 1,
+                _ => {}
+            }
+            assert!(depth >= 0);
+        }
+        assert_eq!((depth, in_string), (0, false));
+    }
+}
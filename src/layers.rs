@@ -0,0 +1,276 @@
+//! Contains the renumbering and filtering of layers.
+//!
+//! A layer map is a list of rules, the first rule matching the layer and
+//! datatype of an element decides what happens with it. Elements not matching
+//! any rule are kept unchanged. The text format contains one rule per line:
+//!
+//! ```text
+//! # renumber a single datatype
+//! 31/0 -> 16/0
+//! # renumber all datatypes of layer 5, keeping the datatypes
+//! 5 -> 6
+//! # remove internal layers
+//! 63/* -> drop
+//! # keep layer 1 unchanged and remove everything else
+//! 1/0
+//! * -> drop
+//! ```
+//!
+//! A source layer or datatype of `*` matches any value, a missing source
+//! datatype matches any datatype. A line without target keeps the matching
+//! elements unchanged.
+
+use std::io::BufRead;
+
+use {ElementParameter, Error, Library};
+
+/// A single rule of a layer map.
+#[derive(Debug,Clone,PartialEq)]
+pub struct LayerRule {
+    /// Layer matched by the rule, `None` matches all layers.
+    pub layer: Option<i16>,
+    /// Datatype matched by the rule, `None` matches all datatypes.
+    pub datatype: Option<i16>,
+    /// New layer and datatype of the matching elements or `None` if they are
+    /// removed. A datatype of `None` keeps the datatype of the element.
+    pub target: Option<(i16, Option<i16>)>
+}
+
+/// An ordered list of rules renumbering or removing layers.
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct LayerMap {
+    /// The rules in the order they are checked.
+    pub rules: Vec<LayerRule>
+}
+
+impl LayerRule {
+    /// Returns true if the rule applies to `layer` and `datatype`.
+    pub fn matches(&self, layer: i16, datatype: i16) -> bool {
+        self.layer.unwrap_or(layer) == layer &&
+            self.datatype.unwrap_or(datatype) == datatype
+    }
+}
+
+impl LayerMap {
+    /// Creates an empty layer map keeping all elements unchanged.
+    pub fn new() -> LayerMap {
+        LayerMap::default()
+    }
+
+    /// Creates a layer map keeping only the given layers.
+    ///
+    /// Every entry is a layer with an optional datatype, a missing datatype
+    /// keeps all datatypes of the layer.
+    pub fn keep_only(layers: &[(i16, Option<i16>)]) -> LayerMap {
+        let mut res = LayerMap::new();
+        for &(layer, datatype) in layers {
+            res.rules.push(LayerRule{layer: Some(layer), datatype,
+                target: Some((layer, datatype))});
+        }
+        res.rules.push(LayerRule{layer: None, datatype: None, target: None});
+        res
+    }
+
+    /// Adds a rule moving `layer`/`datatype` to `new_layer`/`new_datatype`.
+    ///
+    /// A datatype of `None` matches or keeps all datatypes.
+    pub fn remap(&mut self, layer: i16, datatype: Option<i16>, new_layer: i16,
+            new_datatype: Option<i16>) {
+        self.rules.push(LayerRule{layer: Some(layer), datatype,
+            target: Some((new_layer, new_datatype))});
+    }
+
+    /// Adds a rule removing `layer`/`datatype`.
+    ///
+    /// A datatype of `None` removes all datatypes of the layer.
+    pub fn remove(&mut self, layer: i16, datatype: Option<i16>) {
+        self.rules.push(LayerRule{layer: Some(layer), datatype, target: None});
+    }
+
+    /// Returns the new layer and datatype for `layer` and `datatype`.
+    ///
+    /// Returns `None` if elements on the layer are removed.
+    pub fn map(&self, layer: i16, datatype: i16) -> Option<(i16,i16)> {
+        match self.rules.iter().find(|r| r.matches(layer, datatype)) {
+            Some(r) => r.target.map(|(l, d)| (l, d.unwrap_or(datatype))),
+            None => Some((layer, datatype))
+        }
+    }
+
+    /// Parses a layer map in the text format described in the
+    /// [module documentation](index.html).
+    pub fn parse(s: &str) -> Result<LayerMap, Error> {
+        let mut res = LayerMap::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = match line.find('#') {
+                Some(p) => &line[..p],
+                None => line
+            }.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let rule = parse_rule(line).map_err(
+                |e| Error::Parse(format!("line {}: {}", i + 1, e)))?;
+            res.rules.push(rule);
+        }
+        Ok(res)
+    }
+
+    /// Reads a layer map in text format from `r`.
+    pub fn read<R: BufRead>(r: &mut R) -> Result<LayerMap, Error> {
+        let mut s = String::new();
+        r.read_to_string(&mut s)?;
+        LayerMap::parse(&s)
+    }
+}
+
+fn parse_rule(line: &str) -> Result<LayerRule, String> {
+    let mut parts = line.splitn(2, "->");
+    let source = parts.next().unwrap_or("").trim();
+    let mut items = source.splitn(2, '/');
+    let layer = parse_pattern(items.next().unwrap_or(""))?;
+    let datatype = match items.next() {
+        Some(x) => parse_pattern(x)?,
+        None => None
+    };
+    let target = match parts.next().map(|x| x.trim()) {
+        None => match layer {
+            Some(l) => Some((l, datatype)),
+            None => return Err(format!("missing target for {}", source))
+        },
+        Some("drop") => None,
+        Some(x) => {
+            let mut items = x.splitn(2, '/');
+            let l = parse_number(items.next().unwrap_or(""))?;
+            let d = match items.next() {
+                Some(x) => Some(parse_number(x)?),
+                None => None
+            };
+            Some((l, d))
+        }
+    };
+    Ok(LayerRule{layer, datatype, target})
+}
+
+fn parse_pattern(s: &str) -> Result<Option<i16>, String> {
+    match s.trim() {
+        "*" => Ok(None),
+        x => parse_number(x).map(Some)
+    }
+}
+
+fn parse_number(s: &str) -> Result<i16, String> {
+    s.trim().parse().map_err(|_| format!("invalid layer number {:?}", s))
+}
+
+impl Library {
+    /// Renumbers and removes layers according to `map`.
+    ///
+    /// Boundaries, paths, boxes, texts and nodes are changed, references are
+    /// kept. The datatype of texts is their TextType, that of nodes their
+    /// Nodetype and that of boxes their Boxtype. Missing layers and datatypes
+    /// are treated as zero and not added. Returns the number of removed
+    /// elements.
+    pub fn map_layers(&mut self, map: &LayerMap) -> usize {
+        let mut removed = 0;
+        for stru in &mut self.structures {
+            let before = stru.elements.len();
+            stru.elements.retain(|e| e.is_reference() ||
+                map.map(e.layer().unwrap_or(0), e.datatype().unwrap_or(0))
+                    .is_some());
+            removed += before - stru.elements.len();
+            for elem in &mut stru.elements {
+                if elem.is_reference() {
+                    continue;
+                }
+                let (layer, datatype) = match map.map(
+                        elem.layer().unwrap_or(0),
+                        elem.datatype().unwrap_or(0)) {
+                    Some(x) => x,
+                    None => continue
+                };
+                for param in &mut elem.parameters {
+                    match *param {
+                        ElementParameter::Layer(ref mut x) => *x = layer,
+                        ElementParameter::Datatype(ref mut x) |
+                        ElementParameter::TextType(ref mut x) |
+                        ElementParameter::Nodetype(ref mut x) |
+                        ElementParameter::Boxtype(ref mut x) => *x = datatype,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ElementType;
+    use testing::{element, library, rect, sref};
+
+    #[test]
+    fn parse() {
+        let map = LayerMap::parse("# comment\n31/0 -> 16/0\n5 -> 6\n\n\
+            63/* -> drop # internal\n1/0\n* -> drop\n").unwrap();
+        assert_eq!(map.rules, vec![
+            LayerRule{layer: Some(31), datatype: Some(0),
+                target: Some((16, Some(0)))},
+            LayerRule{layer: Some(5), datatype: None, target: Some((6, None))},
+            LayerRule{layer: Some(63), datatype: None, target: None},
+            LayerRule{layer: Some(1), datatype: Some(0),
+                target: Some((1, Some(0)))},
+            LayerRule{layer: None, datatype: None, target: None}]);
+        assert_eq!(map.map(31, 0), Some((16, 0)));
+        assert_eq!(map.map(5, 7), Some((6, 7)));
+        assert_eq!(map.map(63, 2), None);
+        assert_eq!(map.map(1, 0), Some((1, 0)));
+        assert_eq!(map.map(1, 1), None);
+        let map = LayerMap::read(&mut "2 -> 3/4".as_bytes()).unwrap();
+        assert_eq!(map.map(2, 1), Some((3, 4)));
+        for s in &["*", "1 -> x", "1/a", "70000 -> 1", "1 -> 2/"] {
+            assert!(matches!(LayerMap::parse(s), Err(Error::Parse(_))), "{}",
+                s);
+        }
+        assert!(matches!(LayerMap::parse("1\n\n2 -> -"),
+            Err(Error::Parse(ref x)) if x.starts_with("line 3:")));
+    }
+
+    #[test]
+    fn rules() {
+        let mut map = LayerMap::new();
+        assert_eq!(map.map(4, 2), Some((4, 2)));
+        map.remap(4, Some(2), 8, None);
+        map.remove(4, None);
+        assert_eq!(map.map(4, 2), Some((8, 2)));
+        assert_eq!(map.map(4, 3), None);
+        let keep = LayerMap::keep_only(&[(1, None), (2, Some(0))]);
+        assert_eq!(keep.map(1, 5), Some((1, 5)));
+        assert_eq!(keep.map(2, 0), Some((2, 0)));
+        assert_eq!(keep.map(2, 1), None);
+    }
+
+    #[test]
+    fn map_layers() {
+        let mut lib = library(vec![("A", vec![rect(1, 0, 0, 10, 10),
+            rect(2, 0, 0, 10, 10), element(ElementType::Text, vec![
+                ElementParameter::Layer(1), ElementParameter::TextType(3),
+                ElementParameter::XY(vec![(0, 0)]),
+                ElementParameter::String(String::from("PAD"))]),
+            sref("B", (0, 0))])]);
+        let mut map = LayerMap::new();
+        map.remap(1, None, 10, Some(1));
+        map.remove(2, None);
+        assert_eq!(lib.map_layers(&map), 1);
+        let elements = &lib.structures[0].elements;
+        assert_eq!(elements.len(), 3);
+        assert_eq!((elements[0].layer(), elements[0].datatype()),
+            (Some(10), Some(1)));
+        assert_eq!((elements[1].layer(), elements[1].datatype()),
+            (Some(10), Some(1)));
+        assert!(elements[2].is_reference());
+        assert_eq!(lib.map_layers(&LayerMap::keep_only(&[])), 2);
+    }
+}
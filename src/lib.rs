@@ -3,13 +3,47 @@
 //! **Not all element and parameter types are implemented yet.**
 
 extern crate byteorder;
+#[cfg(feature = "chrono")]
+extern crate chrono;
 
+pub mod arrays;
+pub mod boolean;
+pub mod builder;
+pub mod connectivity;
 pub mod constants;
+pub mod date;
+pub mod dedup;
+pub mod density;
+pub mod diff;
+pub mod error;
+pub mod fill;
+pub mod font;
+pub mod fracture;
+pub mod generators;
+pub mod geometry;
+pub mod hierarchy;
+pub mod index;
+pub mod json;
+pub mod layers;
+pub mod merge;
+pub mod normalize;
+pub mod polygon;
+pub mod svg;
+pub mod text;
+pub mod units;
 pub mod utils;
+pub mod validate;
 
+#[cfg(test)]
+mod testing;
+
+pub use error::Error;
+
+use std::collections::HashSet;
 use std::fmt;
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::iter;
 use byteorder::{ByteOrder, BigEndian};
 
 /// A structure representing a GDS file.
@@ -35,7 +69,7 @@ pub struct Library {
 /// A structure representing a date in a GDS file.
 ///
 /// The year numbering starts at 0 A.D..
-#[derive(Debug,Clone)]
+#[derive(Debug,Clone,PartialEq)]
 pub struct Date {
     pub year: i16,
     pub month: i16,
@@ -142,12 +176,56 @@ pub enum ElementParameter {
     EFlags(u16),
     /// Type of the node element.
     Nodetype(i16),
+    /// Type of the box element.
+    Boxtype(i16),
     /// Extension of the first point of the path. Is used in conjunction with
     /// pathtype 4.
     BeginExt(i32)
     //TODO more parameters
 }
 
+/// Handling of elements with more points than fit into a single XY record.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum XyPolicy {
+    /// Return an error.
+    Error,
+    /// Split the element. Boundaries are decomposed into trapezoids, paths
+    /// into consecutive pieces. Other elements result in an error.
+    Split,
+    /// Write several consecutive XY records. This violates the specification
+    /// but is accepted by many tools.
+    MultiRecord
+}
+
+/// Settings for writing libraries.
+#[derive(Debug,Clone,PartialEq)]
+pub struct WriteOptions {
+    /// Handling of elements with too many points.
+    pub xy: XyPolicy,
+    /// Modification date written instead of the one of the library, for
+    /// example [Date::now](struct.Date.html#method.now) to record the time
+    /// of writing.
+    pub date_mod: Option<Date>
+}
+
+impl Default for WriteOptions {
+    fn default() -> WriteOptions {
+        WriteOptions{xy: XyPolicy::Error, date_mod: None}
+    }
+}
+
+/// Handling of elements with several XY records when reading.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum XyReadPolicy {
+    /// Return an error.
+    Error,
+    /// Join the points of all XY records. This reads files written with
+    /// [XyPolicy::MultiRecord](enum.XyPolicy.html).
+    Join,
+    /// Keep the points of the first XY record and ignore the others.
+    First
+}
+
 /// A structure describing a data record in a GDS file.
 ///
 /// This type should normally not used manually as the gds file can be read in
@@ -208,7 +286,9 @@ impl fmt::Display for Library {
 impl Date {
     /// Creates new date object.
     ///
-    /// The returned date contains the default value of 01.01.1970 00:00:00.
+    /// The returned date contains the default value of 01.01.1970 00:00:00,
+    /// the start of the Unix epoch. Use [now](#method.now) for the current
+    /// date.
     pub fn new() -> Date {
         Date{year: 1970, month: 1, day: 1, hour: 0, minute: 0, second: 0}
     }
@@ -220,24 +300,65 @@ impl Library {
     /// Version `v` and name `n` have to be given. The other values are
     /// initialized with default values:
     ///
-    /// * `date_mod` - 01.01.1970 00:00:00
-    /// * `date_acc` - 01.01.1970 00:00:00
+    /// * `date_mod` - the current date
+    /// * `date_acc` - the current date
     /// * `units_user` - 0
     /// * `units_m` - 0
     /// * `structures` - empty
     pub fn new(v: i16, n: String) -> Library{
-        Library{version: v, name: n, date_mod: Date::new(),
-            date_acc: Date::new(), units_user: 0., units_m: 0.,
-            structures: Vec::new()}
+        let now = Date::now();
+        Library{version: v, name: n, date_mod: now.clone(), date_acc: now,
+            units_user: 0., units_m: 0., structures: Vec::new()}
     }
 
     /// Read library from file.
     ///
     /// This function will read the Library from the file given by its filename
-    /// `s`. Specifing a wrong designed file will not result in any errors or
-    /// security problem but in a useless Library object.
-    pub fn read(s: &str) -> Library {
-        let mut file = File::open(s).unwrap();
+    /// `s`. An error is returned if the file can not be read or does not
+    /// contain a complete library. Several XY records of an element are
+    /// joined, see [read_with](#method.read_with).
+    pub fn read(s: &str) -> Result<Library, Error> {
+        Library::read_with(s, XyReadPolicy::Join)
+    }
+
+    /// Read library from file handling elements with several XY records
+    /// using `policy`.
+    pub fn read_with(s: &str, policy: XyReadPolicy) -> Result<Library, Error> {
+        let mut file = BufReader::new(File::open(s)?);
+        Library::from_reader_with(&mut file, policy)
+    }
+
+    /// Read library from a reader.
+    ///
+    /// The records are read from `r` until the end of the library is reached.
+    /// Several XY records of an element are joined.
+    pub fn from_reader<R: Read>(r: &mut R) -> Result<Library, Error> {
+        Library::from_reader_with(r, XyReadPolicy::Join)
+    }
+
+    /// Read library from a reader handling elements with several XY records
+    /// using `policy`.
+    pub fn from_reader_with<R: Read>(r: &mut R, policy: XyReadPolicy)
+            -> Result<Library, Error> {
+        Library::from_records_with(iter::repeat_with(|| Record::read(r)),
+            policy)
+    }
+
+    /// Creates a library from a sequence of records.
+    ///
+    /// The records are consumed until the ENDLIB record is found. The first
+    /// record has to be the HEADER record. Several XY records of an element
+    /// are joined.
+    pub fn from_records<I>(records: I) -> Result<Library, Error>
+            where I: IntoIterator<Item=Result<Record, Error>> {
+        Library::from_records_with(records, XyReadPolicy::Join)
+    }
+
+    /// Creates a library from a sequence of records handling elements with
+    /// several XY records using `policy`.
+    pub fn from_records_with<I>(records: I, policy: XyReadPolicy)
+            -> Result<Library, Error>
+            where I: IntoIterator<Item=Result<Record, Error>> {
         let mut version = 0;
         let mut name: String = String::from("");
         let mut date_mod = Date::new();
@@ -247,10 +368,18 @@ impl Library {
         let mut structures: Vec<Structure> = Vec::new();
         let mut stru = Structure::new();
         let mut elem = Element::new();
+        let mut first = true;
+        let mut complete = false;
 
-        loop {
-            let rec = Record::read(&mut file);
+        for rec in records {
+            let rec = rec?;
+            if first && rec.rec_type != constants::REC_TYPE_HEADER {
+                return Err(Error::Parse(String::from(
+                    "library does not start with a HEADER record")));
+            }
+            first = false;
             if rec.rec_type == constants::REC_TYPE_ENDLIB {
+                complete = true;
                 break;
             } else if rec.rec_type == constants::REC_TYPE_BGNLIB {
                 let mut d_data = [0; 12];
@@ -260,12 +389,8 @@ impl Library {
                         _ => 0
                     };
                 }
-                date_mod = Date{year: d_data[0], month: d_data[1],
-                    day: d_data[2], hour: d_data[3], minute: d_data[4],
-                    second: d_data[5]};
-                date_acc = Date{year: d_data[6], month: d_data[7],
-                    day: d_data[8], hour: d_data[9], minute: d_data[10],
-                    second: d_data[11]};
+                date_mod = Date::from_fields(&d_data[..6]);
+                date_acc = Date::from_fields(&d_data[6..]);
             } else if rec.rec_type == constants::REC_TYPE_HEADER {
                 version = match rec.data.get(0) {
                     Some(&RecordData::Int16(x)) => x,
@@ -293,12 +418,8 @@ impl Library {
                         _ => 0
                     };
                 }
-                stru.date_mod = Date{year: d_data[0],
-                    month: d_data[1], day: d_data[2], hour: d_data[3],
-                    minute: d_data[4], second: d_data[5]};
-                stru.date_acc = Date{year: d_data[6],
-                    month: d_data[7], day: d_data[8], hour: d_data[9],
-                    minute: d_data[10], second: d_data[11]};
+                stru.date_mod = Date::from_fields(&d_data[..6]);
+                stru.date_acc = Date::from_fields(&d_data[6..]);
             } else if rec.rec_type == constants::REC_TYPE_ENDSTR {
                 structures.push(stru);
                 stru = Structure::new();
@@ -331,7 +452,7 @@ impl Library {
             } else if rec.rec_type == constants::REC_TYPE_XY {
                 let mut c = 0;
                 let mut xy_vec: Vec<(i32,i32)> = Vec::new();
-                while c < (rec.data.len() - 1) {
+                while c + 1 < rec.data.len() {
                     let mut x_coord: i32 = 0;
                     let mut y_coord: i32 = 0;
                     match rec.data.get(c) {
@@ -345,7 +466,20 @@ impl Library {
                     c += 2;
                     xy_vec.push((x_coord,y_coord));
                 }
-                elem.parameters.push(ElementParameter::XY(xy_vec));
+                let prev = elem.parameters.iter_mut().filter_map(|p| match *p {
+                    ElementParameter::XY(ref mut x) => Some(x),
+                    _ => None
+                }).next();
+                match (prev, policy) {
+                    (None, _) =>
+                        elem.parameters.push(ElementParameter::XY(xy_vec)),
+                    (Some(x), XyReadPolicy::Join) => x.extend(xy_vec),
+                    (Some(_), XyReadPolicy::First) => {},
+                    (Some(_), XyReadPolicy::Error) =>
+                        return Err(Error::Parse(format!(
+                            "several XY records in element {} of structure {}",
+                            stru.elements.len(), stru.name)))
+                }
             } else if rec.rec_type == constants::REC_TYPE_DATATYPE {
                 match rec.data.get(0) {
                     Some(&RecordData::Int16(x)) => elem.parameters.push(
@@ -367,7 +501,7 @@ impl Library {
             } else if rec.rec_type == constants::REC_TYPE_COLROW {
                 let mut c = 0;
                 let mut cr_vec: Vec<i16> = Vec::new();
-                while c < (rec.data.len() - 1) {
+                while c < rec.data.len() {
                     let mut colrow: i16 = 0;
                     match rec.data.get(c) {
                         Some(&RecordData::Int16(x)) => colrow = x,
@@ -431,6 +565,10 @@ impl Library {
                         ElementParameter::Nodetype(x)),
                         _ => {}
                 };
+            } else if rec.rec_type == constants::REC_TYPE_BOXTYPE {
+                if let Some(&RecordData::Int16(x)) = rec.data.first() {
+                    elem.parameters.push(ElementParameter::Boxtype(x));
+                }
             } else if rec.rec_type == constants::REC_TYPE_BGNEXTN {
                 match rec.data.get(0) {
                     Some(&RecordData::Int32(x)) => elem.parameters.push(
@@ -444,18 +582,121 @@ impl Library {
             }
         }
 
-        Library{version: version, name: name, date_mod: date_mod,
+        if !complete {
+            return Err(Error::Parse(String::from("missing ENDLIB record")));
+        }
+
+        Ok(Library{version: version, name: name, date_mod: date_mod,
             date_acc: date_acc, units_user: units_user, units_m: units_m,
-            structures: structures}
+            structures: structures})
     }
 
     /// Write library object to file.
     ///
     /// The library object will be written to the filed specified by its
-    /// filename `s`.
-    pub fn write(&self, s: &str) {
-        println!("Writing to {}",s);
-        let mut file = File::create(s).unwrap();
+    /// filename `s`. Elements with too many points result in an error, see
+    /// [write_with](#method.write_with).
+    pub fn write(&self, s: &str) -> Result<(), Error> {
+        self.write_with(s, &WriteOptions::default())
+    }
+
+    /// Write library object to file using `options`.
+    pub fn write_with(&self, s: &str, options: &WriteOptions)
+            -> Result<(), Error> {
+        let mut file = BufWriter::new(File::create(s)?);
+        self.to_writer_with(&mut file, options)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Write library object to a writer.
+    ///
+    /// Elements with too many points result in an error.
+    pub fn to_writer<W: Write>(&self, w: &mut W) -> Result<(), Error> {
+        self.to_writer_with(w, &WriteOptions::default())
+    }
+
+    /// Write library object to a writer using `options`.
+    ///
+    /// Nothing is written if an error is detected before writing. Elements
+    /// without element type result in an error.
+    pub fn to_writer_with<W: Write>(&self, w: &mut W, options: &WriteOptions)
+            -> Result<(), Error> {
+        self.check_element_types()?;
+        let large = |e: &Element| match e.xy() {
+            Some(x) => x.len() > constants::MAX_POINTS,
+            None => false
+        };
+        let overflow = |stru: &Structure, i: usize| Error::Overflow(format!(
+            "{} points in element {} of structure {}",
+            stru.elements[i].xy().map_or(0, |x| x.len()), i, stru.name));
+        let split;
+        let lib = match options.xy {
+            XyPolicy::MultiRecord => self,
+            XyPolicy::Error => {
+                for stru in &self.structures {
+                    if let Some(i) = stru.elements.iter().position(large) {
+                        return Err(overflow(stru, i));
+                    }
+                }
+                self
+            },
+            XyPolicy::Split => {
+                let mut lib = self.clone();
+                for stru in &mut lib.structures {
+                    if !stru.elements.iter().any(large) {
+                        continue;
+                    }
+                    let mut elements = Vec::new();
+                    for (i, e) in stru.elements.iter().enumerate() {
+                        match e.split(constants::MAX_POINTS) {
+                            Ok(x) => elements.extend(x),
+                            Err(_) => return Err(overflow(stru, i))
+                        }
+                    }
+                    stru.elements = elements;
+                }
+                split = lib;
+                &split
+            }
+        };
+        let mut records = lib.to_records();
+        if let Some(ref date) = options.date_mod {
+            // the BGNLIB record follows the HEADER record
+            let mut data = date.to_record_data();
+            data.extend(lib.date_acc.to_record_data());
+            records[1] = Record::new(constants::REC_TYPE_BGNLIB,
+                constants::DATA_TYPE_INT16, data);
+        }
+        for rec in records {
+            rec.write(w)?;
+        }
+        Ok(())
+    }
+
+    /// Returns an error if an element has no element type.
+    ///
+    /// Such elements can not be written, their first record would be a
+    /// HEADER record.
+    pub(crate) fn check_element_types(&self) -> Result<(), Error> {
+        for stru in &self.structures {
+            let untyped = stru.elements.iter()
+                .position(|e| matches!(e.element_type, ElementType::None));
+            if let Some(i) = untyped {
+                return Err(Error::InvalidValue(format!(
+                    "unknown type of element {} of structure {}", i,
+                    stru.name)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a vector of records.
+    ///
+    /// Returns all records describing the library in the order they appear in
+    /// a GDS file. Points not fitting into a single XY record are written
+    /// into several consecutive XY records.
+    pub fn to_records(&self) -> Vec<Record> {
         let mut vec: Vec<Record> = Vec::new();
 
         // header
@@ -496,9 +737,30 @@ impl Library {
 
         // tail
         vec.push(Record::new_none(constants::REC_TYPE_ENDLIB));
+        vec
+    }
+
+    /// Returns the structure with the name `name`.
+    ///
+    /// If several structures share the same name, the first one is returned.
+    pub fn structure(&self, name: &str) -> Option<&Structure> {
+        self.structures.iter().find(|s| s.name == name)
+    }
 
-        // write file
-        let _ = vec.iter().map(|x| x.write(&mut file)).collect::<Vec<_>>();
+    /// Returns all structures which are not referenced by any other structure.
+    ///
+    /// The structures are returned in the order of the library.
+    pub fn top_structures(&self) -> Vec<&Structure> {
+        let mut referenced: HashSet<&str> = HashSet::new();
+        for stru in &self.structures {
+            for name in stru.references() {
+                if name != stru.name {
+                    referenced.insert(name);
+                }
+            }
+        }
+        self.structures.iter()
+            .filter(|s| !referenced.contains(s.name.as_str())).collect()
     }
 }
 
@@ -526,10 +788,13 @@ impl Record {
     ///
     /// The record will be constructed using the given record type `rec_type`,
     /// the data type `data_type` and a vector of RecordData objects `data`.
-    /// The size is calculated automatically.
+    /// The size is calculated automatically, data not fitting into a record
+    /// results in an error when [writing](#method.write) the record.
     pub fn new(rec_type: u8, data_type: u8, data: Vec<RecordData>) -> Record {
-        let size: u16 = 4+(constants::data_size(data_type)*data.len()) as u16;
-        Record{size: size, rec_type: rec_type, data_type: data_type, data: data}
+        let mut rec = Record{size: 4, rec_type: rec_type,
+            data_type: data_type, data: data};
+        rec.update_size().ok();
+        rec
     }
 
     /// Creates new record object with no data.
@@ -547,109 +812,114 @@ impl Record {
     ///
     /// The record will be constructed using the given record type `rec_type`,
     /// the data type `data_type` and a single RecordData object `data`.
-    /// The size is calculated automatically.
+    /// The size is calculated automatically, data not fitting into a record
+    /// results in an error when [writing](#method.write) the record.
     pub fn new_single(rec_type: u8, data_type: u8, data: RecordData) -> Record {
-        let mut rec = Record{size: 0, rec_type: rec_type,
-            data_type: data_type, data: vec![data]};
-        rec.update_size();
-        rec
+        Record::new(rec_type, data_type, vec![data])
     }
 
     /// Pushes new data to record.
     ///
     /// The `data` is be pushed to the records data vector and the size is
-    /// recalculated.
-    pub fn push_data(&mut self, data: RecordData) {
+    /// recalculated. An error is returned if the data does not fit into the
+    /// record, the data is pushed nevertheless.
+    pub fn push_data(&mut self, data: RecordData) -> Result<(), Error> {
         self.data.push(data);
-        self.update_size();
+        self.update_size()
     }
 
     /// Calculates new size.
     ///
     /// This function calculates the current size of the record data. This
     /// function is called automatically so that manually invoking is not
-    /// necessary.
-    pub fn update_size(&mut self) {
-        self.size = 4;
+    /// necessary. Strings are padded to an even length. If the data does not
+    /// fit into a record, `Error::Overflow` is returned and the size is not
+    /// changed.
+    pub fn update_size(&mut self) -> Result<(), Error> {
+        let mut size = 4;
         if self.data_type == constants::DATA_TYPE_STR {
             for i in &self.data {
-                match i {
-                    &RecordData::Str(ref x) => self.size += x.len() as u16,
-                    _ => {}
+                if let RecordData::Str(ref x) = *i {
+                    size += x.len() + x.len() % 2;
                 }
             }
         } else {
-            self.size += (constants::data_size(self.data_type)*self.data.len())
-                as u16;
+            size += constants::data_size(self.data_type)*self.data.len();
         }
+        if size > u16::MAX as usize {
+            return Err(Error::Overflow(format!(
+                "{} bytes in record type 0x{:02x}", size, self.rec_type)));
+        }
+        self.size = size as u16;
+        Ok(())
     }
 
     /// Read record from file specified by `file`.
-    pub fn read(file: &mut File) -> Record {
-        let mut buffer = [0; 2];
-        let _ = file.read(&mut buffer);
-        let size = BigEndian::read_u16(&buffer);
-        let _ = file.read(&mut buffer);
-        let rec_type = buffer[0];
-        let data_type = buffer[1];
+    ///
+    /// Trailing NUL characters of strings are removed and invalid UTF-8
+    /// sequences are replaced by the replacement character.
+    pub fn read<R: Read>(file: &mut R) -> Result<Record, Error> {
+        let mut buffer = [0; 4];
+        file.read_exact(&mut buffer)?;
+        let size = BigEndian::read_u16(&buffer[0..2]);
+        let rec_type = buffer[2];
+        let data_type = buffer[3];
+        if size < 4 {
+            return Err(Error::Parse(format!(
+                "invalid size {} of record type 0x{:02x}", size, rec_type)));
+        }
+        let mut bytes = vec![0; size as usize - 4];
+        file.read_exact(&mut bytes)?;
         let mut data: Vec<RecordData> = Vec::new();
-        let mut byte_counter: u16 = 4;
-        let mut buffer = [0;1];
 
         if data_type == constants::DATA_TYPE_STR {
-            let mut str_buf: Vec<u8> = Vec::new();
-            loop {
-                let _ = file.read(&mut buffer);
-                str_buf.push(buffer[0]);
-                byte_counter += 1;
-                if byte_counter == size {break;}
+            while bytes.last() == Some(&0) {
+                bytes.pop();
             }
-            data.push(RecordData::Str(String::from_utf8(str_buf.to_owned())
-                                      .unwrap()));
+            data.push(RecordData::Str(
+                String::from_utf8_lossy(&bytes).into_owned()));
         } else if data_type != constants::DATA_TYPE_NONE {
             let data_size = constants::data_size(data_type);
-            let mut buffer_arr = [0;constants::MAX_DATA_SIZE];
-            loop {
-                for i in 0..data_size {
-                    let _ = file.read(&mut buffer);
-                    buffer_arr[i] = buffer[0];
+            if data_size == 0 {
+                return Err(Error::Parse(format!(
+                    "unknown data type 0x{:02x}", data_type)));
+            }
+            // incomplete trailing data is ignored
+            for chunk in bytes.chunks(data_size) {
+                if chunk.len() < data_size {
+                    break;
                 }
                 match data_type {
                     x if x == constants::DATA_TYPE_BIT =>
                         data.push(RecordData::Bit(
-                        BigEndian::read_u16(&buffer_arr[0..2]))),
+                        BigEndian::read_u16(chunk))),
                     x if x == constants::DATA_TYPE_INT16 =>
                         data.push(RecordData::Int16(
-                        BigEndian::read_i16(&buffer_arr[0..2]))),
+                        BigEndian::read_i16(chunk))),
                     x if x == constants::DATA_TYPE_INT32 =>
                         data.push(RecordData::Int32(
-                        BigEndian::read_i32(&buffer_arr[0..4]))),
+                        BigEndian::read_i32(chunk))),
                     x if x == constants::DATA_TYPE_REAL32 =>
                         data.push(RecordData::Real32(
-                        utils::bytes_to_gds_real32(&buffer_arr[0..4]))),
+                        utils::bytes_to_gds_real32(chunk))),
                     x if x == constants::DATA_TYPE_REAL64 =>
                         data.push(RecordData::Real64(
-                        utils::bytes_to_gds_real(&buffer_arr[0..8]))),
+                        utils::bytes_to_gds_real(chunk))),
                     _ => {},
                 }
-                byte_counter += data_size as u16;
-                if byte_counter == size {break;}
-                if byte_counter + (data_size as u16) > size {
-                    let mut buffer = [0;1];
-                    for _ in 0..(size-byte_counter) {
-                        let _ = file.read(&mut buffer);
-                    }
-                    break;
-                }
             }
         }
 
-        Record{size: size, rec_type: rec_type, data_type: data_type,
-            data: data}
+        Ok(Record{size: size, rec_type: rec_type, data_type: data_type,
+            data: data})
     }
 
     /// Write contents of the record to the file specified by `file`.
-    pub fn write(&self, file: &mut File) {
+    ///
+    /// Strings of odd length are padded with a NUL character. Records with
+    /// more data than fits into a record result in an error, nothing is
+    /// written in that case.
+    pub fn write<W: Write>(&self, file: &mut W) -> Result<(), Error> {
         let mut buf: Vec<u8> = Vec::new();
         buf.extend(utils::u16_to_vec(self.size));
         buf.push(self.rec_type);
@@ -663,11 +933,21 @@ impl Record {
                     buf.extend(utils::gds_real_32_to_bytes(x).to_vec()),
                 &RecordData::Real64(x) =>
                     buf.extend(utils::gds_real_to_bytes(x).to_vec()),
-                &RecordData::Str(ref x) => buf.extend(x.clone().into_bytes()),
+                &RecordData::Str(ref x) => {
+                    buf.extend(x.as_bytes());
+                    if x.len() % 2 != 0 {
+                        buf.push(0);
+                    }
+                },
                 _ => {}
             }
         }
-        let _ = file.write(&buf);
+        if buf.len() > u16::MAX as usize {
+            return Err(Error::Overflow(format!(
+                "{} bytes in record type 0x{:02x}", buf.len(), self.rec_type)));
+        }
+        file.write_all(&buf)?;
+        Ok(())
     }
 
 }
@@ -685,6 +965,21 @@ impl Structure {
         Structure{name: String::from(""), elements: Vec::new(),
             date_mod: Date::new(), date_acc: Date::new()}
     }
+
+    /// Returns the names of all structures referenced by this structure.
+    ///
+    /// Every name is contained only once, in the order of first appearance.
+    pub fn references(&self) -> Vec<&str> {
+        let mut res: Vec<&str> = Vec::new();
+        for elem in &self.elements {
+            if let Some(name) = elem.structure_name() {
+                if elem.is_reference() && !res.contains(&name) {
+                    res.push(name);
+                }
+            }
+        }
+        res
+    }
 }
 
 impl Element {
@@ -697,6 +992,50 @@ impl Element {
         Element{element_type: ElementType::None, parameters: Vec::new()}
     }
 
+    /// Returns the layer of the element if present.
+    pub fn layer(&self) -> Option<i16> {
+        self.parameters.iter().filter_map(|p| match *p {
+            ElementParameter::Layer(x) => Some(x),
+            _ => None
+        }).next()
+    }
+
+    /// Returns the data type of the element if present.
+    ///
+    /// Depending on the element type this is the value of the Datatype, the
+    /// TextType, the Nodetype or the Boxtype parameter.
+    pub fn datatype(&self) -> Option<i16> {
+        self.parameters.iter().filter_map(|p| match *p {
+            ElementParameter::Datatype(x) => Some(x),
+            ElementParameter::TextType(x) => Some(x),
+            ElementParameter::Nodetype(x) => Some(x),
+            ElementParameter::Boxtype(x) => Some(x),
+            _ => None
+        }).next()
+    }
+
+    /// Returns the coordinates of the element if present.
+    pub fn xy(&self) -> Option<&[(i32,i32)]> {
+        self.parameters.iter().filter_map(|p| match *p {
+            ElementParameter::XY(ref x) => Some(x.as_slice()),
+            _ => None
+        }).next()
+    }
+
+    /// Returns the name of the referenced structure if present.
+    pub fn structure_name(&self) -> Option<&str> {
+        self.parameters.iter().filter_map(|p| match *p {
+            ElementParameter::StructureName(ref x) => Some(x.as_str()),
+            _ => None
+        }).next()
+    }
+
+    /// Returns true if the element is a structure or an array reference.
+    pub fn is_reference(&self) -> bool {
+        matches!(self.element_type,
+            ElementType::StructureRef | ElementType::ArrayRef)
+    }
+
     /// Creates an vector of records.
     ///
     /// Returns a vector of records which can be used for writing the content
@@ -720,13 +1059,21 @@ impl Element {
                     constants::REC_TYPE_LAYER, constants::DATA_TYPE_INT16,
                     RecordData::Int16(x))),
                 &ElementParameter::XY(ref x) => {
-                        let mut xy_data: Vec<RecordData> = Vec::new();
-                        for &(x_coord,y_coord) in x {
-                            xy_data.push(RecordData::Int32(x_coord));
-                            xy_data.push(RecordData::Int32(y_coord));
+                        // too many points are written into several records
+                        let mut chunks: Vec<&[(i32,i32)]> =
+                            x.chunks(constants::MAX_POINTS).collect();
+                        if chunks.is_empty() {
+                            chunks.push(&[]);
+                        }
+                        for chunk in chunks {
+                            let mut xy_data: Vec<RecordData> = Vec::new();
+                            for &(x_coord,y_coord) in chunk {
+                                xy_data.push(RecordData::Int32(x_coord));
+                                xy_data.push(RecordData::Int32(y_coord));
+                            }
+                            res.push(Record::new(constants::REC_TYPE_XY,
+                                constants::DATA_TYPE_INT32,xy_data));
                         }
-                        res.push(Record::new(constants::REC_TYPE_XY,
-                            constants::DATA_TYPE_INT32,xy_data));
                     },
                 &ElementParameter::Datatype(x) => res.push(Record::new_single(
                     constants::REC_TYPE_DATATYPE, constants::DATA_TYPE_INT16,
@@ -772,6 +1119,9 @@ impl Element {
                 &ElementParameter::Nodetype(x) => res.push(Record::new_single(
                     constants::REC_TYPE_NODETYPE, constants::DATA_TYPE_INT16,
                     RecordData::Int16(x))),
+                &ElementParameter::Boxtype(x) => res.push(Record::new_single(
+                    constants::REC_TYPE_BOXTYPE, constants::DATA_TYPE_INT16,
+                    RecordData::Int16(x))),
                 &ElementParameter::BeginExt(x) => res.push(Record::new_single(
                     constants::REC_TYPE_BGNEXTN, constants::DATA_TYPE_INT32,
                     RecordData::Int32(x)))
@@ -782,3 +1132,177 @@ impl Element {
         res
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(rec: &Record) -> &str {
+        match rec.data.first() {
+            Some(RecordData::Str(x)) => x,
+            _ => panic!("no string in {:?}", rec)
+        }
+    }
+
+    #[test]
+    fn string_padding() {
+        for &(s, bytes) in &[("TOP", &b"\x00\x08\x06\x06TOP\x00"[..]),
+                ("CELL", &b"\x00\x08\x06\x06CELL"[..]),
+                ("", &b"\x00\x04\x06\x06"[..])] {
+            let rec = Record::new_single(constants::REC_TYPE_STRNAME,
+                constants::DATA_TYPE_STR, RecordData::Str(s.to_string()));
+            let mut buf = Vec::new();
+            rec.write(&mut buf).unwrap();
+            assert_eq!(buf, bytes);
+            assert_eq!(rec.size as usize, buf.len());
+            let read = Record::read(&mut &buf[..]).unwrap();
+            assert_eq!(string(&read), s);
+        }
+
+        // the size of a record is limited to 65535 bytes
+        let mut rec = Record::new_single(constants::REC_TYPE_STRING,
+            constants::DATA_TYPE_STR, RecordData::Str("x".repeat(65530)));
+        assert_eq!(rec.size, 65534);
+        assert!(matches!(rec.push_data(RecordData::Str(String::from("y"))),
+            Err(Error::Overflow(_))));
+        assert_eq!(rec.size, 65534);
+        let mut buf = Vec::new();
+        assert!(rec.write(&mut buf).is_err());
+        let rec = Record::new_single(constants::REC_TYPE_STRING,
+            constants::DATA_TYPE_STR, RecordData::Str("x".repeat(70000)));
+        assert!(matches!(rec.write(&mut buf), Err(Error::Overflow(_))));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn string_decoding() {
+        let read = |bytes: &[u8]| Record::read(&mut &bytes[..]).unwrap();
+        // padding and trailing NULs written by other tools are removed
+        assert_eq!(string(&read(b"\x00\x0a\x06\x06CELL\x00\x00")), "CELL");
+        assert_eq!(string(&read(b"\x00\x08\x19\x06A\x00B\x00")), "A\0B");
+        // invalid UTF-8 is replaced instead of rejected
+        for &rec_type in &[constants::REC_TYPE_STRNAME,
+                constants::REC_TYPE_STRING, constants::REC_TYPE_PROPVALUE] {
+            let bytes = [0x00, 0x08, rec_type, 0x06, b'c', b'a', b'f', 0xe9];
+            assert_eq!(string(&read(&bytes)), "caf\u{fffd}");
+        }
+    }
+
+    #[test]
+    fn foreign_file() {
+        // written byte by byte following the GDSII specification: odd
+        // length names padded with NUL, Latin-1 names and strings, a
+        // property and a file padded to 2048 bytes
+        let bytes = include_bytes!("../tests/data/foreign.gds");
+        let lib = Library::from_reader(&mut &bytes[..]).unwrap();
+        assert_eq!(lib.version, 600);
+        assert_eq!(lib.name, "FIX.DB");
+        let d = &lib.date_mod;
+        assert_eq!((d.year, d.month, d.day, d.hour, d.minute, d.second),
+            (2023, 5, 17, 9, 30, 0));
+        assert_eq!(lib.units_user, 1e-3);
+        assert_eq!(lib.units_m, 1e-9);
+        let names: Vec<&str> = lib.structures.iter()
+            .map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["CELL\u{fffd}", "TOP"]);
+        let cell = &lib.structures[0].elements;
+        assert_eq!(cell.len(), 1);
+        assert_eq!(cell[0].xy().unwrap().len(), 5);
+        let top = &lib.structures[1].elements;
+        assert!(matches!(top[0].parameters[0],
+            ElementParameter::StructureName(ref x) if x == "CELL\u{fffd}"));
+        assert!(matches!(top[1].parameters[3],
+            ElementParameter::String(ref x) if x == "5 \u{fffd}m"));
+        assert!(lib.validate().iter().all(|d| d.message.contains("character")));
+
+        // the library is written back with even record lengths
+        let mut buf = Vec::new();
+        lib.to_writer(&mut buf).unwrap();
+        let read = Library::from_reader(&mut &buf[..]).unwrap();
+        assert_eq!(read.structures.len(), 2);
+        assert_eq!(read.structures[0].name, "CELL\u{fffd}");
+    }
+
+    fn large_library() -> Library {
+        let n = constants::MAX_POINTS as i32 + 10;
+        let mut xy: Vec<(i32,i32)> = (0..n).map(|i| (i, i % 2)).collect();
+        let path = testing::element(ElementType::Path, vec![
+            ElementParameter::Layer(1), ElementParameter::Datatype(0),
+            ElementParameter::Width(1), ElementParameter::XY(xy.clone())]);
+        xy.extend(vec![(n - 1, 10), (0, 10), (0, 0)]);
+        testing::library(vec![("TOP", vec![path, testing::boundary(1, xy)])])
+    }
+
+    #[test]
+    fn large_elements() {
+        let lib = large_library();
+        let n = constants::MAX_POINTS + 10;
+        let mut buf = Vec::new();
+        assert!(lib.to_writer(&mut buf).is_err());
+        assert!(buf.is_empty());
+
+        let options = |xy| WriteOptions{xy, ..WriteOptions::default()};
+        lib.to_writer_with(&mut buf, &options(XyPolicy::MultiRecord)).unwrap();
+        let read = |policy| Library::from_reader_with(&mut &buf[..], policy);
+        let joined = read(XyReadPolicy::Join).unwrap();
+        let elements = &joined.structures[0].elements;
+        assert_eq!(elements[0].xy().unwrap(),
+            lib.structures[0].elements[0].xy().unwrap());
+        assert_eq!(elements[1].xy().unwrap().len(), n + 3);
+        let first = read(XyReadPolicy::First).unwrap();
+        assert_eq!(first.structures[0].elements[0].xy().unwrap().len(),
+            constants::MAX_POINTS);
+        assert!(read(XyReadPolicy::Error).is_err());
+
+        let mut buf = Vec::new();
+        lib.to_writer_with(&mut buf, &options(XyPolicy::Split)).unwrap();
+        let split = Library::from_reader_with(&mut &buf[..],
+            XyReadPolicy::Error).unwrap();
+        let elements = &split.structures[0].elements;
+        let paths = elements.iter()
+            .filter(|e| matches!(e.element_type, ElementType::Path)).count();
+        assert_eq!(paths, 2);
+        assert!(elements.iter()
+            .all(|e| e.xy().unwrap().len() <= constants::MAX_POINTS));
+        let area = |e: &Element| boolean::area(&boolean::union(
+            &[e.xy().unwrap().to_vec()]));
+        let total: f64 = elements.iter()
+            .filter(|e| matches!(e.element_type, ElementType::Boundary))
+            .map(&area).sum();
+        assert!((total - area(&lib.structures[0].elements[1])).abs() < 1e-6);
+
+        // texts, nodes and boxes can not be split
+        let mut lib = lib;
+        lib.structures[0].elements[0].element_type = ElementType::Node;
+        let mut buf = Vec::new();
+        assert!(matches!(lib.to_writer_with(&mut buf,
+                &options(XyPolicy::Split)),
+            Err(Error::Overflow(ref m))
+            if m.ends_with("in element 0 of structure TOP")));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn dates() {
+        let mut lib = Library::new(600, String::from("LIB"));
+        assert!(lib.date_mod.validate().is_ok());
+        assert_eq!(lib.date_mod, lib.date_acc);
+        lib.date_mod = Date::new();
+        lib.date_acc = Date::new();
+        let read = |lib: &Library, options: &WriteOptions| {
+            let mut buf = Vec::new();
+            lib.to_writer_with(&mut buf, options).unwrap();
+            Library::from_reader(&mut &buf[..]).unwrap()
+        };
+        assert_eq!(read(&lib, &WriteOptions::default()).date_mod,
+            Date::new());
+        let date = Date{year: 2023, month: 5, day: 17, hour: 9, minute: 30,
+            second: 0};
+        let options = WriteOptions{date_mod: Some(date.clone()),
+            ..WriteOptions::default()};
+        let touched = read(&lib, &options);
+        assert_eq!(touched.date_mod, date);
+        assert_eq!(touched.date_acc, Date::new());
+        assert_eq!(lib.date_mod, Date::new());
+    }
+}
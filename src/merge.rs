@@ -0,0 +1,188 @@
+//! Contains the merging of libraries.
+
+use std::collections::{HashMap, HashSet};
+
+use {Error, Library, Structure};
+use diff::content_key;
+use hierarchy::is_valid_name;
+
+/// Policies for structures with a name already used in the library.
+#[derive(Debug,Clone,PartialEq)]
+pub enum MergePolicy {
+    /// Keep the existing structure. References of imported structures will
+    /// point to the existing one.
+    Skip,
+    /// Rename the imported structure by appending the given suffix. If the
+    /// new name is also used, a number is appended as well. New names which
+    /// are not [valid](../hierarchy/fn.is_valid_name.html) result in an
+    /// error.
+    Rename(String),
+    /// Abort the merge with an error.
+    Error
+}
+
+/// Description of the actions taken by a merge.
+#[derive(Debug,Clone,Default)]
+pub struct MergeReport {
+    /// True if the coordinates of the imported structures were converted to
+    /// the database unit of the library.
+    pub rescaled: bool,
+    /// Names of imported structures.
+    pub imported: Vec<String>,
+    /// Names of structures which were not imported because an identical
+    /// structure already exists.
+    pub identical: Vec<String>,
+    /// Names of structures which were not imported because of the
+    /// [Skip](enum.MergePolicy.html) policy.
+    pub skipped: Vec<String>,
+    /// Imported structures which got a new name given as tuple of old and new
+    /// name.
+    pub renamed: Vec<(String, String)>
+}
+
+impl Library {
+    /// Imports all structures of `other` into the library.
+    ///
+    /// If the database units differ, the coordinates of the imported
+    /// structures are converted to the database unit of this library. Units
+    /// which differ but are not positive result in an error.
+    /// Structures with a name already present in the library are dropped if
+    /// their content is identical (independent of the element order).
+    /// Otherwise `policy` decides what happens. In case of an error the
+    /// library is not changed.
+    pub fn merge(&mut self, other: &Library, policy: MergePolicy)
+            -> Result<MergeReport, Error> {
+        let mut report = MergeReport::default();
+        let mut other = other.clone();
+        if other.units_m != self.units_m {
+            let valid = |x: f64| x.is_finite() && x > 0.;
+            if !valid(self.units_m) || !valid(other.units_m) {
+                return Err(Error::InvalidValue(format!(
+                    "database units {} and {} can not be converted",
+                    other.units_m, self.units_m)));
+            }
+            other.rescale(self.units_m)?;
+            report.rescaled = true;
+        }
+
+        let mut used: HashSet<String> = self.structures.iter()
+            .chain(other.structures.iter()).map(|s| s.name.clone()).collect();
+        let existing = self.structure_index();
+        let mut names: HashMap<String, String> = HashMap::new();
+        let mut imported: Vec<Structure> = Vec::new();
+        // referenced structures have to be handled first to know their names
+        for stru in other.bottom_up() {
+            if let Some(e) = existing.get(stru.name.as_str()) {
+                let renamed: HashMap<&str, &str> = names.iter()
+                    .map(|(a, b)| (a.as_str(), b.as_str())).collect();
+                if content_key(&stru.elements, &renamed) ==
+                        content_key(&e.elements, &HashMap::new()) {
+                    report.identical.push(stru.name.clone());
+                    continue;
+                }
+                match policy {
+                    MergePolicy::Skip => {
+                        report.skipped.push(stru.name.clone());
+                        continue;
+                    },
+                    MergePolicy::Error => return Err(
+                        Error::DuplicateStructure(stru.name.clone())),
+                    MergePolicy::Rename(ref suffix) => {
+                        let mut name = format!("{}{}", stru.name, suffix);
+                        let mut i = 2;
+                        while used.contains(&name) {
+                            name = format!("{}{}{}", stru.name, suffix, i);
+                            i += 1;
+                        }
+                        if !is_valid_name(&name) {
+                            return Err(Error::InvalidValue(format!(
+                                "invalid structure name {}", name)));
+                        }
+                        names.insert(stru.name.clone(), name.clone());
+                        report.renamed.push((stru.name.clone(), name));
+                    }
+                }
+            }
+            let mut stru = stru.clone();
+            if let Some(n) = names.get(&stru.name) {
+                stru.name = n.clone();
+            }
+            used.insert(stru.name.clone());
+            report.imported.push(stru.name.clone());
+            imported.push(stru);
+        }
+
+        for stru in &mut imported {
+            stru.rename_references(&names);
+        }
+        self.structures.extend(imported);
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::{library, rect, sref};
+
+    fn names(lib: &Library) -> Vec<&str> {
+        lib.structures.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    #[test]
+    fn identical_and_renamed() {
+        let mut a = library(vec![("VIA", vec![rect(1, 0, 0, 5, 5)]),
+            ("CELL", vec![rect(1, 0, 0, 1, 1)]), ("CELL_B", vec![])]);
+        let b = library(vec![("VIA", vec![rect(1, 0, 0, 5, 5)]),
+            ("CELL", vec![rect(1, 0, 0, 2, 2)]),
+            ("TOP", vec![sref("CELL", (10, 20)), sref("VIA", (10, 20))])]);
+        let report = a.merge(&b, MergePolicy::Rename(String::from("_B")))
+            .unwrap();
+        assert!(!report.rescaled);
+        assert_eq!(report.identical, vec![String::from("VIA")]);
+        assert_eq!(report.renamed,
+            vec![(String::from("CELL"), String::from("CELL_B2"))]);
+        assert_eq!(names(&a), vec!["VIA", "CELL", "CELL_B", "CELL_B2", "TOP"]);
+        assert_eq!(a.structure("TOP").unwrap().references(),
+            vec!["CELL_B2", "VIA"]);
+    }
+
+    #[test]
+    fn skip_and_error() {
+        let a = library(vec![("CELL", vec![rect(1, 0, 0, 1, 1)])]);
+        let b = library(vec![("CELL", vec![rect(1, 0, 0, 2, 2)]),
+            ("TOP", vec![sref("CELL", (10, 20))])]);
+        let mut skipped = a.clone();
+        let report = skipped.merge(&b, MergePolicy::Skip).unwrap();
+        assert_eq!(report.skipped, vec![String::from("CELL")]);
+        assert_eq!(names(&skipped), vec!["CELL", "TOP"]);
+
+        let mut failed = a.clone();
+        assert!(failed.merge(&b, MergePolicy::Error).is_err());
+        assert_eq!(names(&failed), vec!["CELL"]);
+        for suffix in &["-B", &"_".repeat(29)] {
+            let policy = MergePolicy::Rename(suffix.to_string());
+            assert!(matches!(failed.merge(&b, policy),
+                Err(Error::InvalidValue(_))));
+            assert_eq!(names(&failed), vec!["CELL"]);
+        }
+    }
+
+    #[test]
+    fn rescaling() {
+        let mut a = library(vec![]);
+        let mut b = library(vec![("TOP", vec![sref("X", (10, 20))])]);
+        b.units_m = 1e-8;
+        let report = a.merge(&b, MergePolicy::Error).unwrap();
+        assert!(report.rescaled);
+        assert_eq!(a.structures[0].elements[0].xy().unwrap(), &[(100, 200)]);
+
+        for &units in &[0., -1e-9, f64::NAN] {
+            let mut c = library(vec![]);
+            b.units_m = units;
+            assert!(matches!(c.merge(&b, MergePolicy::Error),
+                Err(Error::InvalidValue(_))));
+            assert!(c.structures.is_empty());
+        }
+    }
+}
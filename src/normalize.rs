@@ -0,0 +1,179 @@
+//! Contains the normalisation of boundaries and boxes.
+//!
+//! Files written by different tools describe the same shapes with
+//! different point orders, repeated and collinear points. Normalised shapes
+//! can be compared point by point.
+
+use {Element, ElementParameter, ElementType, Library, Structure};
+use polygon::{self, Orientation};
+
+/// Conversion between rectangular boundaries and boxes.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum BoxConversion {
+    /// Boundaries and boxes are kept.
+    Keep,
+    /// Boxes are converted into boundaries, the boxtype becomes the
+    /// datatype.
+    ToBoundary,
+    /// Axis-aligned rectangular boundaries with datatype zero are converted
+    /// into boxes with boxtype zero. Other boundaries are kept.
+    ToBox
+}
+
+/// Settings of the normalisation.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct NormalizeOptions {
+    /// Orientation of the polygons, `Degenerate` keeps the orientation.
+    pub orientation: Orientation,
+    /// Conversion between boundaries and boxes.
+    pub boxes: BoxConversion
+}
+
+impl Default for NormalizeOptions {
+    fn default() -> NormalizeOptions {
+        NormalizeOptions{orientation: Orientation::Counterclockwise,
+            boxes: BoxConversion::Keep}
+    }
+}
+
+impl Element {
+    /// Normalises the points of a boundary or box.
+    ///
+    /// See [polygon::normalize](../polygon/fn.normalize.html) for the
+    /// changes of the points. Other elements are not changed. Returns true
+    /// if the element was changed.
+    pub fn normalize(&mut self, options: &NormalizeOptions) -> bool {
+        let is_box = match self.element_type {
+            ElementType::Boundary => false,
+            ElementType::Box => true,
+            _ => return false
+        };
+        let mut changed = false;
+        for param in &mut self.parameters {
+            if let ElementParameter::XY(ref mut xy) = *param {
+                let n = polygon::normalize(xy, options.orientation);
+                if n != *xy {
+                    *xy = n;
+                    changed = true;
+                }
+            }
+        }
+        match options.boxes {
+            BoxConversion::ToBoundary if is_box => {
+                self.element_type = ElementType::Boundary;
+                let datatype = self.datatype().unwrap_or(0);
+                self.parameters.retain(
+                    |p| !matches!(*p, ElementParameter::Boxtype(_)));
+                // the datatype has to precede the points
+                let pos = self.parameters.iter().position(
+                    |p| matches!(*p, ElementParameter::XY(_)))
+                    .unwrap_or(self.parameters.len());
+                self.parameters.insert(pos,
+                    ElementParameter::Datatype(datatype));
+                changed = true;
+            },
+            BoxConversion::ToBox if !is_box && self.datatype() == Some(0) &&
+                    self.xy().map(is_rectangle) == Some(true) => {
+                self.element_type = ElementType::Box;
+                for param in &mut self.parameters {
+                    if let ElementParameter::Datatype(_) = *param {
+                        *param = ElementParameter::Boxtype(0);
+                    }
+                }
+                changed = true;
+            },
+            _ => {}
+        }
+        changed
+    }
+}
+
+/// Returns true if the closed polygon is an axis-aligned rectangle.
+fn is_rectangle(xy: &[(i32,i32)]) -> bool {
+    xy.len() == 5 && xy[0] == xy[4] && (0..4).all(|i| {
+        let (a, b) = (xy[i], xy[i + 1]);
+        a != b && (a.0 == b.0 || a.1 == b.1)
+    })
+}
+
+impl Structure {
+    /// Normalises all boundaries and boxes of the structure.
+    ///
+    /// Returns the number of changed elements.
+    pub fn normalize(&mut self, options: &NormalizeOptions) -> usize {
+        self.elements.iter_mut().map(|e| e.normalize(options))
+            .filter(|&x| x).count()
+    }
+}
+
+impl Library {
+    /// Normalises all boundaries and boxes of the library.
+    ///
+    /// Returns the number of changed elements.
+    pub fn normalize(&mut self, options: &NormalizeOptions) -> usize {
+        self.structures.iter_mut().map(|s| s.normalize(options)).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::{boundary, element, library};
+
+    #[test]
+    fn points() {
+        let mut stru = Structure::new();
+        stru.elements = vec![
+            boundary(1, vec![(10, 10), (10, 0), (5, 0), (0, 0), (0, 10),
+                (10, 10)]),
+            boundary(1, vec![(0, 0), (10, 0), (10, 10), (0, 10), (0, 0)]),
+            element(ElementType::Path, vec![ElementParameter::Layer(1),
+                ElementParameter::XY(vec![(10, 0), (0, 0), (0, 0)])])];
+        let options = NormalizeOptions::default();
+        assert_eq!(stru.normalize(&options), 1);
+        assert_eq!(stru.elements[0].xy(), stru.elements[1].xy());
+        assert_eq!(stru.elements[2].xy().unwrap().len(), 3);
+        assert_eq!(stru.normalize(&options), 0);
+
+        let options = NormalizeOptions{orientation: Orientation::Clockwise,
+            ..options};
+        assert_eq!(stru.normalize(&options), 2);
+        assert_eq!(stru.elements[0].xy().unwrap(),
+            &[(0, 0), (0, 10), (10, 10), (10, 0), (0, 0)]);
+    }
+
+    #[test]
+    fn boxes() {
+        let rect = vec![(0, 0), (10, 0), (10, 5), (0, 5), (0, 0)];
+        let mut lib = library(vec![("TOP", vec![boundary(1, rect.clone()),
+            element(ElementType::Boundary, vec![ElementParameter::Layer(1),
+                ElementParameter::Datatype(1), ElementParameter::XY(
+                    rect.clone())]),
+            boundary(1, vec![(0, 0), (10, 0), (0, 5), (0, 0)])])]);
+        let to_box = NormalizeOptions{boxes: BoxConversion::ToBox,
+            ..NormalizeOptions::default()};
+        assert_eq!(lib.normalize(&to_box), 1);
+        let elements = &lib.structures[0].elements;
+        assert!(matches!(elements[0].element_type, ElementType::Box));
+        assert!(matches!(elements[0].parameters[..],
+            [ElementParameter::Layer(1), ElementParameter::Boxtype(0),
+            ElementParameter::XY(_)]));
+        assert!(matches!(elements[1].element_type, ElementType::Boundary));
+        assert!(matches!(elements[2].element_type, ElementType::Boundary));
+        // the box is written with its BOXTYPE record
+        let mut buf = Vec::new();
+        lib.to_writer(&mut buf).unwrap();
+        let mut lib = Library::from_reader(&mut &buf[..]).unwrap();
+        assert!(lib.validate().is_empty());
+        assert_eq!(lib.structures[0].elements[0].datatype(), Some(0));
+
+        let to_boundary = NormalizeOptions{boxes: BoxConversion::ToBoundary,
+            ..NormalizeOptions::default()};
+        assert_eq!(lib.normalize(&to_boundary), 1);
+        let elem = &lib.structures[0].elements[0];
+        assert!(matches!(elem.element_type, ElementType::Boundary));
+        assert!(matches!(elem.parameters[..], [ElementParameter::Layer(1),
+            ElementParameter::Datatype(0), ElementParameter::XY(_)]));
+        assert_eq!(elem.xy().unwrap(), &rect[..]);
+    }
+}
@@ -0,0 +1,515 @@
+//! Contains properties of polygons and statistics of the shapes on layers.
+//!
+//! The functions take the points of a polygon as stored in an XY parameter,
+//! the closing point may be omitted.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::f64::consts::PI;
+use std::ptr;
+
+use {Element, ElementParameter, ElementType, Error, Library, Structure};
+use boolean;
+
+/// Orientation of a polygon.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Orientation {
+    /// The points are ordered counterclockwise.
+    Counterclockwise,
+    /// The points are ordered clockwise.
+    Clockwise,
+    /// The polygon has no area.
+    Degenerate
+}
+
+/// Directions of the edges of a polygon.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord)]
+pub enum AngleClass {
+    /// All edges are horizontal or vertical.
+    Manhattan,
+    /// All edges are horizontal, vertical or diagonal at 45 degrees.
+    FortyFive,
+    /// The edges have arbitrary directions.
+    AllAngle
+}
+
+/// Shapes on a single layer.
+#[derive(Debug,Clone,PartialEq)]
+pub struct LayerStatistics {
+    /// Layer of the shapes.
+    pub layer: i16,
+    /// Datatype of the shapes.
+    pub datatype: i16,
+    /// Number of shapes.
+    pub shapes: u64,
+    /// Number of points without closing points.
+    pub vertices: u64,
+    /// Sum of the areas of the shapes in square database units.
+    pub area: f64,
+    /// Sum of the perimeters of the shapes in database units.
+    pub perimeter: f64
+}
+
+/// Returns the points without the closing point.
+fn open(xy: &[(i32,i32)]) -> &[(i32,i32)] {
+    if xy.len() > 1 && xy[0] == xy[xy.len() - 1] {
+        &xy[..xy.len() - 1]
+    } else {
+        xy
+    }
+}
+
+/// Returns the area of a polygon, positive if it is oriented
+/// counterclockwise.
+pub fn signed_area(xy: &[(i32,i32)]) -> f64 {
+    let xy = open(xy);
+    let mut res = 0i128;
+    for i in 0..xy.len() {
+        let (a, b) = (xy[i], xy[(i + 1) % xy.len()]);
+        res += a.0 as i128*b.1 as i128 - b.0 as i128*a.1 as i128;
+    }
+    res as f64/2.
+}
+
+/// Returns the area of a polygon.
+pub fn area(xy: &[(i32,i32)]) -> f64 {
+    signed_area(xy).abs()
+}
+
+/// Returns the length of the outline of a polygon.
+pub fn perimeter(xy: &[(i32,i32)]) -> f64 {
+    let xy = open(xy);
+    (0..xy.len()).map(|i| {
+        let (a, b) = (xy[i], xy[(i + 1) % xy.len()]);
+        (b.0 as f64 - a.0 as f64).hypot(b.1 as f64 - a.1 as f64)
+    }).sum()
+}
+
+/// Returns the orientation of a polygon.
+pub fn orientation(xy: &[(i32,i32)]) -> Orientation {
+    let a = signed_area(xy);
+    if a > 0. {
+        Orientation::Counterclockwise
+    } else if a < 0. {
+        Orientation::Clockwise
+    } else {
+        Orientation::Degenerate
+    }
+}
+
+/// Returns true if the polygon is convex.
+///
+/// Repeated and collinear points are allowed. Polygons without area are not
+/// convex.
+pub fn is_convex(xy: &[(i32,i32)]) -> bool {
+    let pts = distinct(xy);
+    if pts.len() < 3 || signed_area(&pts) == 0. {
+        return false;
+    }
+    let n = pts.len();
+    let mut sign = 0;
+    let mut turn = 0.;
+    for i in 0..n {
+        let (a, b, c) = (pts[i], pts[(i + 1) % n], pts[(i + 2) % n]);
+        let s = cross(a, b, c).signum();
+        if s == 0 && dot(a, b, c) < 0 {
+            return false;
+        }
+        if s != 0 {
+            if sign != 0 && s != sign {
+                return false;
+            }
+            sign = s;
+        }
+        let d1 = (b.1 as f64 - a.1 as f64).atan2(b.0 as f64 - a.0 as f64);
+        let d2 = (c.1 as f64 - b.1 as f64).atan2(c.0 as f64 - b.0 as f64);
+        let mut d = d2 - d1;
+        if d > PI {
+            d -= 2.*PI;
+        } else if d < -PI {
+            d += 2.*PI;
+        }
+        turn += d;
+    }
+    // polygons winding around more than once turn by a multiple of 360°
+    (turn.abs() - 2.*PI).abs() < 1e-6
+}
+
+/// Returns true if two edges of the polygon intersect or touch apart from
+/// the common point of neighbouring edges.
+///
+/// Repeated points are ignored, neighbouring edges overlapping each other
+/// count as intersection.
+pub fn is_self_intersecting(xy: &[(i32,i32)]) -> bool {
+    let pts = distinct(xy);
+    let n = pts.len();
+    if n < 3 {
+        return false;
+    }
+    for i in 0..n {
+        let (a, b) = (pts[i], pts[(i + 1) % n]);
+        // the next edge only shares b unless it runs back along this edge
+        let c = pts[(i + 2) % n];
+        if cross(a, b, c) == 0 && dot(a, b, c) < 0 {
+            return true;
+        }
+        for j in i + 2..n {
+            if i == 0 && j == n - 1 {
+                continue;
+            }
+            if segments_intersect(a, b, pts[j], pts[(j + 1) % n]) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Returns the class of the edge directions of a polygon or path.
+pub fn angle_class(xy: &[(i32,i32)]) -> AngleClass {
+    let mut res = AngleClass::Manhattan;
+    for w in xy.windows(2) {
+        let dx = (w[1].0 as i64 - w[0].0 as i64).abs();
+        let dy = (w[1].1 as i64 - w[0].1 as i64).abs();
+        if dx == 0 || dy == 0 {
+            continue;
+        } else if dx == dy {
+            res = AngleClass::FortyFive;
+        } else {
+            return AngleClass::AllAngle;
+        }
+    }
+    res
+}
+
+/// Returns a normalised copy of a polygon.
+///
+/// Repeated points and collinear points are removed, the polygon is
+/// reversed if its orientation differs from `orientation` and starts at its
+/// lowest point with the smallest x-coordinate. The result is closed. An
+/// orientation of `Degenerate` keeps the orientation. Polygons without area
+/// only get repeated points removed.
+pub fn normalize(xy: &[(i32,i32)], orientation: Orientation)
+        -> Vec<(i32,i32)> {
+    let mut pts: Vec<(i32,i32)> = Vec::with_capacity(xy.len());
+    for p in distinct(xy) {
+        while pts.len() > 1 && cross(pts[pts.len() - 2], pts[pts.len() - 1],
+                p) == 0 {
+            pts.pop();
+        }
+        pts.push(p);
+    }
+    // collinear points at the start and end of the list
+    loop {
+        let n = pts.len();
+        if n > 2 && cross(pts[n - 2], pts[n - 1], pts[0]) == 0 {
+            pts.pop();
+        } else if n > 2 && cross(pts[n - 1], pts[0], pts[1]) == 0 {
+            pts.remove(0);
+        } else {
+            break;
+        }
+    }
+    if pts.len() < 3 || signed_area(&pts) == 0. {
+        let mut res = distinct(xy);
+        if let Some(&p) = res.first() {
+            res.push(p);
+        }
+        return res;
+    }
+    if orientation != Orientation::Degenerate &&
+            self::orientation(&pts) != orientation {
+        pts.reverse();
+    }
+    let start = (0..pts.len()).min_by_key(|&i| (pts[i].1, pts[i].0))
+        .unwrap_or(0);
+    pts.rotate_left(start);
+    let first = pts[0];
+    pts.push(first);
+    pts
+}
+
+/// Returns true if the point `p` lies inside or on the border of the
+/// polygon.
+pub fn contains(xy: &[(i32,i32)], p: (i32,i32)) -> bool {
+    let xy = open(xy);
+    let mut inside = false;
+    for i in 0..xy.len() {
+        let (a, b) = (xy[i], xy[(i + 1) % xy.len()]);
+        if segments_intersect(a, b, p, p) {
+            return true;
+        }
+        if (a.1 > p.1) != (b.1 > p.1) {
+            // the edge crosses the horizontal line through the point, count
+            // the crossings left of the point
+            let c = cross(a, b, p);
+            if (c > 0) == (b.1 > a.1) {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// Returns true if the closed segments `a`-`b` and `c`-`d` share a point.
+pub fn segments_intersect(a: (i32,i32), b: (i32,i32), c: (i32,i32),
+        d: (i32,i32)) -> bool {
+    let within = |p: (i32,i32), q: (i32,i32), r: (i32,i32)|
+        r.0 >= p.0.min(q.0) && r.0 <= p.0.max(q.0) &&
+        r.1 >= p.1.min(q.1) && r.1 <= p.1.max(q.1);
+    let (d1, d2) = (cross(c, d, a), cross(c, d, b));
+    let (d3, d4) = (cross(a, b, c), cross(a, b, d));
+    if d1.signum()*d2.signum() < 0 && d3.signum()*d4.signum() < 0 {
+        return true;
+    }
+    (d1 == 0 && within(c, d, a)) || (d2 == 0 && within(c, d, b)) ||
+        (d3 == 0 && within(a, b, c)) || (d4 == 0 && within(a, b, d))
+}
+
+/// Returns the cross product of `b - a` and `p - a`.
+fn cross(a: (i32,i32), b: (i32,i32), p: (i32,i32)) -> i64 {
+    (b.0 as i64 - a.0 as i64)*(p.1 as i64 - a.1 as i64) -
+        (b.1 as i64 - a.1 as i64)*(p.0 as i64 - a.0 as i64)
+}
+
+/// Returns the dot product of `b - a` and `p - b`.
+fn dot(a: (i32,i32), b: (i32,i32), p: (i32,i32)) -> i64 {
+    let v = (a.0 as i64 - b.0 as i64, a.1 as i64 - b.1 as i64);
+    let w = (p.0 as i64 - b.0 as i64, p.1 as i64 - b.1 as i64);
+    -(v.0*w.0 + v.1*w.1)
+}
+
+/// Returns the points without repeated points and without closing point.
+fn distinct(xy: &[(i32,i32)]) -> Vec<(i32,i32)> {
+    let mut res: Vec<(i32,i32)> = Vec::with_capacity(xy.len());
+    for &p in xy {
+        if res.last() != Some(&p) {
+            res.push(p);
+        }
+    }
+    while res.len() > 1 && res[0] == res[res.len() - 1] {
+        res.pop();
+    }
+    res
+}
+
+impl Library {
+    /// Returns the number, vertices, area and perimeter of the shapes on
+    /// every layer of the structure `top`.
+    ///
+    /// The statistics are computed once per structure and multiplied by the
+    /// number of instances of the structure, taking the magnification of
+    /// the references into account. Overlapping shapes are counted
+    /// separately. Boundaries, boxes and paths are counted, the area of a
+    /// path is the area of its outline, its perimeter is approximated by
+    /// twice its length including the extensions plus twice its width. The
+    /// statistics are sorted by layer and datatype.
+    pub fn layer_statistics(&self, top: &str)
+            -> Result<Vec<LayerStatistics>, Error> {
+        let index = self.structure_index();
+        if !index.contains_key(top) {
+            return Err(Error::MissingStructure(top.to_string()));
+        }
+        // number of instances and sums of the magnifications and squared
+        // magnifications of all instances
+        let mut weights: HashMap<&str, (u64, f64, f64)> = HashMap::new();
+        weights.insert(top, (1, 1., 1.));
+        let mut done: HashSet<&str> = HashSet::new();
+        let mut stats: BTreeMap<(i16,i16), LayerStatistics> = BTreeMap::new();
+        for stru in self.bottom_up().into_iter().rev() {
+            if !ptr::eq(index[stru.name.as_str()], stru) ||
+                    !done.insert(&stru.name) {
+                continue;
+            }
+            let (n, m, m2) = match weights.get(stru.name.as_str()) {
+                Some(&w) => w,
+                None => continue
+            };
+            add_statistics(stru, (n, m, m2), &mut stats);
+            for elem in stru.elements.iter().filter(|e| e.is_reference()) {
+                let name = match elem.structure_name() {
+                    Some(x) if !done.contains(x) => x,
+                    _ => continue
+                };
+                let (cols, rows) = elem.array_size();
+                let k = cols.max(0) as u64*rows.max(0) as u64;
+                let mag = elem.parameters.iter().filter_map(|p| match *p {
+                    ElementParameter::Magnification(x) => Some(x.abs()),
+                    _ => None
+                }).next().unwrap_or(1.);
+                let w = weights.entry(name).or_insert((0, 0., 0.));
+                w.0 += n*k;
+                w.1 += m*mag*k as f64;
+                w.2 += m2*mag*mag*k as f64;
+            }
+        }
+        Ok(stats.into_iter().map(|x| x.1).collect())
+    }
+}
+
+/// Adds the shapes of a structure with `weights` to `stats`.
+fn add_statistics(stru: &Structure, weights: (u64, f64, f64),
+        stats: &mut BTreeMap<(i16,i16), LayerStatistics>) {
+    let (n, m, m2) = weights;
+    for elem in &stru.elements {
+        let xy = match elem.xy() {
+            Some(x) => x,
+            None => continue
+        };
+        let (a, p, v) = match elem.element_type {
+            ElementType::Boundary | ElementType::Box =>
+                (area(xy), perimeter(xy), open(xy).len()),
+            ElementType::Path =>
+                (boolean::area(&boolean::union(&elem.polygons())),
+                    path_perimeter(elem, xy), xy.len()),
+            _ => continue
+        };
+        let key = (elem.layer().unwrap_or(0), elem.datatype().unwrap_or(0));
+        let s = stats.entry(key).or_insert(LayerStatistics{layer: key.0,
+            datatype: key.1, shapes: 0, vertices: 0, area: 0.,
+            perimeter: 0.});
+        s.shapes += n;
+        s.vertices += n*v as u64;
+        s.area += m2*a;
+        s.perimeter += m*p;
+    }
+}
+
+/// Returns the approximate perimeter of a path.
+fn path_perimeter(elem: &Element, xy: &[(i32,i32)]) -> f64 {
+    let (mut width, mut pathtype, mut begin) = (0., 0, 0.);
+    for param in &elem.parameters {
+        match *param {
+            ElementParameter::Width(x) => width = (x as f64).abs(),
+            ElementParameter::Pathtype(x) => pathtype = x,
+            ElementParameter::BeginExt(x) => begin = x as f64,
+            _ => {}
+        }
+    }
+    if width == 0. {
+        return 0.;
+    }
+    let length: f64 = xy.windows(2).map(|w| (w[1].0 as f64 - w[0].0 as f64)
+        .hypot(w[1].1 as f64 - w[0].1 as f64)).sum();
+    let ext = match pathtype {
+        1 | 2 => width,
+        4 => begin,
+        _ => 0.
+    };
+    2.*(length + ext) + 2.*width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use builder::{LibraryBuilder, Strans, StructureBuilder};
+
+    const SQUARE: [(i32,i32); 5] = [(0, 0), (10, 0), (10, 10), (0, 10),
+        (0, 0)];
+
+    #[test]
+    fn metrics() {
+        assert_eq!(signed_area(&SQUARE), 100.);
+        assert_eq!(signed_area(&SQUARE[..4]), 100.);
+        let mut cw = SQUARE.to_vec();
+        cw.reverse();
+        assert_eq!(signed_area(&cw), -100.);
+        assert_eq!(area(&cw), 100.);
+        assert_eq!(perimeter(&SQUARE), 40.);
+        assert_eq!(perimeter(&[(0, 0), (3, 4), (0, 0)]), 10.);
+        assert_eq!(orientation(&SQUARE), Orientation::Counterclockwise);
+        assert_eq!(orientation(&cw), Orientation::Clockwise);
+        assert_eq!(orientation(&[(0, 0), (5, 5), (10, 10)]),
+            Orientation::Degenerate);
+        // no overflow for the largest coordinates
+        let m = i32::MAX;
+        assert_eq!(area(&[(-m, -m), (m, -m), (m, m), (-m, m)]),
+            4.*m as f64*m as f64);
+    }
+
+    #[test]
+    fn shape_classes() {
+        assert!(is_convex(&SQUARE));
+        assert!(is_convex(&[(0, 0), (5, 0), (10, 0), (10, 10), (10, 10),
+            (0, 10)]));
+        assert!(!is_convex(&[(0, 0), (10, 0), (5, 5), (10, 10), (0, 10)]));
+        assert!(!is_convex(&[(0, 0), (10, 0), (20, 0)]));
+        // a pentagram turns by 720 degrees
+        let star = [(0, 10), (6, -8), (-10, 3), (10, 3), (-6, -8)];
+        assert!(!is_convex(&star));
+        assert!(is_self_intersecting(&star));
+        assert!(is_self_intersecting(&[(0, 0), (10, 10), (10, 0), (0, 10)]));
+        // touching in a single vertex and running back along an edge
+        assert!(is_self_intersecting(&[(0, 0), (10, 0), (5, 0), (5, 5)]));
+        assert!(is_self_intersecting(&[(0, 0), (4, 0), (4, 4), (2, 0),
+            (0, 4)]));
+        assert!(!is_self_intersecting(&SQUARE));
+        assert!(!is_self_intersecting(&[(0, 0), (10, 0), (5, 5), (10, 10),
+            (0, 10), (0, 0)]));
+        assert_eq!(angle_class(&SQUARE), AngleClass::Manhattan);
+        assert_eq!(angle_class(&[(0, 0), (10, 0), (0, 10), (0, 0)]),
+            AngleClass::FortyFive);
+        assert_eq!(angle_class(&[(0, 0), (10, 0), (0, 5), (0, 0)]),
+            AngleClass::AllAngle);
+    }
+
+    #[test]
+    fn normalised_polygons() {
+        let xy = [(10, 10), (10, 5), (10, 0), (10, 0), (0, 0), (0, 10),
+            (5, 10), (10, 10)];
+        assert_eq!(normalize(&xy, Orientation::Counterclockwise),
+            SQUARE.to_vec());
+        let mut cw = SQUARE.to_vec();
+        cw.reverse();
+        assert_eq!(normalize(&xy, Orientation::Clockwise), cw);
+        assert_eq!(normalize(&xy, Orientation::Degenerate),
+            vec![(0, 0), (0, 10), (10, 10), (10, 0), (0, 0)]);
+        assert_eq!(normalize(&[(0, 0), (5, 0), (5, 0), (0, 0)],
+            Orientation::Counterclockwise), vec![(0, 0), (5, 0), (0, 0)]);
+    }
+
+    #[test]
+    fn point_in_polygon() {
+        let u = [(0, 0), (30, 0), (30, 30), (20, 30), (20, 10), (10, 10),
+            (10, 30), (0, 30)];
+        for &(p, inside) in &[((5, 5), true), ((15, 20), false),
+                ((0, 15), true), ((20, 20), true), ((15, 10), true),
+                ((31, 5), false), ((15, 30), false), ((25, 29), true),
+                ((-1, 0), false)] {
+            assert_eq!(contains(&u, p), inside, "{:?}", p);
+        }
+        assert!(segments_intersect((0, 0), (10, 10), (0, 10), (10, 0)));
+        assert!(segments_intersect((0, 0), (10, 0), (10, 0), (20, 5)));
+        assert!(segments_intersect((0, 0), (10, 0), (5, 0), (20, 0)));
+        assert!(!segments_intersect((0, 0), (10, 0), (11, 0), (20, 0)));
+        assert!(!segments_intersect((0, 0), (10, 10), (0, 1), (10, 11)));
+    }
+
+    #[test]
+    fn statistics() {
+        let cell = StructureBuilder::new("CELL")
+            .rect(1, 0, 0, 0, 10, 10)
+            .path(2, 0, 4, &[(0, 0), (20, 0)])
+            .build();
+        let top = StructureBuilder::new("TOP")
+            .rect(1, 0, 0, 0, 100, 50)
+            .aref("CELL", 3, 2, (0, 0), (50, 0), (0, 50), Strans::identity())
+            .sref("CELL", (500, 0), Strans{magnification: 2.,
+                ..Strans::identity()})
+            .build();
+        let lib = LibraryBuilder::new("LIB").structure(cell).structure(top)
+            .build();
+        let stats = lib.layer_statistics("TOP").unwrap();
+        assert_eq!(stats, vec![
+            LayerStatistics{layer: 1, datatype: 0, shapes: 8, vertices: 32,
+                area: 5000. + 6.*100. + 400., perimeter: 300. + 6.*40. + 80.},
+            LayerStatistics{layer: 2, datatype: 0, shapes: 7, vertices: 14,
+                area: 6.*80. + 320., perimeter: 6.*48. + 96.}]);
+
+        // the same statistics as for the flattened structure
+        let flat = lib.flatten("TOP").unwrap();
+        let mut flat_lib = Library::new(600, String::from("FLAT"));
+        flat_lib.structures.push(flat);
+        assert_eq!(flat_lib.layer_statistics("TOP").unwrap(), stats);
+        assert!(lib.layer_statistics("MISSING").is_err());
+    }
+}
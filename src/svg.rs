@@ -0,0 +1,140 @@
+//! Contains an export of structures into SVG images.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use {Element, ElementParameter, ElementType, Error, Library};
+use geometry::BoundingBox;
+
+/// Colors used for the layers, selected by the layer number.
+const COLORS: &[&str] = &["#1f77b4", "#ff7f0e", "#2ca02c", "#d62728",
+    "#9467bd", "#8c564b", "#e377c2", "#7f7f7f", "#bcbd22", "#17becf"];
+
+impl Library {
+    /// Writes the flattened structure `name` as SVG image to `w`.
+    ///
+    /// Every layer/datatype combination is written into its own group.
+    /// Boundaries and boxes are drawn as polygons, paths as lines with their
+    /// width and texts at their position. Nodes are omitted.
+    pub fn write_svg<W: Write>(&self, name: &str, w: &mut W)
+            -> Result<(), Error> {
+        let stru = self.flatten(name)
+            .ok_or_else(|| Error::MissingStructure(name.to_string()))?;
+        let mut layers: BTreeMap<(i16,i16), Vec<&Element>> = BTreeMap::new();
+        let mut bbox: Option<BoundingBox> = None;
+        for elem in &stru.elements {
+            if let ElementType::Node = elem.element_type {
+                continue;
+            }
+            if let Some(b) = elem.bounding_box() {
+                bbox = Some(bbox.map_or(b, |x| x.union(&b)));
+            }
+            let key = (elem.layer().unwrap_or(0), elem.datatype().unwrap_or(0));
+            layers.entry(key).or_default().push(elem);
+        }
+        let bbox = bbox.unwrap_or_else(|| BoundingBox::new((0, 0)));
+        let size = bbox.width().max(bbox.height()).max(1);
+
+        writeln!(w, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(w, "<svg xmlns=\"http://www.w3.org/2000/svg\" \
+            viewBox=\"{} {} {} {}\">", bbox.x_min, -bbox.y_max,
+            bbox.width().max(1), bbox.height().max(1))?;
+        writeln!(w, "<title>{}</title>", escape(name))?;
+        writeln!(w, "<g transform=\"scale(1,-1)\">")?;
+        for (&(layer, datatype), elems) in &layers {
+            let color = COLORS[layer.unsigned_abs() as usize % COLORS.len()];
+            writeln!(w, "<g id=\"L{}D{}\" fill=\"{}\" stroke=\"{}\" \
+                fill-opacity=\"0.5\">", layer, datatype, color, color)?;
+            for elem in elems {
+                write_element(w, elem, size)?;
+            }
+            writeln!(w, "</g>")?;
+        }
+        writeln!(w, "</g>")?;
+        writeln!(w, "</svg>")?;
+        Ok(())
+    }
+}
+
+fn write_element<W: Write>(w: &mut W, elem: &Element, size: i64)
+        -> Result<(), Error> {
+    let xy = match elem.xy() {
+        Some(x) if !x.is_empty() => x,
+        _ => return Ok(())
+    };
+    let points = xy.iter().map(|p| format!("{},{}", p.0, p.1))
+        .collect::<Vec<_>>().join(" ");
+    match elem.element_type {
+        ElementType::Boundary | ElementType::Box =>
+            writeln!(w, "<polygon points=\"{}\" stroke-width=\"0\"/>",
+                points)?,
+        ElementType::Path => {
+            let mut width = 0;
+            let mut cap = "butt";
+            for param in &elem.parameters {
+                match *param {
+                    ElementParameter::Width(x) => width = x.abs(),
+                    ElementParameter::Pathtype(1) => cap = "round",
+                    ElementParameter::Pathtype(2) => cap = "square",
+                    _ => {}
+                }
+            }
+            writeln!(w, "<polyline points=\"{}\" fill=\"none\" \
+                stroke-width=\"{}\" stroke-linecap=\"{}\" \
+                stroke-opacity=\"0.5\"/>", points, width.max(1), cap)?;
+        },
+        ElementType::Text => {
+            let string = elem.parameters.iter().filter_map(|p| match *p {
+                ElementParameter::String(ref x) => Some(x.as_str()),
+                _ => None
+            }).next().unwrap_or("");
+            writeln!(w, "<text transform=\"translate({},{}) scale(1,-1)\" \
+                font-size=\"{}\" fill-opacity=\"1\">{}</text>", xy[0].0,
+                xy[0].1, (size/50).max(1), escape(string))?;
+        },
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Escapes the special characters of XML.
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::{element, library, rect, sref};
+
+    #[test]
+    fn image() {
+        let lib = library(vec![("A", vec![rect(1, 0, 0, 10, 10)]),
+            ("TOP", vec![sref("A", (100, 0)),
+                element(ElementType::Path, vec![ElementParameter::Layer(12),
+                    ElementParameter::Datatype(3), ElementParameter::Width(4),
+                    ElementParameter::XY(vec![(0, 0), (0, 50)])]),
+                element(ElementType::Text, vec![ElementParameter::Layer(1),
+                    ElementParameter::TextType(0),
+                    ElementParameter::XY(vec![(5, 5)]),
+                    ElementParameter::String(String::from("a<b&c"))])])]);
+        let mut buf = Vec::new();
+        lib.write_svg("TOP", &mut buf).unwrap();
+        let s = String::from_utf8(buf).unwrap();
+        assert!(s.contains("viewBox=\"-2 -52 112 54\""));
+        assert!(s.contains("<title>TOP</title>"));
+        assert!(s.contains("<g id=\"L1D0\" fill=\"#ff7f0e\" \
+            stroke=\"#ff7f0e\""));
+        assert!(s.contains("<polygon points=\"100,0 110,0 110,10 100,10 \
+            100,0\" stroke-width=\"0\"/>"));
+        assert!(s.contains("<g id=\"L12D3\" fill=\"#2ca02c\""));
+        assert!(s.contains("points=\"0,0 0,50\" fill=\"none\" \
+            stroke-width=\"4\" stroke-linecap=\"butt\""));
+        assert!(s.contains(">a&lt;b&amp;c</text>"));
+        assert!(s.find("L1D0").unwrap() < s.find("L12D3").unwrap());
+        assert!(s.ends_with("</g>\n</svg>\n"));
+        assert!(matches!(lib.write_svg("B", &mut Vec::new()),
+            Err(Error::MissingStructure(_))));
+    }
+}
@@ -0,0 +1,44 @@
+//! Contains helpers for creating elements and libraries in unit tests.
+
+use {Element, ElementParameter, ElementType, Library, Structure};
+
+/// Creates an element of type `element_type` with `parameters`.
+pub fn element(element_type: ElementType, parameters: Vec<ElementParameter>)
+        -> Element {
+    Element{element_type, parameters}
+}
+
+/// Creates a boundary on `layer` with datatype zero.
+pub fn boundary(layer: i16, xy: Vec<(i32,i32)>) -> Element {
+    element(ElementType::Boundary, vec![ElementParameter::Layer(layer),
+        ElementParameter::Datatype(0), ElementParameter::XY(xy)])
+}
+
+/// Creates a closed rectangle with the corners `(x0,y0)` and `(x1,y1)` as
+/// boundary on `layer` with datatype zero.
+pub fn rect(layer: i16, x0: i32, y0: i32, x1: i32, y1: i32) -> Element {
+    boundary(layer, vec![(x0, y0), (x1, y0), (x1, y1), (x0, y1), (x0, y0)])
+}
+
+/// Creates a reference to the structure `name` placed at `origin`.
+pub fn sref(name: &str, origin: (i32,i32)) -> Element {
+    element(ElementType::StructureRef, vec![
+        ElementParameter::StructureName(name.to_string()),
+        ElementParameter::XY(vec![origin])])
+}
+
+/// Creates a library with a database unit of 1 nm and a user unit of 1 µm.
+///
+/// Every entry of `structures` is the name and the elements of a structure.
+pub fn library(structures: Vec<(&str, Vec<Element>)>) -> Library {
+    let mut lib = Library::new(600, String::from("LIB"));
+    lib.units_user = 1e-3;
+    lib.units_m = 1e-9;
+    for (name, elements) in structures {
+        let mut stru = Structure::new();
+        stru.name = name.to_string();
+        stru.elements = elements;
+        lib.structures.push(stru);
+    }
+    lib
+}
@@ -0,0 +1,224 @@
+//! Contains a human readable text representation of GDS files.
+//!
+//! Every record is written on its own line starting with the name of the
+//! record type followed by its data:
+//!
+//! ```text
+//! HEADER 600
+//! LIBNAME "LIB"
+//! UNITS 0.001 1e-9
+//! BGNSTR 2017 1 1 0 0 0 2017 1 1 0 0 0
+//!   STRNAME "TOP"
+//!   BOUNDARY
+//!     LAYER 1
+//!     XY 0 0 10 0 10 10 0 0
+//!   ENDEL
+//! ENDSTR
+//! ENDLIB
+//! ```
+//!
+//! Flags are written as hexadecimal numbers, strings are quoted and escaped
+//! like JSON strings, so control characters do not break the lines. Empty
+//! lines and lines starting with `#` are ignored when reading.
+
+use std::io::{BufRead, Write};
+
+use {constants, Error, Library, Record, RecordData};
+use json::quote;
+
+impl Library {
+    /// Writes the library in text representation to `w`.
+    ///
+    /// Elements without element type result in an error, nothing is written
+    /// in that case.
+    pub fn write_text<W: Write>(&self, w: &mut W) -> Result<(), Error> {
+        self.check_element_types()?;
+        // the indentation follows the matched start and end records
+        let (mut in_structure, mut in_element) = (false, false);
+        for rec in self.to_records() {
+            if rec.rec_type == constants::REC_TYPE_ENDSTR {
+                in_structure = false;
+                in_element = false;
+            } else if rec.rec_type == constants::REC_TYPE_ENDEL {
+                in_element = false;
+            }
+            let indent = in_structure as usize + in_element as usize;
+            let name = match constants::record_name(rec.rec_type) {
+                Some(x) => x.to_string(),
+                None => format!("0x{:02x}", rec.rec_type)
+            };
+            let mut line = format!("{:1$}{2}", "", 2*indent, name);
+            for d in &rec.data {
+                match *d {
+                    RecordData::None => continue,
+                    RecordData::Bit(x) => line += &format!(" 0x{:04x}", x),
+                    RecordData::Int16(x) => line += &format!(" {}", x),
+                    RecordData::Int32(x) => line += &format!(" {}", x),
+                    RecordData::Real32(x) => line += &format!(" {:?}", x),
+                    RecordData::Real64(x) => line += &format!(" {:?}", x),
+                    RecordData::Str(ref x) => line += &format!(" {}", quote(x))
+                }
+            }
+            writeln!(w, "{}", line)?;
+            if rec.rec_type == constants::REC_TYPE_BGNSTR {
+                in_structure = true;
+            } else if in_structure && is_element_start(rec.rec_type) {
+                in_element = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a library in text representation from `r`.
+    pub fn read_text<R: BufRead>(r: &mut R) -> Result<Library, Error> {
+        let mut records = Vec::new();
+        for (i, line) in r.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let rec = parse_record(line).map_err(
+                |e| Error::Parse(format!("line {}: {}", i + 1, e)))?;
+            records.push(Ok(rec));
+        }
+        Library::from_records(records)
+    }
+}
+
+fn is_element_start(rec_type: u8) -> bool {
+    [constants::REC_TYPE_BOUNDARY, constants::REC_TYPE_PATH,
+        constants::REC_TYPE_SREF, constants::REC_TYPE_AREF,
+        constants::REC_TYPE_TEXT, constants::REC_TYPE_NODE,
+        constants::REC_TYPE_BOX].contains(&rec_type)
+}
+
+/// Parses a single line of the text representation.
+fn parse_record(line: &str) -> Result<Record, String> {
+    let (name, rest) = match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim()),
+        None => (line, "")
+    };
+    let rec_type = constants::record_type(name)
+        .ok_or_else(|| format!("unknown record type {}", name))?;
+    let data_type = constants::record_data_type(rec_type)
+        .unwrap_or(constants::DATA_TYPE_NONE);
+    if data_type == constants::DATA_TYPE_NONE {
+        if !rest.is_empty() {
+            return Err(format!("unexpected data for {}", name));
+        }
+        return Ok(Record::new_none(rec_type));
+    }
+    if data_type == constants::DATA_TYPE_STR {
+        return Ok(Record::new_single(rec_type, data_type,
+            RecordData::Str(unquote(rest)?)));
+    }
+    let mut data = Vec::new();
+    for word in rest.split_whitespace() {
+        let invalid = |_| format!("invalid value {} for {}", word, name);
+        data.push(match data_type {
+            x if x == constants::DATA_TYPE_BIT => RecordData::Bit(
+                match word.strip_prefix("0x") {
+                    Some(x) => u16::from_str_radix(x, 16).map_err(invalid)?,
+                    None => word.parse().map_err(invalid)?
+                }),
+            x if x == constants::DATA_TYPE_INT16 =>
+                RecordData::Int16(word.parse().map_err(invalid)?),
+            x if x == constants::DATA_TYPE_INT32 =>
+                RecordData::Int32(word.parse().map_err(invalid)?),
+            x if x == constants::DATA_TYPE_REAL32 =>
+                RecordData::Real32(word.parse().map_err(
+                    |_| format!("invalid value {} for {}", word, name))?),
+            _ => RecordData::Real64(word.parse().map_err(
+                    |_| format!("invalid value {} for {}", word, name))?)
+        });
+    }
+    Ok(Record::new(rec_type, data_type, data))
+}
+
+/// Removes the quotes and escapes added by
+/// [quote](../json/fn.quote.html).
+fn unquote(s: &str) -> Result<String, String> {
+    if s.len() < 2 || !s.starts_with('"') || !s.ends_with('"') {
+        return Err(format!("invalid string {}", s));
+    }
+    let mut res = String::new();
+    let mut chars = s[1..s.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        res.push(match chars.next() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('/') => '/',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('u') => {
+                let code: String = chars.by_ref().take(4).collect();
+                u32::from_str_radix(&code, 16).ok()
+                    .filter(|_| code.len() == 4)
+                    .and_then(std::char::from_u32)
+                    .ok_or_else(|| format!("invalid escape \\u{} in {}",
+                        code, s))?
+            },
+            _ => return Err(format!("invalid escape in string {}", s))
+        });
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use {Element, ElementParameter, ElementType, Error, Library};
+    use testing::{element, library};
+
+    fn text(lib: &Library) -> String {
+        let mut buf = Vec::new();
+        lib.write_text(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let lib = library(vec![("TOP", vec![element(ElementType::Text, vec![
+            ElementParameter::Layer(1), ElementParameter::TextType(0),
+            ElementParameter::XY(vec![(-5, 7)]),
+            ElementParameter::String(String::from(
+                "a \"b\" \\ c\nd\r\te\u{1}"))])])]);
+        let s = text(&lib);
+        assert!(s.contains("\n  TEXT\n    LAYER 1\n"));
+        assert!(s.contains("\n  ENDEL\nENDSTR\n"));
+        // control characters are escaped and do not break the lines
+        assert!(s.contains(
+            "\n    STRING \"a \\\"b\\\" \\\\ c\\nd\\u000d\\u0009e\\u0001\"\n"));
+        let read = Library::read_text(&mut s.as_bytes()).unwrap();
+        assert_eq!(text(&read), s);
+        let s = "HEADER 600\nLIBNAME \"\\t\\u00e9\\/\"\nUNITS 0.001 1e-9\n\
+            ENDLIB";
+        let read = Library::read_text(&mut s.as_bytes()).unwrap();
+        assert_eq!(read.name, "\t\u{e9}/");
+        for name in &["\"\\x\"", "\"\\u00\"", "\"\\ud800\""] {
+            let s = format!("HEADER 600\nLIBNAME {}\nENDLIB\n", name);
+            assert!(Library::read_text(&mut s.as_bytes()).is_err());
+        }
+    }
+
+    #[test]
+    fn unknown_element_type() {
+        let mut lib = library(vec![("TOP", vec![element(ElementType::Text,
+            vec![ElementParameter::XY(vec![(0, 0)])])]), ("B", vec![])]);
+        lib.structures[1].elements.push(Element::new());
+        // elements without type would start with a HEADER record
+        let mut buf = Vec::new();
+        assert!(matches!(lib.write_text(&mut buf),
+            Err(Error::InvalidValue(ref m))
+            if m == "unknown type of element 0 of structure B"));
+        assert!(lib.to_writer(&mut buf).is_err());
+        assert!(buf.is_empty());
+    }
+}
@@ -0,0 +1,256 @@
+//! Contains functions for changing the database unit of a library and for
+//! snapping coordinates to a grid.
+
+use {Element, ElementParameter, ElementType, Error, Library};
+
+/// Rounding modes used when coordinates are converted.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Rounding {
+    /// Round to the nearest integer, halfway cases away from zero.
+    Nearest,
+    /// Round towards negative infinity.
+    Down,
+    /// Round towards positive infinity.
+    Up,
+    /// Round towards zero.
+    TowardZero
+}
+
+/// A vertex not lying on the grid.
+#[derive(Debug,Clone,PartialEq)]
+pub struct OffGrid {
+    /// Name of the structure containing the vertex.
+    pub structure: String,
+    /// Index of the element in the structure.
+    pub element: usize,
+    /// The coordinates of the vertex.
+    pub point: (i32,i32)
+}
+
+impl Rounding {
+    /// Rounds `x` to an integer.
+    pub fn apply(&self, x: f64) -> f64 {
+        match *self {
+            Rounding::Nearest => x.round(),
+            Rounding::Down => x.floor(),
+            Rounding::Up => x.ceil(),
+            Rounding::TowardZero => x.trunc()
+        }
+    }
+}
+
+impl Library {
+    /// Converts the library to a new database unit.
+    ///
+    /// `units_m` is the size of the new database unit in metres. All
+    /// coordinates, widths and extensions are scaled and rounded to the
+    /// nearest integer. The size of the user unit is not changed. See
+    /// [rescale_with](#method.rescale_with) for details.
+    pub fn rescale(&mut self, units_m: f64) -> Result<(), Error> {
+        self.rescale_with(units_m, Rounding::Nearest)
+    }
+
+    /// Converts the library to a new database unit using `rounding`.
+    ///
+    /// An error is returned if the current or the new database unit is not a
+    /// positive number or if a scaled value does not fit into 32 bits. In
+    /// case of an error the library is not changed.
+    pub fn rescale_with(&mut self, units_m: f64, rounding: Rounding)
+            -> Result<(), Error> {
+        if !(units_m.is_finite() && units_m > 0.) {
+            return Err(Error::InvalidValue(
+                format!("invalid database unit {}", units_m)));
+        }
+        if !(self.units_m.is_finite() && self.units_m > 0.) {
+            return Err(Error::InvalidValue(
+                format!("invalid database unit {} of library", self.units_m)));
+        }
+        let factor = self.units_m/units_m;
+        let mut structures = self.structures.clone();
+        for stru in &mut structures {
+            let name = &stru.name;
+            for (i, elem) in stru.elements.iter_mut().enumerate() {
+                *elem = elem.scaled(factor, rounding).map_err(|e| match e {
+                    Error::Overflow(s) => Error::Overflow(format!(
+                        "{} in element {} of structure {}", s, i, name)),
+                    e => e
+                })?;
+            }
+        }
+        self.structures = structures;
+        self.units_user *= units_m/self.units_m;
+        self.units_m = units_m;
+        Ok(())
+    }
+
+    /// Returns all vertices which are not a multiple of `grid`.
+    ///
+    /// The grid is given in database units.
+    pub fn off_grid(&self, grid: i32) -> Vec<OffGrid> {
+        let mut res = Vec::new();
+        if grid <= 1 {
+            return res;
+        }
+        for stru in &self.structures {
+            for (i, elem) in stru.elements.iter().enumerate() {
+                for &p in elem.xy().unwrap_or(&[]) {
+                    if p.0 % grid != 0 || p.1 % grid != 0 {
+                        res.push(OffGrid{structure: stru.name.clone(),
+                            element: i, point: p});
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    /// Moves all vertices to the nearest multiple of `grid`.
+    ///
+    /// The grid is given in database units. The vertices which were not on
+    /// the grid before are returned. Points which would leave the range of 32
+    /// bit integers are moved towards zero instead.
+    pub fn snap_to_grid(&mut self, grid: i32) -> Vec<OffGrid> {
+        let res = self.off_grid(grid);
+        if res.is_empty() {
+            return res;
+        }
+        let snap = |x: i32| {
+            let g = grid as i64;
+            let s = ((x as i64 + g/2).div_euclid(g))*g;
+            if s > i32::MAX as i64 {
+                s - g
+            } else if s < i32::MIN as i64 {
+                s + g
+            } else {
+                s
+            }
+        };
+        for stru in &mut self.structures {
+            for elem in &mut stru.elements {
+                for param in &mut elem.parameters {
+                    if let ElementParameter::XY(ref mut x) = *param {
+                        for p in x.iter_mut() {
+                            *p = (snap(p.0) as i32, snap(p.1) as i32);
+                        }
+                    }
+                }
+            }
+        }
+        res
+    }
+}
+
+impl Element {
+    /// Returns a copy with all coordinates, widths and extensions scaled.
+    ///
+    /// The values are multiplied by `factor` and rounded using `rounding`.
+    /// Widths are rounded by magnitude, so negative absolute widths round
+    /// like positive ones. The column and row pitch of an array reference is
+    /// rounded instead of its displacement points, so all instances stay on
+    /// a regular lattice. An error is returned if a value does not fit into
+    /// 32 bits.
+    pub fn scaled(&self, factor: f64, rounding: Rounding)
+            -> Result<Element, Error> {
+        let fit = |x: f64, v: f64| {
+            if v >= i32::MIN as f64 && v <= i32::MAX as f64 {
+                Ok(v as i32)
+            } else {
+                Err(Error::Overflow(format!("value {} scaled by {}", x,
+                    factor)))
+            }
+        };
+        let s = |x: i32| fit(x as f64, rounding.apply(x as f64*factor));
+        let magnitude = |x: i32| {
+            let v = rounding.apply((x as f64*factor).abs());
+            fit(x as f64, if x < 0 { -v } else { v })
+        };
+        let (cols, rows) = self.array_size();
+        let is_array = matches!(self.element_type, ElementType::ArrayRef) &&
+            cols > 0 && rows > 0;
+        let mut res = self.clone();
+        for param in &mut res.parameters {
+            match *param {
+                ElementParameter::XY(ref mut x) if is_array && x.len() == 3 => {
+                    let origin = (s(x[0].0)?, s(x[0].1)?);
+                    let displaced = |p: (i32,i32), n: i32|
+                            -> Result<(i32,i32), Error> {
+                        // the pitch is rounded, the displacement spans all
+                        // columns or rows
+                        let pitch = |a: i32, b: i32| rounding.apply(
+                            (b as f64 - a as f64)/n as f64*factor);
+                        let px = pitch(x[0].0, p.0);
+                        let py = pitch(x[0].1, p.1);
+                        Ok((fit(p.0 as f64, origin.0 as f64 + n as f64*px)?,
+                            fit(p.1 as f64, origin.1 as f64 + n as f64*py)?))
+                    };
+                    let col = displaced(x[1], cols)?;
+                    let row = displaced(x[2], rows)?;
+                    *x = vec![origin, col, row];
+                },
+                ElementParameter::XY(ref mut x) => for p in x.iter_mut() {
+                    *p = (s(p.0)?, s(p.1)?);
+                },
+                ElementParameter::Width(ref mut x) => *x = magnitude(*x)?,
+                ElementParameter::BeginExt(ref mut x) => *x = s(*x)?,
+                _ => {}
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::{boundary, element, library};
+
+    #[test]
+    fn array_pitch() {
+        let aref = element(ElementType::ArrayRef, vec![
+            ElementParameter::StructureName(String::from("A")),
+            ElementParameter::ColRow(vec![3, 2]),
+            ElementParameter::XY(vec![(10, 10), (40, 10), (10, 50)])]);
+        let scaled = aref.scaled(0.15, Rounding::Nearest).unwrap();
+        // pitch 1.5 becomes 2 for all three columns, pitch 3 stays 3
+        assert_eq!(scaled.xy().unwrap(), &[(2, 2), (8, 2), (2, 8)]);
+        assert!(aref.scaled(1e8, Rounding::Nearest).is_err());
+    }
+
+    #[test]
+    fn widths() {
+        let path = element(ElementType::Path, vec![
+            ElementParameter::Layer(1), ElementParameter::Width(-15),
+            ElementParameter::BeginExt(-15),
+            ElementParameter::XY(vec![(-15, 15), (25, 15)])]);
+        let scaled = path.scaled(0.1, Rounding::Down).unwrap();
+        assert_eq!(scaled.xy().unwrap(), &[(-2, 1), (2, 1)]);
+        let params: Vec<i32> = scaled.parameters.iter().filter_map(|p|
+            match *p {
+                ElementParameter::Width(x) | ElementParameter::BeginExt(x) =>
+                    Some(x),
+                _ => None
+            }).collect();
+        assert_eq!(params, vec![-1, -2]);
+    }
+
+    #[test]
+    fn rescale_and_grid() {
+        let mut lib = library(vec![("TOP", vec![boundary(1,
+            vec![(0, 0), (7, 0), (7, -12), (0, 0)])])]);
+        let off = lib.off_grid(5);
+        assert_eq!(off.iter().map(|o| o.point).collect::<Vec<_>>(),
+            vec![(7, 0), (7, -12)]);
+        let mut snapped = lib.clone();
+        assert_eq!(snapped.snap_to_grid(5).len(), 2);
+        assert!(snapped.off_grid(5).is_empty());
+        assert_eq!(snapped.structures[0].elements[0].xy().unwrap(),
+            &[(0, 0), (5, 0), (5, -10), (0, 0)]);
+
+        lib.rescale(1e-8).unwrap();
+        assert_eq!(lib.units_m, 1e-8);
+        assert!((lib.units_user - 1e-2).abs() < 1e-15);
+        assert_eq!(lib.structures[0].elements[0].xy().unwrap(),
+            &[(0, 0), (1, 0), (1, -1), (0, 0)]);
+        assert!(lib.rescale(0.).is_err());
+    }
+}
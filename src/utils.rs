@@ -1,4 +1,4 @@
-/// Contains some helper functions used in the gds crate.
+//! Contains some helper functions used in the gds crate.
 
 extern crate byteorder;
 
@@ -8,37 +8,21 @@ use byteorder::{ByteOrder, BigEndian};
 ///
 /// The used format is not conform to IEEE floats as it uses 16 as base and
 /// excess 64 notation for the exponent. The first byte is used for the
-/// exponent (bits 2-8) and the sign (bit 1).
+/// exponent (bits 2-8) and the sign (bit 1). The result is rounded to the
+/// nearest float, values outside of the range of `f32` become infinite or
+/// zero.
 pub fn bytes_to_gds_real32(bytes: &[u8]) -> f32 {
-    let exp: i8 = ((0b01111111 & bytes[0]) as i8) - 64 - 6;
-    let mut man_arr = [0;4];
-    man_arr[1..].copy_from_slice(&bytes[1..]);
-    let man_arr = man_arr;
-    let man: f32 = BigEndian::read_u32(&man_arr) as f32;
-    let base: f32 = 16.;
-    if (0b10000000 & bytes[0]) == 0{
-        man*base.powi(exp as i32)
-    } else {
-        -man*base.powi(exp as i32)
-    }
+    decode_real(&bytes[..4]) as f32
 }
 
 /// Convert a slice of bytes into a 64 bit real.
 ///
 /// The used format is not conform to IEEE floats as it uses 16 as base and
 /// excess 64 notation for the exponent. The first byte is used for the
-/// exponent (bits 2-8) and the sign (bit 1).
+/// exponent (bits 2-8) and the sign (bit 1). The 56 bit mantissa is rounded
+/// to the nearest float, ties to even. Unnormalised mantissas are accepted.
 pub fn bytes_to_gds_real(bytes: &[u8]) -> f64 {
-    let exp: i8 = ((0b01111111 & bytes[0]) as i8) - 64 - 14;
-    let mut man_arr = [0;8];
-    man_arr[1..].copy_from_slice(&bytes[1..]);
-    let man: f64 = BigEndian::read_u64(&man_arr) as f64;
-    let base: f64 = 16.;
-    if (0b10000000 & bytes[0]) == 0{
-        man*base.powi(exp as i32)
-    } else {
-        -man*base.powi(exp as i32)
-    }
+    decode_real(&bytes[..8])
 }
 
 /// Converts a 64 bit real into a array of bytes.
@@ -46,31 +30,15 @@ pub fn bytes_to_gds_real(bytes: &[u8]) -> f64 {
 /// The used format is not conform to IEEE floats as it uses 16 as base and
 /// excess 64 notation for the exponent. The first byte is used for the
 /// exponent (bits 2-8) and the sign (bit 1).
+///
+/// Every float between about 5.4e-79 and 7.2e75 is converted exactly and
+/// converting the bytes back results in the same float. Smaller values are
+/// written with an unnormalised mantissa and rounded to the nearest
+/// representable value. Larger and infinite values are limited to the largest
+/// representable value, NaN is written as zero.
 pub fn gds_real_to_bytes(r: f64) -> [u8;8] {
-    let mut exp: u8 = 64;
-    let mut man: f64 = r.abs();
-    let base: f64 = 16.;
-    if man != 0. {
-        while man > 1. {
-            man /= 16.;
-            exp += 1;
-        }
-        while man < 1./16. {
-            man *= 16.;
-            exp -= 1;
-        }
-    }
-    let man: u64 = (man*base.powi(14)) as u64;
-    let mut man_arr = [0;8];
-    BigEndian::write_u64(&mut man_arr,man);
-    if r < 0. {
-        exp |= 0b10000000;
-    } else {
-        exp &= 0b01111111;
-    }
     let mut out_arr = [0;8];
-    out_arr[0] = exp;
-    out_arr[1..].copy_from_slice(&man_arr[1..8]);
+    encode_real(r, &mut out_arr);
     out_arr
 }
 
@@ -78,33 +46,108 @@ pub fn gds_real_to_bytes(r: f64) -> [u8;8] {
 ///
 /// The used format is not conform to IEEE floats as it uses 16 as base and
 /// excess 64 notation for the exponent. The first byte is used for the
-/// exponent (bits 2-8) and the sign (bit 1).
+/// exponent (bits 2-8) and the sign (bit 1). The mantissa is rounded to the
+/// nearest value, ties to even. NaN is written as zero.
 pub fn gds_real_32_to_bytes(r: f32) -> [u8;4] {
-    let mut exp: u8 = 64;
-    let mut man: f32 = r.abs();
-    let base: f32 = 16.;
-    if man != 0. {
-        while man > 1. {
-            man /= 16.;
-            exp += 1;
-        }
-        while man < 1./16. {
-            man *= 16.;
-            exp -= 1;
-        }
+    let mut out_arr = [0;4];
+    encode_real(r as f64, &mut out_arr);
+    out_arr
+}
+
+/// Returns the value of a real in GDS format with any mantissa length up to
+/// seven bytes.
+///
+/// The result is exact if the mantissa fits into 53 bits.
+fn decode_real(bytes: &[u8]) -> f64 {
+    let bits = 8*(bytes.len() as i32 - 1);
+    let man = bytes[1..].iter().fold(0u64, |m, &b| m << 8 | b as u64);
+    let exp = 4*((bytes[0] & 0x7f) as i32 - 64) - bits;
+    // the integer conversion rounds, the scaling by a power of two is exact
+    let res = man as f64*f64::from_bits(((exp + 1023) as u64) << 52);
+    if bytes[0] & 0x80 == 0 {
+        res
+    } else {
+        -res
     }
-    let man: u32 = (man*base.powi(6)) as u32;
-    let mut man_arr = [0;4];
-    BigEndian::write_u32(&mut man_arr,man);
+}
+
+/// Writes `r` as real in GDS format using all bytes of `out` with the first
+/// one for the sign and the exponent.
+fn encode_real(r: f64, out: &mut [u8]) {
+    for b in out.iter_mut() {
+        *b = 0;
+    }
+    let bits = 8*(out.len() as i32 - 1);
+    let (exp, man) = if r.is_nan() {
+        (0, 0)
+    } else if r.is_infinite() {
+        (127, (1 << bits) - 1)
+    } else {
+        encode_abs(r.abs(), bits)
+    };
+    if man == 0 {
+        return;
+    }
+    out[0] = exp;
     if r < 0. {
-        exp |= 0b10000000;
+        out[0] |= 0x80;
+    }
+    for (i, b) in out[1..].iter_mut().rev().enumerate() {
+        *b = (man >> (8*i)) as u8;
+    }
+}
+
+/// Returns the biased exponent and the mantissa with `bits` bits of a
+/// positive finite float.
+fn encode_abs(x: f64, bits: i32) -> (u8, u64) {
+    // x = m*2^e
+    let raw = x.to_bits();
+    let biased = ((raw >> 52) & 0x7ff) as i32;
+    let frac = raw & ((1 << 52) - 1);
+    let (m, e) = if biased == 0 {
+        (frac, -1074)
     } else {
-        exp &= 0b01111111;
+        (frac | 1 << 52, biased - 1075)
+    };
+    if m == 0 {
+        return (0, 0);
+    }
+    // x lies in [2^p, 2^(p+1)) and therefore in [16^(q-1), 16^q)
+    let p = e + 63 - m.leading_zeros() as i32;
+    let q = (p.div_euclid(4) + 1).max(-64);
+    if q > 63 {
+        return (127, (1 << bits) - 1);
+    }
+    // x = man*2^(4*q - bits)
+    let shift = e - (4*q - bits);
+    let man = if shift >= 0 {
+        m << shift
+    } else {
+        round_shift(m, -shift)
+    };
+    if man >> bits == 0 {
+        ((q + 64) as u8, man)
+    } else if q < 63 {
+        // rounding resulted in an additional hexadecimal digit
+        ((q + 65) as u8, man >> 4)
+    } else {
+        (127, (1 << bits) - 1)
+    }
+}
+
+/// Shifts `m` to the right by `s` bits rounding to nearest, ties to even.
+fn round_shift(m: u64, s: i32) -> u64 {
+    if s > 63 {
+        return 0;
+    }
+    let res = m >> s;
+    let rem = m & ((1 << s) - 1);
+    let half = 1 << (s - 1);
+    if rem > half || (rem == half && res & 1 == 1) {
+        res + 1
+    } else {
+        res
     }
-    let mut out_arr = [0;4];
-    out_arr[0] = exp;
-    out_arr[1..].copy_from_slice(&man_arr[1..4]);
-    out_arr
 }
 
 /// Converts a 16 bit signed integer into a vector of bytes.
@@ -134,3 +177,110 @@ pub fn u32_to_vec(i: u32) -> Vec<u8> {
     BigEndian::write_u32(&mut buf,i);
     buf.to_vec()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns pseudo random numbers of a xorshift generator.
+    fn random(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    fn one() {
+        assert_eq!(gds_real_to_bytes(1.), [0x41, 0x10, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(gds_real_to_bytes(-1.), [0xc1, 0x10, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(gds_real_32_to_bytes(1.), [0x41, 0x10, 0, 0]);
+        assert_eq!(bytes_to_gds_real(&[0x41, 0x10, 0, 0, 0, 0, 0, 0]), 1.);
+        // unnormalised mantissa
+        assert_eq!(bytes_to_gds_real(&[0x42, 0x01, 0, 0, 0, 0, 0, 0]), 1.);
+        assert_eq!(gds_real_to_bytes(1e-3),
+            [0x3e, 0x41, 0x89, 0x37, 0x4b, 0xc6, 0xa7, 0xf0]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut state = 0x2545f4914f6cdd1d;
+        for _ in 0..10000 {
+            // normalised bytes with a mantissa of at most 53 bits
+            let mut bytes = [0; 8];
+            BigEndian::write_u64(&mut bytes, random(&mut state) & !7);
+            bytes[1] = bytes[1].max(0x10);
+            let x = bytes_to_gds_real(&bytes);
+            assert_eq!(gds_real_to_bytes(x), bytes, "{}", x);
+
+            let x = f64::from_bits(random(&mut state));
+            if x.is_finite() && x.abs() > 5.4e-79 && x.abs() < 7.2e75 {
+                assert_eq!(bytes_to_gds_real(&gds_real_to_bytes(x)), x);
+            }
+        }
+    }
+
+    #[test]
+    fn ties_to_even() {
+        // the mantissa of 8 has 56 bits in GDS and 53 bits in a float
+        let decode = |last| bytes_to_gds_real(&[0x41, 0x80, 0, 0, 0, 0, 0,
+            last]);
+        assert_eq!(decode(0x04), 8.);
+        assert_eq!(decode(0x05), 8. + 2f64.powi(-49));
+        assert_eq!(decode(0x0c), 8. + 2f64.powi(-48));
+        // 24 bit mantissas of 32 bit reals
+        let unit = 2f64.powi(-20);
+        let encode = |x: f64| gds_real_32_to_bytes(x as f32);
+        assert_eq!(encode(1. + unit/2.), [0x41, 0x10, 0, 0]);
+        assert_eq!(encode(1. + 3.*unit/2.), [0x41, 0x10, 0, 2]);
+        assert_eq!(encode(1. + 3.*unit/4.), [0x41, 0x10, 0, 1]);
+        assert_eq!(bytes_to_gds_real32(&[0x41, 0x10, 0, 1]),
+            (1. + unit) as f32);
+    }
+
+    #[test]
+    fn underflow() {
+        let smallest = 16f64.powi(-64)/16.;
+        assert_eq!(gds_real_to_bytes(smallest),
+            [0x00, 0x10, 0, 0, 0, 0, 0, 0]);
+        // smaller values are unnormalised
+        assert_eq!(gds_real_to_bytes(smallest/16.),
+            [0x00, 0x01, 0, 0, 0, 0, 0, 0]);
+        let tiny = smallest*2f64.powi(-52);
+        assert_eq!(gds_real_to_bytes(tiny), [0x00, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(bytes_to_gds_real(&gds_real_to_bytes(tiny)), tiny);
+        assert_eq!(gds_real_to_bytes(tiny/2.), [0; 8]);
+        assert_eq!(gds_real_to_bytes(tiny/3.), [0; 8]);
+        assert_eq!(gds_real_to_bytes(1e-300), [0; 8]);
+        assert_eq!(gds_real_to_bytes(-1e-300), [0; 8]);
+        assert_eq!(bytes_to_gds_real32(&[0x00, 0x10, 0, 0]), 0.);
+    }
+
+    #[test]
+    fn overflow() {
+        let largest = [0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        let max = bytes_to_gds_real(&largest);
+        assert!(max > 7.2e75 && max < 7.3e75);
+        assert_eq!(gds_real_to_bytes(max), largest);
+        assert_eq!(gds_real_to_bytes(1e80), largest);
+        assert_eq!(gds_real_to_bytes(f64::INFINITY), largest);
+        let mut smallest = largest;
+        smallest[0] = 0xff;
+        assert_eq!(gds_real_to_bytes(f64::NEG_INFINITY), smallest);
+        assert_eq!(gds_real_to_bytes(f64::NAN), [0; 8]);
+        assert_eq!(gds_real_32_to_bytes(f32::NAN), [0; 4]);
+        assert_eq!(gds_real_32_to_bytes(f32::MAX),
+            [0x60, 0xff, 0xff, 0xff]);
+        // values beyond the range of f32 become infinite
+        assert_eq!(bytes_to_gds_real32(&[0x7f, 0x10, 0, 0]), f32::INFINITY);
+    }
+
+    #[test]
+    fn negative_zero() {
+        assert_eq!(gds_real_to_bytes(-0.), [0; 8]);
+        assert_eq!(gds_real_32_to_bytes(-0.), [0; 4]);
+        let x = bytes_to_gds_real(&[0x80, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(x, 0.);
+        assert!(x.is_sign_negative());
+    }
+}
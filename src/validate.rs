@@ -0,0 +1,310 @@
+//! Contains checks of libraries against the limits of the GDS format.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use {Date, Element, ElementParameter, ElementType, Error, Library};
+use constants::MAX_POINTS;
+use hierarchy::is_valid_name;
+
+/// Maximum length of the string of a text element.
+pub const MAX_STRING_LENGTH: usize = 512;
+
+/// Severity of a diagnostic.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord)]
+pub enum Severity {
+    /// The library can be written but might not be accepted by other tools.
+    Warning,
+    /// The library violates the GDS format.
+    Error
+}
+
+/// A problem found by [validate](../struct.Library.html#method.validate).
+#[derive(Debug,Clone,PartialEq)]
+pub struct Diagnostic {
+    /// Severity of the problem.
+    pub severity: Severity,
+    /// Name of the structure, `None` for problems of the library itself.
+    pub structure: Option<String>,
+    /// Index of the element in the structure, `None` for problems of the
+    /// structure itself.
+    pub element: Option<usize>,
+    /// Description of the problem.
+    pub message: String
+}
+
+impl Library {
+    /// Checks the library for violations of the GDS format.
+    ///
+    /// The units, the dates, the library and structure names including those
+    /// of references, duplicate structures, undefined references, the
+    /// parameters required by every element type, the number of points, the
+    /// closure of boundaries and the strings of texts are checked. An empty
+    /// vector is returned if no problems were found.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut res = Vec::new();
+        let lib = |severity, message: String|
+            Diagnostic{severity, structure: None, element: None, message};
+        let valid = |x: f64| x.is_finite() && x > 0.;
+        if !valid(self.units_m) || !valid(self.units_user) {
+            res.push(lib(Severity::Error, format!("invalid units {} {}",
+                self.units_user, self.units_m)));
+        }
+
+        for m in check_dates(&self.date_mod, &self.date_acc) {
+            res.push(lib(Severity::Warning, m));
+        }
+        if let Some((severity, m)) = check_name(&self.name) {
+            res.push(lib(severity, format!("library name {}", m)));
+        }
+
+        let names: HashSet<&str> = self.structures.iter()
+            .map(|s| s.name.as_str()).collect();
+        let mut count: HashMap<&str, usize> = HashMap::new();
+        for stru in &self.structures {
+            *count.entry(stru.name.as_str()).or_insert(0) += 1;
+        }
+        for stru in &self.structures {
+            let structure = |severity, message: String| Diagnostic{severity,
+                structure: Some(stru.name.clone()), element: None, message};
+            if let Some((severity, m)) = check_name(&stru.name) {
+                res.push(structure(severity, format!("name {}", m)));
+            }
+            for m in check_dates(&stru.date_mod, &stru.date_acc) {
+                res.push(structure(Severity::Warning, m));
+            }
+            if count[stru.name.as_str()] > 1 {
+                res.push(structure(Severity::Error,
+                    String::from("duplicate structure name")));
+            }
+            for (i, elem) in stru.elements.iter().enumerate() {
+                for (severity, message) in check_element(elem, &names) {
+                    res.push(Diagnostic{severity,
+                        structure: Some(stru.name.clone()), element: Some(i),
+                        message});
+                }
+            }
+        }
+        res
+    }
+}
+
+/// Returns the problem of a library or structure name.
+///
+/// Names have between 1 and 32 characters, other characters than those
+/// accepted by [is_valid_name](../hierarchy/fn.is_valid_name.html) are only
+/// a warning.
+fn check_name(name: &str) -> Option<(Severity, String)> {
+    if name.is_empty() || name.len() > 32 {
+        Some((Severity::Error, format!("has {} characters", name.len())))
+    } else if !is_valid_name(name) {
+        Some((Severity::Warning, String::from("contains invalid characters")))
+    } else {
+        None
+    }
+}
+
+/// Returns the problems of the modification and access dates.
+///
+/// Dates consisting of zeros are accepted.
+fn check_dates(date_mod: &Date, date_acc: &Date) -> Vec<String> {
+    let mut res = Vec::new();
+    for &(date, what) in &[(date_mod, "modification"), (date_acc, "access")] {
+        if date.is_zero() {
+            continue;
+        }
+        if let Err(Error::InvalidValue(e)) = date.validate() {
+            res.push(format!("{} date: {}", what, e));
+        }
+    }
+    res
+}
+
+/// Returns the problems of a single element.
+fn check_element(elem: &Element, names: &HashSet<&str>)
+        -> Vec<(Severity, String)> {
+    let mut res = Vec::new();
+    let mut warnings = Vec::new();
+    let mut error = |m: String| res.push((Severity::Error, m));
+    let has = |f: &dyn Fn(&ElementParameter) -> bool|
+        elem.parameters.iter().any(f);
+    let layer = has(&|p| matches!(*p, ElementParameter::Layer(_)));
+    let datatype = has(&|p| matches!(*p, ElementParameter::Datatype(_)));
+    let texttype = has(&|p| matches!(*p, ElementParameter::TextType(_)));
+    let nodetype = has(&|p| matches!(*p, ElementParameter::Nodetype(_)));
+    let boxtype = has(&|p| matches!(*p, ElementParameter::Boxtype(_)));
+    let (min, max, required): (usize, usize, &[(bool, &str)]) =
+            match elem.element_type {
+        ElementType::None => {
+            error(String::from("unknown element type"));
+            return res;
+        },
+        ElementType::Boundary => (4, MAX_POINTS,
+            &[(layer, "LAYER"), (datatype, "DATATYPE")]),
+        ElementType::Path => (2, MAX_POINTS,
+            &[(layer, "LAYER"), (datatype, "DATATYPE")]),
+        ElementType::StructureRef => (1, 1, &[]),
+        ElementType::ArrayRef => (3, 3, &[]),
+        ElementType::Text => (1, 1,
+            &[(layer, "LAYER"), (texttype, "TEXTTYPE")]),
+        ElementType::Node => (1, 50,
+            &[(layer, "LAYER"), (nodetype, "NODETYPE")]),
+        ElementType::Box => (5, 5, &[(layer, "LAYER"), (boxtype, "BOXTYPE")])
+    };
+    for &(found, name) in required {
+        if !found {
+            error(format!("missing {}", name));
+        }
+    }
+    match elem.xy() {
+        None => error(String::from("missing XY")),
+        Some(xy) if xy.len() < min || xy.len() > max => error(if min == max {
+            format!("{} points instead of {}", xy.len(), min)
+        } else {
+            format!("{} points outside of {}..{}", xy.len(), min, max)
+        }),
+        Some(xy) => if let ElementType::Boundary | ElementType::Box =
+                elem.element_type {
+            if xy.first() != xy.last() {
+                error(String::from("polygon is not closed"));
+            }
+        }
+    }
+    if elem.is_reference() {
+        match elem.structure_name() {
+            None => error(String::from("missing SNAME")),
+            Some(n) => {
+                match check_name(n) {
+                    Some((Severity::Error, m)) =>
+                        error(format!("structure name {}", m)),
+                    Some((_, m)) =>
+                        warnings.push(format!("structure name {}", m)),
+                    None => {}
+                }
+                if !names.contains(n) {
+                    error(format!("reference to undefined structure {}", n));
+                }
+            }
+        }
+    }
+    let mut colrow = false;
+    for param in &elem.parameters {
+        match *param {
+            ElementParameter::ColRow(ref x) => {
+                colrow = true;
+                if x.len() != 2 || x.iter().any(|&v| v < 1) {
+                    error(format!("invalid COLROW {:?}", x));
+                }
+            },
+            ElementParameter::Magnification(x) if !(x.is_finite() && x > 0.) =>
+                error(format!("invalid magnification {}", x)),
+            ElementParameter::Angle(x) if !x.is_finite() =>
+                error(format!("invalid angle {}", x)),
+            ElementParameter::Pathtype(x) if ![0, 1, 2, 4].contains(&x) =>
+                error(format!("invalid path type {}", x)),
+            ElementParameter::String(ref x) => {
+                if x.len() > MAX_STRING_LENGTH {
+                    error(format!("string has {} characters", x.len()));
+                }
+                if !x.chars().all(|c| c == ' ' || c.is_ascii_graphic()) {
+                    warnings.push(String::from("string contains \
+                        non-printable or non-ASCII characters"));
+                }
+            },
+            _ => {}
+        }
+    }
+    if let ElementType::ArrayRef = elem.element_type {
+        if !colrow {
+            error(String::from("missing COLROW"));
+        }
+    }
+    if let ElementType::Text = elem.element_type {
+        if !has(&|p| matches!(*p, ElementParameter::String(_))) {
+            error(String::from("missing STRING"));
+        }
+    }
+    res.extend(warnings.into_iter().map(|m| (Severity::Warning, m)));
+    res
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error")
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.severity)?;
+        if let Some(ref s) = self.structure {
+            write!(f, "structure {}: ", s)?;
+        }
+        if let Some(i) = self.element {
+            write!(f, "element {}: ", i)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::{boundary, element, sref};
+
+    fn library(name: &str, elements: Vec<Element>) -> Library {
+        let mut lib = ::testing::library(vec![("TOP", elements)]);
+        lib.name = name.to_string();
+        lib
+    }
+
+    fn messages(lib: &Library) -> Vec<(Severity, String)> {
+        lib.validate().into_iter().map(|d| (d.severity, d.message)).collect()
+    }
+
+    #[test]
+    fn valid_library() {
+        let lib = library("LIB", vec![
+            boundary(1, vec![(0, 0), (1, 0), (1, 1), (0, 0)]),
+            element(ElementType::Text, vec![ElementParameter::Layer(1),
+                ElementParameter::TextType(0), ElementParameter::XY(
+                    vec![(0, 0)]), ElementParameter::String(
+                    String::from("odd"))])]);
+        assert_eq!(messages(&lib), vec![]);
+    }
+
+    #[test]
+    fn names() {
+        let lib = library("", vec![sref("TOP", (0, 0)), sref("A B", (0, 0)),
+            sref(&"X".repeat(33), (0, 0))]);
+        assert_eq!(messages(&lib), vec![
+            (Severity::Error, String::from("library name has 0 characters")),
+            (Severity::Error,
+                String::from("reference to undefined structure A B")),
+            (Severity::Warning, String::from(
+                "structure name contains invalid characters")),
+            (Severity::Error, String::from("structure name has 33 characters")),
+            (Severity::Error, format!("reference to undefined structure {}",
+                "X".repeat(33)))]);
+        let lib = library("MY LIB", vec![]);
+        assert_eq!(messages(&lib), vec![(Severity::Warning,
+            String::from("library name contains invalid characters"))]);
+    }
+
+    #[test]
+    fn strings() {
+        let text = |s: String| element(ElementType::Text, vec![
+            ElementParameter::Layer(1), ElementParameter::TextType(0),
+            ElementParameter::XY(vec![(0, 0)]), ElementParameter::String(s)]);
+        let lib = library("LIB", vec![text(String::from("a\tb")),
+            text("x".repeat(513))]);
+        let m = messages(&lib);
+        assert_eq!(m.len(), 2);
+        assert_eq!(m[0].0, Severity::Warning);
+        assert_eq!(m[1], (Severity::Error,
+            String::from("string has 513 characters")));
+    }
+}